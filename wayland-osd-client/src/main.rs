@@ -1,14 +1,62 @@
-use anyhow::Context;
-use clap::{Parser, Subcommand};
-use serde_json::json;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 
-const PIPE_PATH: &str = "/tmp/wayland-osd.pipe";
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand, ValueEnum};
+use wayland_osd_core::ipc::Instance;
+use wayland_osd_core::message::{
+    Markers, DISMISS_MESSAGE_TYPE, DND_MESSAGE_TYPE, PROFILE_MESSAGE_TYPE, RELOAD_MESSAGE_TYPE,
+};
+use wayland_osd_core::OsdMessage;
+use wayland_osd_core::{capture, demo, ipc};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Start the server first if it isn't running yet
+    #[arg(long, global = true)]
+    spawn: bool,
+    /// Group this message with others sharing the tag, defaults to the message type
+    #[arg(long, global = true)]
+    tag: Option<String>,
+    /// Update the OSD showing this id in place, a different id starts a new popup
+    #[arg(long, global = true)]
+    id: Option<String>,
+    /// Keep lower priority messages from replacing this one while it is shown
+    #[arg(long, global = true, allow_hyphen_values = true)]
+    priority: Option<i32>,
+    /// Icon theme name or absolute path to an SVG or PNG, replacing the built-in icon
+    #[arg(long, global = true)]
+    icon: Option<String>,
+    /// Draw a line across the bar at this value, may be given several times
+    #[arg(long, global = true, allow_hyphen_values = true)]
+    marker: Vec<i32>,
+    /// Extra CSS class of the window, for styling this message from the stylesheet
+    #[arg(long, global = true)]
+    style: Option<String>,
+    /// Draw the bar as this many blocks, 0 for a continuous bar
+    #[arg(long, global = true)]
+    segments: Option<u32>,
+    /// Count volume and mic values above this as overamplified, instead of the configured one
+    #[arg(long, global = true)]
+    normal_max: Option<i32>,
+    /// Show the message on this output, by connector name (e.g. DP-1)
+    #[arg(long, global = true)]
+    output: Option<String>,
+    /// How long to show the message, defaults to the server's configured timeout
+    #[arg(long, global = true)]
+    timeout_ms: Option<u64>,
+    /// Keep the message up until it is dismissed or replaced
+    #[arg(long, global = true)]
+    sticky: bool,
+    /// Send to the server running as this named instance
+    #[arg(long, global = true)]
+    instance: Option<String>,
+    /// Wait for the server to read the message, failing with the reason if it was rejected
+    #[arg(long, global = true)]
+    wait: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -33,6 +81,23 @@ enum Commands {
         /// Audio device name
         #[arg(long)]
         device: Option<String>,
+        /// Audio device class hint used to pick the icon (e.g. headphones, hdmi, bluetooth)
+        #[arg(long)]
+        device_class: Option<String>,
+    },
+    /// Control microphone-related OSD
+    Mic {
+        /// Current input volume level
+        volume: i32,
+        /// Maximum input volume level
+        #[arg(long, default_value = "100")]
+        max_volume: i32,
+        /// Show muted state
+        #[arg(long)]
+        mute: bool,
+        /// Microphone device name
+        #[arg(long)]
+        device: Option<String>,
     },
     /// Control brightness-related OSD
     Brightness {
@@ -46,92 +111,565 @@ enum Commands {
     Text {
         /// Message to display
         message: String,
+        /// Render the message as Pango markup
+        #[arg(long)]
+        markup: bool,
+    },
+    /// Show the battery charge
+    Battery {
+        /// Charge in percent
+        level: i32,
+        #[arg(long, value_enum, default_value_t = BatteryStatus::Discharging)]
+        status: BatteryStatus,
+        /// Show a red bar for longer
+        #[arg(long)]
+        critical: bool,
+    },
+    /// Show a Bluetooth device that connected or disconnected
+    Bluetooth {
+        /// Device name
+        device: String,
+        #[arg(value_enum)]
+        state: BluetoothState,
+        /// Battery level of the device in percent
+        #[arg(long)]
+        battery: Option<i32>,
     },
+    /// Show a changed network connection
+    Network {
+        #[arg(value_enum)]
+        connection: Connection,
+        /// Name of the Wi-Fi network
+        #[arg(long)]
+        ssid: Option<String>,
+        /// Wi-Fi signal strength in percent
+        #[arg(long)]
+        signal: Option<i32>,
+    },
+    /// Show the track a media player switched to
+    Media {
+        /// Track title
+        title: String,
+        #[arg(long)]
+        artist: Option<String>,
+        #[arg(long, value_enum, default_value_t = Playback::Playing)]
+        playback: Playback,
+        /// Cover image, an absolute path or a URI
+        #[arg(long)]
+        artwork: Option<String>,
+    },
+    /// Show the state of a lock key
+    Lock {
+        #[arg(value_enum)]
+        key: LockKey,
+        #[arg(value_enum)]
+        state: LockState,
+    },
+    /// Show the power profile switched to
+    PowerProfile {
+        #[arg(value_enum)]
+        profile: PowerProfile,
+    },
+    /// Show the keyboard layout switched to
+    KeyboardLayout {
+        /// Name of the layout, e.g. "English (US)"
+        layout: String,
+    },
+    /// Show how far a task like a backup or file copy got
+    Progress {
+        /// Work done so far
+        value: i32,
+        /// Work in total
+        #[arg(long, default_value = "100")]
+        max_value: i32,
+        /// What is being done, shown under the bar
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Count down, the server updates the bar and time left every second
+    Countdown {
+        /// Length of the countdown in seconds
+        seconds: u64,
+        /// What is counted down, shown under the bar
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Show any combination of icon (with --icon), bar and label
+    Custom {
+        /// Fill the bar to this value
+        #[arg(long, allow_hyphen_values = true)]
+        value: Option<i32>,
+        /// Value of a full bar
+        #[arg(long, default_value = "100")]
+        max_value: i32,
+        /// Label, shown below the bar if there is one
+        #[arg(long)]
+        text: Option<String>,
+        /// Extra CSS class of the bar, or of the label without a bar
+        #[arg(long)]
+        class: Option<String>,
+    },
+    /// Cycle through representative messages to try out positioning and themes
+    Demo {
+        /// Time each message stays up before the next one is sent
+        #[arg(long, default_value = "2000")]
+        interval_ms: u64,
+        /// Stop after showing every message once instead of repeating
+        #[arg(long)]
+        once: bool,
+    },
+    /// Re-send the messages of a capture recorded with `wayland-osd-server --dump`
+    Replay {
+        /// Capture file to read
+        file: PathBuf,
+        /// Playback speed, 2 replays twice as fast
+        #[arg(long, default_value = "1.0")]
+        speed: f64,
+    },
+    /// Switch the server to another config profile
+    Profile {
+        /// Name of the profile, `default` for the config without overrides
+        name: String,
+    },
+    /// Make the server re-read its config file and stylesheet
+    Reload,
+    /// Hide the OSD right away
+    Dismiss,
+    /// Suppress every OSD until do-not-disturb is turned off again
+    Dnd {
+        #[arg(value_enum, default_value_t = DndMode::Toggle)]
+        mode: DndMode,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DndMode {
+    On,
+    Off,
+    Toggle,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BatteryStatus {
+    Charging,
+    Discharging,
+    Full,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BluetoothState {
+    Connected,
+    Disconnected,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Connection {
+    Wireless,
+    Wired,
+    Disconnected,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Playback {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PowerProfile {
+    Performance,
+    Balanced,
+    PowerSaver,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LockKey {
+    Caps,
+    Num,
+    Scroll,
 }
 
-struct OsdClient;
+#[derive(Clone, Copy, ValueEnum)]
+enum LockState {
+    On,
+    Off,
+}
 
-impl OsdClient {
-    fn new() -> anyhow::Result<Self> {
-        Ok(Self)
+/// How long to wait for a freshly spawned server to start reading its pipe
+const SPAWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn spawn_server(instance: &Instance) -> anyhow::Result<()> {
+    let mut command = Command::new("wayland-osd-server");
+    if let Some(name) = instance.name() {
+        command.args(["--instance", name]);
     }
 
-    fn send_message(&self, message: &str) -> anyhow::Result<()> {
-        // Try to open pipe multiple times
-        let mut attempts = 0;
-        let max_attempts = 5;
-        let mut last_error = None;
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Detach from the keybind daemon's process group so the server outlives us
+        .process_group(0)
+        .spawn()
+        .context("Failed to start wayland-osd-server")?;
 
-        while attempts < max_attempts {
-            match OpenOptions::new().write(true).open(PIPE_PATH) {
-                Ok(mut file) => {
-                    // Create a single buffer with message and separator to ensure atomic write
-                    let mut buffer = message.as_bytes().to_vec();
-                    buffer.push(0);
-                    file.write_all(&buffer)
-                        .context("Failed to write message to OSD pipe")?;
-                    // Ensure the write is flushed
-                    file.flush()
-                        .context("Failed to flush message to OSD pipe")?;
-                    // Add a small delay to prevent overwhelming the server
-                    std::thread::sleep(std::time::Duration::from_millis(5));
-                    return Ok(());
-                }
-                Err(e) => {
-                    last_error = Some(e);
-                    attempts += 1;
-                    if attempts < max_attempts {
-                        std::thread::sleep(std::time::Duration::from_millis(50));
-                    }
-                }
-            }
+    if !ipc::wait_for_server(instance, SPAWN_TIMEOUT) {
+        bail!(
+            "wayland-osd-server did not start within {:?}",
+            SPAWN_TIMEOUT
+        );
+    }
+
+    Ok(())
+}
+
+fn send(instance: &Instance, message: &str, wait: bool) -> anyhow::Result<()> {
+    if !wait {
+        return ipc::send_message(instance, message);
+    }
+
+    match ipc::send_and_wait(instance, message)?.into_iter().next() {
+        Some(reply) => bail!("Server rejected the message: {}", reply),
+        None => Ok(()),
+    }
+}
+
+fn run_demo(instance: &Instance, interval: Duration, once: bool) -> anyhow::Result<()> {
+    loop {
+        for (name, message) in demo::samples() {
+            println!("{}", name);
+            ipc::send_message(instance, &message.to_json())?;
+            std::thread::sleep(interval);
         }
 
-        Err(last_error.unwrap().into())
+        if once {
+            return Ok(());
+        }
     }
 }
 
+fn replay(instance: &Instance, file: &Path, speed: f64) -> anyhow::Result<()> {
+    if speed <= 0.0 {
+        bail!("Speed must be positive");
+    }
+
+    let entries = capture::read_capture(file)?;
+    let started = Instant::now();
+
+    for entry in entries {
+        // Timed from the start rather than between messages, so delays don't add up
+        let due = entry.offset().div_f64(speed);
+        if let Some(wait) = due.checked_sub(started.elapsed()) {
+            std::thread::sleep(wait);
+        }
+        ipc::send_message(instance, &entry.message.to_json())?;
+    }
+
+    Ok(())
+}
+
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let client = OsdClient::new()?;
 
-    match cli.command {
+    let instance = Instance::new(cli.instance.clone())?;
+
+    if cli.spawn && !ipc::server_running(&instance) {
+        spawn_server(&instance)?;
+    }
+
+    match &cli.command {
         Commands::Json { message } => {
             // Validate JSON before sending
-            serde_json::from_str::<serde_json::Value>(&message).context("Invalid JSON message")?;
-            client.send_message(&message)?;
+            serde_json::from_str::<serde_json::Value>(message).context("Invalid JSON message")?;
+            return send(&instance, message, cli.wait);
         }
+        Commands::Replay { file, speed } => {
+            return replay(&instance, file, *speed);
+        }
+        Commands::Demo { interval_ms, once } => {
+            return run_demo(&instance, Duration::from_millis(*interval_ms), *once);
+        }
+        _ => {}
+    }
+
+    let wait = cli.wait;
+    match build_message(cli) {
+        Some(message) => send(&instance, &message.to_json(), wait),
+        None => Ok(()),
+    }
+}
+
+/// The message a command sends, `None` for commands that don't send a single message
+fn build_message(cli: Cli) -> Option<OsdMessage> {
+    let mut message = match cli.command {
+        Commands::Json { .. } | Commands::Replay { .. } | Commands::Demo { .. } => return None,
         Commands::Audio {
             volume,
             max_volume,
             mute,
             device,
-        } => {
-            let message = json!({
-                "type": "volume",
-                "value": volume,
-                "max_value": max_volume,
-                "muted": mute,
-                "device_name": device
-            });
-            client.send_message(&message.to_string())?;
-        }
-        Commands::Brightness { level, max_level } => {
-            let message = json!({
-                "type": "brightness",
-                "value": level,
-                "max_value": max_level
-            });
-            client.send_message(&message.to_string())?;
-        }
-        Commands::Text { message } => {
-            let message = json!({
-                "type": "text",
-                "text": message
-            });
-            client.send_message(&message.to_string())?;
-        }
+            device_class,
+        } => OsdMessage {
+            value: Some(volume),
+            max_value: Some(max_volume),
+            muted: Some(mute),
+            device_name: device,
+            device_class,
+            ..OsdMessage::new("volume")
+        },
+        Commands::Mic {
+            volume,
+            max_volume,
+            mute,
+            device,
+        } => OsdMessage {
+            value: Some(volume),
+            max_value: Some(max_volume),
+            muted: Some(mute),
+            device_name: device,
+            ..OsdMessage::new("mic")
+        },
+        Commands::Brightness { level, max_level } => OsdMessage {
+            value: Some(level),
+            max_value: Some(max_level),
+            ..OsdMessage::new("brightness")
+        },
+        Commands::Text { message, markup } => OsdMessage {
+            text: Some(message),
+            markup: markup.then_some(true),
+            ..OsdMessage::new("text")
+        },
+        Commands::Battery {
+            level,
+            status,
+            critical,
+        } => OsdMessage {
+            value: Some(level),
+            status: Some(
+                match status {
+                    BatteryStatus::Charging => "charging",
+                    BatteryStatus::Discharging => "discharging",
+                    BatteryStatus::Full => "full",
+                }
+                .to_string(),
+            ),
+            critical: Some(critical),
+            ..OsdMessage::new("battery")
+        },
+        Commands::Bluetooth {
+            device,
+            state,
+            battery,
+        } => OsdMessage {
+            device_name: Some(device),
+            connected: Some(matches!(state, BluetoothState::Connected)),
+            value: battery,
+            ..OsdMessage::new("bluetooth")
+        },
+        Commands::Network {
+            connection,
+            ssid,
+            signal,
+        } => OsdMessage {
+            connection: Some(
+                match connection {
+                    Connection::Wireless => "wireless",
+                    Connection::Wired => "wired",
+                    Connection::Disconnected => "disconnected",
+                }
+                .to_string(),
+            ),
+            ssid,
+            value: signal,
+            ..OsdMessage::new("network")
+        },
+        Commands::Media {
+            title,
+            artist,
+            playback,
+            artwork,
+        } => OsdMessage {
+            title: Some(title),
+            artist,
+            playback: Some(
+                match playback {
+                    Playback::Playing => "playing",
+                    Playback::Paused => "paused",
+                    Playback::Stopped => "stopped",
+                }
+                .to_string(),
+            ),
+            artwork,
+            ..OsdMessage::new("media")
+        },
+        Commands::Lock { key, state } => OsdMessage {
+            key: Some(
+                match key {
+                    LockKey::Caps => "caps",
+                    LockKey::Num => "num",
+                    LockKey::Scroll => "scroll",
+                }
+                .to_string(),
+            ),
+            enabled: Some(matches!(state, LockState::On)),
+            ..OsdMessage::new("lock")
+        },
+        Commands::PowerProfile { profile } => OsdMessage {
+            text: Some(
+                match profile {
+                    PowerProfile::Performance => "performance",
+                    PowerProfile::Balanced => "balanced",
+                    PowerProfile::PowerSaver => "power-saver",
+                }
+                .to_string(),
+            ),
+            ..OsdMessage::new("power-profile")
+        },
+        Commands::KeyboardLayout { layout } => OsdMessage {
+            text: Some(layout),
+            ..OsdMessage::new("keyboard-layout")
+        },
+        Commands::Progress {
+            value,
+            max_value,
+            label,
+        } => OsdMessage {
+            value: Some(value),
+            max_value: Some(max_value),
+            label,
+            ..OsdMessage::new("progress")
+        },
+        Commands::Countdown { seconds, label } => OsdMessage {
+            duration_ms: Some(seconds.saturating_mul(1000)),
+            label,
+            ..OsdMessage::new("countdown")
+        },
+        Commands::Custom {
+            value,
+            max_value,
+            text,
+            class,
+        } => OsdMessage {
+            value,
+            max_value: value.map(|_| max_value),
+            text,
+            css_class: class,
+            ..OsdMessage::new("custom")
+        },
+        Commands::Profile { name } => OsdMessage {
+            text: Some(name),
+            ..OsdMessage::new(PROFILE_MESSAGE_TYPE)
+        },
+        Commands::Reload => OsdMessage::new(RELOAD_MESSAGE_TYPE),
+        Commands::Dismiss => OsdMessage::new(DISMISS_MESSAGE_TYPE),
+        Commands::Dnd { mode } => OsdMessage {
+            text: match mode {
+                DndMode::On => Some("on".to_string()),
+                DndMode::Off => Some("off".to_string()),
+                DndMode::Toggle => None,
+            },
+            ..OsdMessage::new(DND_MESSAGE_TYPE)
+        },
+    };
+
+    message.tag = cli.tag;
+    message.id = cli.id;
+    message.icon = cli.icon;
+    message.priority = cli.priority;
+    message.normal_max = cli.normal_max;
+    message.segments = cli.segments;
+    message.style = cli.style;
+    if !cli.marker.is_empty() {
+        message.marker = Some(Markers::Many(cli.marker));
     }
+    message.timeout_ms = cli.timeout_ms;
+    message.sticky = cli.sticky.then_some(true);
+    message.output = cli.output;
+    Some(message)
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(args: &[&str]) -> OsdMessage {
+        let cli = Cli::try_parse_from(["wayland-osd-client"].iter().chain(args))
+            .expect("valid arguments");
+        build_message(cli).expect("command sends a message")
+    }
+
+    #[test]
+    fn audio() {
+        let msg = message(&["audio", "40", "--mute", "--device-class", "hdmi"]);
+        assert_eq!(msg.message_type, "volume");
+        assert_eq!(msg.value, Some(40));
+        assert_eq!(msg.max_value, Some(100));
+        assert_eq!(msg.muted, Some(true));
+        assert_eq!(msg.device_class.as_deref(), Some("hdmi"));
+    }
+
+    #[test]
+    fn global_options_apply_to_every_command() {
+        let msg = message(&[
+            "--tag",
+            "keys",
+            "--priority",
+            "-1",
+            "--marker",
+            "20",
+            "--marker",
+            "80",
+            "--sticky",
+            "brightness",
+            "30",
+        ]);
+        assert_eq!(msg.tag.as_deref(), Some("keys"));
+        assert_eq!(msg.priority, Some(-1));
+        assert_eq!(msg.marker, Some(Markers::Many(vec![20, 80])));
+        assert_eq!(msg.sticky, Some(true));
+        // Unset flags are left out of the message
+        assert_eq!(msg.timeout_ms, None);
+        assert_eq!(message(&["brightness", "30"]).sticky, None);
+    }
+
+    #[test]
+    fn value_enums_become_strings() {
+        let msg = message(&["battery", "5", "--status", "charging", "--critical"]);
+        assert_eq!(msg.status.as_deref(), Some("charging"));
+        assert_eq!(msg.critical, Some(true));
+
+        let msg = message(&["power-profile", "power-saver"]);
+        assert_eq!(msg.text.as_deref(), Some("power-saver"));
+    }
+
+    #[test]
+    fn custom_bar_only_with_value() {
+        let msg = message(&["custom", "--text", "Fan"]);
+        assert_eq!(msg.value, None);
+        assert_eq!(msg.max_value, None);
+
+        let msg = message(&["custom", "--value", "-5", "--max-value", "50"]);
+        assert_eq!(msg.value, Some(-5));
+        assert_eq!(msg.max_value, Some(50));
+    }
+
+    #[test]
+    fn control_messages() {
+        assert_eq!(message(&["dnd"]).text, None);
+        assert_eq!(message(&["dnd", "on"]).text.as_deref(), Some("on"));
+        assert_eq!(message(&["reload"]).message_type, RELOAD_MESSAGE_TYPE);
+    }
+
+    #[test]
+    fn countdown_saturates() {
+        let msg = message(&["countdown", &u64::MAX.to_string()]);
+        assert_eq!(msg.duration_ms, Some(u64::MAX));
+    }
+
+    #[test]
+    fn commands_without_a_single_message() {
+        let cli = Cli::try_parse_from(["wayland-osd-client", "json", "{}"]).unwrap();
+        assert!(build_message(cli).is_none());
+    }
 }
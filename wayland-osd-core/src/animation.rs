@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// How an animation progresses over its duration
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    /// Starts fast and slows down towards the target, so retargeting mid-way stays smooth
+    #[default]
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map linear progress in `0..=1` to eased progress
+    pub fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t.powi(3)
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// Animation settings shared by every animated part of the OSD
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct AnimationConfig {
+    /// Upper bound for animation updates per second, 0 follows the display's refresh rate
+    pub max_fps: u32,
+    /// How long the bar takes to move to a new value, 0 jumps immediately
+    pub progress_duration_ms: u64,
+    pub progress_easing: Easing,
+    /// How long the icon takes to fade over to a new one, 0 swaps immediately
+    pub icon_crossfade_ms: u64,
+    /// How long the OSD takes to appear, 0 shows it immediately
+    pub fade_in_ms: u64,
+    /// How long the OSD takes to disappear, 0 hides it immediately
+    pub fade_out_ms: u64,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            max_fps: 0,
+            progress_duration_ms: 100,
+            progress_easing: Easing::default(),
+            icon_crossfade_ms: 150,
+            fade_in_ms: 150,
+            fade_out_ms: 250,
+        }
+    }
+}
+
+impl AnimationConfig {
+    /// Minimum time between two animation updates, if capped
+    pub fn min_frame_interval(&self) -> Option<Duration> {
+        (self.max_fps > 0).then(|| Duration::from_secs(1) / self.max_fps)
+    }
+
+    pub fn progress_duration(&self) -> Duration {
+        Duration::from_millis(self.progress_duration_ms)
+    }
+
+    pub fn icon_crossfade_duration(&self) -> Duration {
+        Duration::from_millis(self.icon_crossfade_ms)
+    }
+
+    pub fn fade_in_duration(&self) -> Duration {
+        Duration::from_millis(self.fade_in_ms)
+    }
+
+    pub fn fade_out_duration(&self) -> Duration {
+        Duration::from_millis(self.fade_out_ms)
+    }
+}
+
+/// A transition between two values over a fixed duration, linear unless eased
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tween {
+    pub from: f64,
+    pub to: f64,
+    pub duration: Duration,
+    pub easing: Easing,
+}
+
+impl Tween {
+    pub fn new(from: f64, to: f64, duration: Duration) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            easing: Easing::Linear,
+        }
+    }
+
+    pub fn with_easing(self, easing: Easing) -> Self {
+        Self { easing, ..self }
+    }
+
+    pub fn value_at(&self, elapsed: Duration) -> f64 {
+        self.from + (self.to - self.from) * self.easing.apply(self.progress(elapsed))
+    }
+
+    pub fn is_finished(&self, elapsed: Duration) -> bool {
+        elapsed >= self.duration
+    }
+
+    fn progress(&self, elapsed: Duration) -> f64 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+
+        (elapsed.as_secs_f64() / self.duration.as_secs_f64()).clamp(0.0, 1.0)
+    }
+}
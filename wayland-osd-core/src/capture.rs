@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::message::OsdMessage;
+
+/// A received message and when it arrived, one per line of a capture file
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CaptureEntry {
+    /// Time since the capture started in milliseconds
+    pub offset_ms: u64,
+    pub message: OsdMessage,
+}
+
+impl CaptureEntry {
+    pub fn offset(&self) -> Duration {
+        Duration::from_millis(self.offset_ms)
+    }
+}
+
+/// Records messages as JSON lines, timed relative to when it was created
+pub struct CaptureWriter {
+    file: BufWriter<File>,
+    started: Instant,
+}
+
+impl CaptureWriter {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create capture file {}", path.display()))?;
+
+        Ok(Self {
+            file: BufWriter::new(file),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, message: &OsdMessage) -> anyhow::Result<()> {
+        let entry = CaptureEntry {
+            offset_ms: self.started.elapsed().as_millis() as u64,
+            message: message.clone(),
+        };
+
+        // Flushed right away, so the capture survives the server being killed
+        serde_json::to_writer(&mut self.file, &entry)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Read every entry of a capture file, in the order they were recorded
+pub fn read_capture(path: &Path) -> anyhow::Result<Vec<CaptureEntry>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open capture file {}", path.display()))?;
+
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(|(i, line)| {
+            let line = line?;
+            serde_json::from_str(&line)
+                .with_context(|| format!("Invalid entry on line {} of {}", i + 1, path.display()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("wayland-osd-{}-{}.jsonl", std::process::id(), name))
+    }
+
+    #[test]
+    fn reads_back_recorded_messages_in_order() {
+        let path = temp_path("capture");
+        let mut writer = CaptureWriter::create(&path).unwrap();
+        for value in [10, 20] {
+            writer
+                .record(&OsdMessage {
+                    value: Some(value),
+                    ..OsdMessage::new("volume")
+                })
+                .unwrap();
+        }
+        drop(writer);
+
+        let entries = read_capture(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let values: Vec<_> = entries.iter().map(|entry| entry.message.value).collect();
+        assert_eq!(values, [Some(10), Some(20)]);
+        assert!(entries[0].offset() <= entries[1].offset());
+    }
+
+    #[test]
+    fn skips_blank_lines_and_names_invalid_ones() {
+        let path = temp_path("invalid");
+        fs::write(
+            &path,
+            "{\"offset_ms\":5,\"message\":{\"type\":\"text\"}}\n\nnot json\n",
+        )
+        .unwrap();
+
+        let error = read_capture(&path).unwrap_err();
+        fs::write(
+            &path,
+            "\n{\"offset_ms\":5,\"message\":{\"type\":\"text\"}}\n",
+        )
+        .unwrap();
+        let entries = read_capture(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(error.to_string().contains("line 3"), "{}", error);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].offset(), Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,1131 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{debug, info};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::animation::AnimationConfig;
+use crate::hooks::HookConfig;
+use crate::ingest::OverflowPolicy;
+use crate::ipc::Instance;
+use crate::journal::JournalConfig;
+use crate::layout::Layout;
+use crate::state::DEFAULT_TIMEOUT;
+
+/// Icons that can be shown in place of the generic speaker for a volume OSD.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeviceIcon {
+    Speaker,
+    Headphones,
+    Headset,
+    Tv,
+    UsbDac,
+    Bluetooth,
+}
+
+/// Set of embedded icons used by the server
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum IconPack {
+    /// Rounded icons in the style of Adwaita
+    #[default]
+    Default,
+    /// Angular icons, needs the server's `icon-pack-sharp` feature
+    Sharp,
+}
+
+/// Maps a keyword found in the device class or name to an icon.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeviceIconRule {
+    /// Case-insensitive substring matched against `device_class` and `device_name`
+    #[serde(rename = "match", deserialize_with = "non_empty_keyword")]
+    pub keyword: String,
+    pub icon: DeviceIcon,
+}
+
+/// An empty keyword would be found in every device
+fn non_empty_keyword<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    let keyword = String::deserialize(deserializer)?;
+    if keyword.is_empty() {
+        return Err(serde::de::Error::custom(
+            "device icon keyword must not be empty",
+        ));
+    }
+    Ok(keyword)
+}
+
+/// Colors the bar fill while the value is within a range
+#[derive(Debug, Deserialize, Clone)]
+pub struct ColorThreshold {
+    /// Applies when the value is below this percentage
+    pub below: Option<f64>,
+    /// Applies when the value is above this percentage
+    pub above: Option<f64>,
+    /// Any CSS color
+    pub color: String,
+}
+
+impl ColorThreshold {
+    fn matches(&self, percent: f64) -> bool {
+        self.below.is_none_or(|below| percent < below)
+            && self.above.is_none_or(|above| percent > above)
+    }
+}
+
+/// A color of a bar gradient at a value
+#[derive(Debug, Deserialize, Clone)]
+pub struct GradientStop {
+    /// Percentage of the full scale
+    pub at: f64,
+    /// Only `#rrggbb` and `#rgb` colors are blended, others switch over at the next stop
+    pub color: String,
+}
+
+/// Bar color of a message type, written as `"#3465a4"`, a list of thresholds
+/// or a table with a `gradient`
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum BarColors {
+    /// Any CSS color, regardless of the value
+    Fixed(String),
+    Thresholds(Vec<ColorThreshold>),
+    /// Blends between the stops, which are in increasing order
+    Gradient {
+        gradient: Vec<GradientStop>,
+    },
+}
+
+impl BarColors {
+    fn color(&self, percent: f64) -> Option<String> {
+        match self {
+            BarColors::Fixed(color) => Some(color.clone()),
+            BarColors::Thresholds(thresholds) => thresholds
+                .iter()
+                .find(|threshold| threshold.matches(percent))
+                .map(|threshold| threshold.color.clone()),
+            BarColors::Gradient { gradient } => gradient_color(gradient, percent),
+        }
+    }
+}
+
+/// Blend the colors of the stops around `percent`, outside of them the nearest stop's color
+fn gradient_color(stops: &[GradientStop], percent: f64) -> Option<String> {
+    let (from, to) = match stops.iter().position(|stop| stop.at > percent) {
+        Some(0) => return stops.first().map(|stop| stop.color.clone()),
+        Some(next) => (&stops[next - 1], &stops[next]),
+        None => return stops.last().map(|stop| stop.color.clone()),
+    };
+
+    let (Some(start), Some(end)) = (hex_rgb(&from.color), hex_rgb(&to.color)) else {
+        return Some(from.color.clone());
+    };
+    let t = (percent - from.at) / (to.at - from.at);
+    let blend = |i: usize| (start[i] as f64 + (end[i] as f64 - start[i] as f64) * t).round() as u8;
+    Some(format!("#{:02x}{:02x}{:02x}", blend(0), blend(1), blend(2)))
+}
+
+/// Channels of a `#rrggbb` or `#rgb` color
+fn hex_rgb(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    let digits = match hex.len() {
+        3 => 1,
+        6 => 2,
+        _ => return None,
+    };
+    let channel = |i: usize| {
+        let value = u8::from_str_radix(hex.get(i * digits..(i + 1) * digits)?, 16).ok()?;
+        // `#rgb` is shorthand for `#rrggbb`
+        Some(if digits == 1 { value * 17 } else { value })
+    };
+
+    Some([channel(0)?, channel(1)?, channel(2)?])
+}
+
+/// How the value is written out next to the bar
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValueFormat {
+    /// Only the bar
+    #[default]
+    None,
+    /// `45%` of the full scale
+    Percent,
+    /// `45/150`, the message's value and maximum
+    Fraction,
+}
+
+impl ValueFormat {
+    /// The text for a bar at `percent`, `value` being the message's value and maximum
+    pub fn format(self, percent: f64, value: Option<(i32, i32)>) -> Option<String> {
+        match (self, value) {
+            (ValueFormat::None, _) => None,
+            (ValueFormat::Fraction, Some((value, max))) => Some(format!("{}/{}", value, max)),
+            (ValueFormat::Percent | ValueFormat::Fraction, _) => {
+                Some(format!("{}%", percent.round()))
+            }
+        }
+    }
+}
+
+/// Size and styling of the progress bar
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BarConfig {
+    /// Thickness of the bar in pixels
+    pub height: u32,
+    /// Minimum length of the bar in pixels, unset keeps the natural length
+    pub width: Option<u32>,
+    /// Corner radius of the trough and the fill in pixels
+    pub radius: u32,
+    /// Any CSS color
+    pub trough_color: String,
+    /// Write out the value of volume, brightness and other bars
+    pub value: ValueFormat,
+    /// Draw the value on top of the bar instead of as the `value` layout element
+    pub value_inside: bool,
+    /// Volume and mic values above this are overamplified, drawn red past the marker line
+    pub normal_max: i32,
+    /// Draw the bar as this many blocks instead of a continuous fill
+    pub segments: Option<u32>,
+}
+
+impl Default for BarConfig {
+    fn default() -> Self {
+        Self {
+            height: 10,
+            width: None,
+            radius: 5,
+            trough_color: "rgba(100, 100, 100, 0.7)".to_string(),
+            value: ValueFormat::default(),
+            value_inside: false,
+            normal_max: 100,
+            segments: None,
+        }
+    }
+}
+
+/// Font of a label, unset parts keep the stylesheet's
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct FontConfig {
+    pub family: Option<String>,
+    /// Size in pixels
+    pub size: Option<u32>,
+    pub weight: Option<FontWeight>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FontWeight {
+    Thin,
+    Light,
+    Normal,
+    Medium,
+    Semibold,
+    Bold,
+    Heavy,
+}
+
+impl FontWeight {
+    /// The numeric CSS weight
+    pub fn css(self) -> u32 {
+        match self {
+            FontWeight::Thin => 100,
+            FontWeight::Light => 300,
+            FontWeight::Normal => 400,
+            FontWeight::Medium => 500,
+            FontWeight::Semibold => 600,
+            FontWeight::Bold => 700,
+            FontWeight::Heavy => 900,
+        }
+    }
+}
+
+/// Fonts of the labels
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct FontsConfig {
+    /// The text of text, media and other messages
+    pub label: FontConfig,
+    /// The device name under the bar
+    pub device_label: FontConfig,
+}
+
+/// How the label of text messages fits long text
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TextConfig {
+    /// Width the label grows to before wrapping or ellipsizing, in characters
+    pub max_width_chars: i32,
+    /// Break long text into lines instead of widening the window
+    pub wrap: bool,
+    /// Lines shown at most when wrapping, 0 for no limit, only applies when ellipsizing
+    pub max_lines: i32,
+    pub ellipsize: Ellipsize,
+}
+
+impl Default for TextConfig {
+    fn default() -> Self {
+        Self {
+            max_width_chars: 40,
+            wrap: true,
+            max_lines: 3,
+            ellipsize: Ellipsize::default(),
+        }
+    }
+}
+
+/// Where text that doesn't fit is cut off with an ellipsis
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Ellipsize {
+    /// Never cut off, the text gets as long as it needs
+    None,
+    Start,
+    Middle,
+    #[default]
+    End,
+}
+
+/// Commands run by scrolling over an interactive OSD
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ScrollConfig {
+    /// Change per scroll step
+    pub step: u32,
+    /// Commands by message type, with `{delta}`, `{step}` and `{sign}` placeholders
+    pub commands: HashMap<String, String>,
+}
+
+impl Default for ScrollConfig {
+    fn default() -> Self {
+        Self {
+            step: 5,
+            commands: HashMap::new(),
+        }
+    }
+}
+
+impl ScrollConfig {
+    /// The command for scrolling `steps` steps over a message of `message_type`, positive is up
+    ///
+    /// `{delta}` becomes the signed change (`+5` or `-5`), `{step}` its
+    /// magnitude and `{sign}` just the sign.
+    pub fn command(&self, message_type: &str, steps: i32) -> Option<String> {
+        let command = self.commands.get(message_type)?;
+        let delta = steps * self.step as i32;
+        Some(
+            command
+                .replace("{delta}", &format!("{:+}", delta))
+                .replace("{step}", &delta.unsigned_abs().to_string())
+                .replace("{sign}", if delta < 0 { "-" } else { "+" }),
+        )
+    }
+}
+
+/// The user's stylesheet
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct StyleConfig {
+    /// CSS file loaded on top of the built-in styles, skipped when it doesn't exist
+    pub file: Option<PathBuf>,
+    /// Leave out the built-in styles entirely
+    pub replace_builtin: bool,
+    /// Whether the built-in styles are light or dark
+    pub color_scheme: ColorScheme,
+}
+
+/// Common changes to the look of the window, for those who'd rather not write CSS
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// Corner radius in pixels
+    pub radius: Option<u32>,
+    /// Border width in pixels, 1 when only `border_color` is set
+    pub border_width: Option<u32>,
+    /// Any CSS color
+    pub border_color: Option<String>,
+    /// Any CSS color, black when only `background_opacity` is set
+    pub background: Option<String>,
+    /// Opacity of the background from 0 to 1, the contents stay opaque
+    pub background_opacity: Option<f64>,
+    /// Drop a shadow behind text and icons, keeping them readable on a transparent background
+    pub shadow: bool,
+}
+
+/// Look of the built-in styles
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorScheme {
+    /// Follow the desktop's preference, dark unless it prefers light
+    #[default]
+    Auto,
+    Dark,
+    Light,
+}
+
+impl Default for StyleConfig {
+    fn default() -> Self {
+        Self {
+            file: Some(PathBuf::from("style.css")),
+            replace_builtin: false,
+            color_scheme: ColorScheme::default(),
+        }
+    }
+}
+
+/// How messages are queued between the IPC readers and the UI
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct IngestConfig {
+    /// Maximum number of messages waiting to be displayed
+    pub queue_size: usize,
+    pub overflow: OverflowPolicy,
+    pub parsing: ParseMode,
+    /// Minimum time between two updates of the OSD, 0 to update on every message
+    pub min_interval_ms: u64,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            queue_size: 64,
+            overflow: OverflowPolicy::default(),
+            parsing: ParseMode::default(),
+            min_interval_ms: 0,
+        }
+    }
+}
+
+impl IngestConfig {
+    pub fn min_interval(&self) -> Duration {
+        Duration::from_millis(self.min_interval_ms)
+    }
+}
+
+/// How picky the server is about incoming JSON
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParseMode {
+    /// Ignore fields the server doesn't know
+    #[default]
+    Lenient,
+    /// Reject messages with unknown or malformed fields, logging which one
+    Strict,
+}
+
+/// How a message is shown while another one is on screen
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueueMode {
+    /// Replace it right away
+    #[default]
+    Replace,
+    /// Wait for it to time out unless it has the same tag, showing messages one after another
+    Sequential,
+}
+
+/// When the timeout of the OSD starts counting
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeoutMode {
+    /// With every message, so updates keep it up
+    #[default]
+    Extend,
+    /// When the OSD appears, however many updates follow
+    Fixed,
+    /// With every message that changes what is shown, so key repeat at the
+    /// end of the scale doesn't keep it up
+    LastChange,
+}
+
+/// A time of day, written as `HH:MM`
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay {
+    /// Minutes since midnight
+    minutes: u32,
+}
+
+impl TimeOfDay {
+    pub fn new(hour: u32, minute: u32) -> Self {
+        Self {
+            minutes: hour * 60 + minute,
+        }
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(time: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid time `{}`, expected HH:MM", time);
+        let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+        let hour: u32 = hour.parse().map_err(|_| invalid())?;
+        let minute: u32 = minute.parse().map_err(|_| invalid())?;
+        if hour > 23 || minute > 59 {
+            return Err(invalid());
+        }
+        Ok(Self::new(hour, minute))
+    }
+}
+
+/// What quiet hours do to messages that aren't critical
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuietMode {
+    /// Don't show them at all
+    #[default]
+    Suppress,
+    /// Show them fainter and shorter
+    Dim,
+}
+
+/// Times of the day during which OSDs are toned down
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct QuietHoursConfig {
+    /// Quiet hours are off unless both `from` and `to` are set
+    pub from: Option<TimeOfDay>,
+    /// May be before `from`, for quiet hours over midnight
+    pub to: Option<TimeOfDay>,
+    pub mode: QuietMode,
+    /// Opacity of the OSD when dimmed
+    pub opacity: f64,
+    /// Longest an OSD stays up when dimmed
+    pub timeout_ms: u64,
+}
+
+impl Default for QuietHoursConfig {
+    fn default() -> Self {
+        Self {
+            from: None,
+            to: None,
+            mode: QuietMode::default(),
+            opacity: 0.5,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+impl QuietHoursConfig {
+    /// Whether `time` falls into the quiet hours
+    pub fn contains(&self, time: TimeOfDay) -> bool {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from <= to => from <= time && time < to,
+            (Some(from), Some(to)) => time >= from || time < to,
+            _ => false,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+/// What happens to messages of a lower priority than the one on screen
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LowerPriority {
+    /// Show them one after another once the higher priority message has timed out
+    #[default]
+    Queue,
+    /// Discard them
+    Drop,
+}
+
+/// How messages with a `priority` interact
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PriorityConfig {
+    pub lower: LowerPriority,
+}
+
+/// Where on the output the OSD is placed
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    Top,
+    #[default]
+    Bottom,
+    Left,
+    Right,
+    Center,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Screen edges a surface can be attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Anchor {
+    /// The edges the surface is attached to, none for centered
+    pub fn edges(self) -> &'static [Edge] {
+        match self {
+            Anchor::Top => &[Edge::Top],
+            Anchor::Bottom => &[Edge::Bottom],
+            Anchor::Left => &[Edge::Left],
+            Anchor::Right => &[Edge::Right],
+            Anchor::Center => &[],
+            Anchor::TopLeft => &[Edge::Top, Edge::Left],
+            Anchor::TopRight => &[Edge::Top, Edge::Right],
+            Anchor::BottomLeft => &[Edge::Bottom, Edge::Left],
+            Anchor::BottomRight => &[Edge::Bottom, Edge::Right],
+        }
+    }
+}
+
+/// Distance from each screen edge in pixels, negative values move past it
+///
+/// Only the edges the OSD is anchored to have an effect.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct Margins {
+    pub top: i32,
+    pub bottom: i32,
+    pub left: i32,
+    pub right: i32,
+}
+
+impl Default for Margins {
+    fn default() -> Self {
+        Self {
+            top: 50,
+            bottom: 50,
+            left: 50,
+            right: 50,
+        }
+    }
+}
+
+impl Margins {
+    pub fn get(&self, edge: Edge) -> i32 {
+        match edge {
+            Edge::Top => self.top,
+            Edge::Bottom => self.bottom,
+            Edge::Left => self.left,
+            Edge::Right => self.right,
+        }
+    }
+}
+
+/// Placement and behavior of the layer-shell surface
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub anchor: Anchor,
+    pub margin: Margins,
+    /// Unmap the surface while hidden instead of making it transparent
+    pub unmap_when_hidden: bool,
+    /// Connector names of the outputs to show the OSD on (e.g. `DP-1`), most preferred first
+    pub outputs: Vec<String>,
+    /// Show a copy of the OSD on every output, ignoring `outputs`
+    pub show_on_all_outputs: bool,
+    /// Space around the contents in pixels, unset keeps the stylesheet's
+    pub padding: Option<u32>,
+    /// Take pointer and touch input while shown, a click dismisses the OSD
+    pub interactive: bool,
+    /// Reserve space along the anchored edge while shown, pushing other windows aside
+    pub exclusive_zone: bool,
+    /// Width of the OSD in percent of the output it is on, unset sizes it to its contents
+    pub width_percent: Option<u32>,
+    /// Ask the compositor to blur the background behind the OSD
+    pub blur: bool,
+    /// Opacity of the whole OSD from 0 to 1 while shown, unset for fully opaque
+    pub opacity: Option<f64>,
+}
+
+impl WindowConfig {
+    /// Index of the most preferred output among the `connected` ones, if any of them is listed
+    ///
+    /// An output `requested` by the message comes before the configured ones.
+    pub fn preferred_output(&self, requested: Option<&str>, connected: &[&str]) -> Option<usize> {
+        requested
+            .into_iter()
+            .chain(self.outputs.iter().map(String::as_str))
+            .find_map(|output| connected.iter().position(|connected| *connected == output))
+    }
+
+    /// Whether the output has to be picked by us instead of the compositor
+    pub fn selects_output(&self, requested: Option<&str>) -> bool {
+        requested.is_some() || !self.outputs.is_empty()
+    }
+}
+
+/// Message types shown in a window of their own, independent of the main one
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CategoryConfig {
+    /// Message types belonging to the category
+    pub types: Vec<String>,
+    /// Placement of the category's window, defaults to the one in `window`
+    pub anchor: Option<Anchor>,
+    pub margin: Option<Margins>,
+    /// Overrides `timeout_ms` for the category's messages
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// How long the OSD stays up after a message that doesn't set `timeout_ms`
+    pub timeout_ms: u64,
+    /// Rules are tried in order, the first match wins
+    pub device_icons: Vec<DeviceIconRule>,
+    pub icon_pack: IconPack,
+    /// Take the built-in icons from the icon theme when it has them
+    pub use_icon_theme: bool,
+    /// Size of the icons in pixels
+    pub icon_size: i32,
+    pub ingest: IngestConfig,
+    pub timeout_mode: TimeoutMode,
+    pub quiet_hours: QuietHoursConfig,
+    pub queue_mode: QueueMode,
+    pub priority: PriorityConfig,
+    pub animation: AnimationConfig,
+    pub window: WindowConfig,
+    /// Categories by name, each with its own window and timeout
+    pub categories: HashMap<String, CategoryConfig>,
+    /// Message types set to `false` are ignored
+    pub show: HashMap<String, bool>,
+    pub style: StyleConfig,
+    pub theme: ThemeConfig,
+    pub bar: BarConfig,
+    pub text: TextConfig,
+    pub fonts: FontsConfig,
+    /// Bar colors by message type, the first matching threshold wins
+    pub bar_colors: HashMap<String, BarColors>,
+    /// Spacing of tick marks on the bar in percent, by message type
+    pub bar_ticks: HashMap<String, u32>,
+    pub hooks: Vec<HookConfig>,
+    pub scroll: ScrollConfig,
+    pub journal: JournalConfig,
+    /// Layouts by message type, types without one use their built-in layout
+    pub layouts: HashMap<String, Layout>,
+    /// GtkBuilder file replacing the built-in widgets, `layouts` are ignored when set
+    pub ui_file: Option<PathBuf>,
+    /// Profile applied on startup
+    pub profile: Option<String>,
+    /// Named sets of settings overriding the rest of the file, see [`Config::with_profile`]
+    pub profiles: HashMap<String, toml::Table>,
+    /// Rhai script that can rewrite messages, needs the `scripting` feature
+    pub script: Option<PathBuf>,
+    /// The file as it was read, which profiles are applied on top of
+    #[serde(skip)]
+    source: toml::Table,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let rule = |keyword: &str, icon| DeviceIconRule {
+            keyword: keyword.to_string(),
+            icon,
+        };
+
+        Self {
+            timeout_ms: DEFAULT_TIMEOUT.as_millis() as u64,
+            device_icons: vec![
+                rule("bluez", DeviceIcon::Bluetooth),
+                rule("bluetooth", DeviceIcon::Bluetooth),
+                rule("headset", DeviceIcon::Headset),
+                rule("headphone", DeviceIcon::Headphones),
+                rule("hdmi", DeviceIcon::Tv),
+                rule("displayport", DeviceIcon::Tv),
+                rule("usb", DeviceIcon::UsbDac),
+            ],
+            icon_pack: IconPack::default(),
+            use_icon_theme: true,
+            icon_size: 16,
+            ingest: IngestConfig::default(),
+            timeout_mode: TimeoutMode::default(),
+            quiet_hours: QuietHoursConfig::default(),
+            queue_mode: QueueMode::default(),
+            priority: PriorityConfig::default(),
+            animation: AnimationConfig::default(),
+            window: WindowConfig::default(),
+            categories: HashMap::new(),
+            show: HashMap::new(),
+            style: StyleConfig::default(),
+            theme: ThemeConfig::default(),
+            bar: BarConfig::default(),
+            text: TextConfig::default(),
+            fonts: FontsConfig::default(),
+            bar_colors: HashMap::new(),
+            bar_ticks: HashMap::new(),
+            hooks: Vec::new(),
+            scroll: ScrollConfig::default(),
+            journal: JournalConfig::default(),
+            layouts: HashMap::new(),
+            ui_file: None,
+            profile: None,
+            profiles: HashMap::new(),
+            script: None,
+            source: toml::Table::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Location of the config file, `$XDG_CONFIG_HOME/wayland-osd/config.toml`
+    pub fn path() -> Option<PathBuf> {
+        Self::instance_path(&Instance::default())
+    }
+
+    /// Location of an instance's config file, named instances use `instance-<name>.toml`
+    pub fn instance_path(instance: &Instance) -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(
+            config_dir
+                .join("wayland-osd")
+                .join(instance.file_name("config", "toml")),
+        )
+    }
+
+    /// Resolve a path from the config file, relative paths are taken from the config directory
+    pub fn resolve_path(path: &Path) -> PathBuf {
+        match Self::path().as_deref().and_then(Path::parent) {
+            Some(config_dir) if path.is_relative() => config_dir.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    /// Load the config file, falling back to the defaults when it doesn't exist
+    pub fn load() -> anyhow::Result<Self> {
+        Self::load_instance(&Instance::default())
+    }
+
+    /// Load the config file of an instance, see [`Config::load`]
+    pub fn load_instance(instance: &Instance) -> anyhow::Result<Self> {
+        let config = match Self::instance_path(instance) {
+            Some(path) => {
+                let table = load_file(&path)?;
+                Self::from_table(table)
+                    .with_context(|| format!("Failed to parse config file {}", path.display()))?
+            }
+            None => {
+                debug!("Could not determine config directory, using defaults");
+                Self::default()
+            }
+        };
+
+        match config.profile.clone() {
+            Some(profile) => config.with_profile(&profile),
+            None => Ok(config),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    /// The category whose window shows messages of `message_type`, `None` for the main window
+    pub fn category(&self, message_type: &str) -> Option<&str> {
+        // The first by name, should a type be listed more than once
+        self.categories
+            .iter()
+            .filter(|(_, category)| category.types.iter().any(|t| t == message_type))
+            .map(|(name, _)| name.as_str())
+            .min()
+    }
+
+    /// The settings of a category's window, with its overrides applied
+    pub fn for_category(&self, name: Option<&str>) -> Config {
+        let mut config = self.clone();
+        let Some(category) = name.and_then(|name| self.categories.get(name)) else {
+            return config;
+        };
+
+        if let Some(anchor) = category.anchor {
+            config.window.anchor = anchor;
+        }
+        if let Some(margin) = category.margin {
+            config.window.margin = margin;
+        }
+        if let Some(timeout_ms) = category.timeout_ms {
+            config.timeout_ms = timeout_ms;
+        }
+        config
+    }
+
+    /// The layout used to display messages of `message_type`
+    pub fn layout(&self, message_type: &str) -> Layout {
+        self.layouts
+            .get(message_type)
+            .cloned()
+            .unwrap_or_else(|| Layout::default_for(message_type))
+    }
+
+    /// The settings of the profile `name`, based on this config
+    ///
+    /// Tables in the profile are merged into the ones in the file, any other
+    /// value replaces the one from the file. The profile `default` is the
+    /// file without any overrides.
+    pub fn with_profile(&self, name: &str) -> anyhow::Result<Config> {
+        let mut table = self.source.clone();
+
+        if name != DEFAULT_PROFILE {
+            let profile = self
+                .profiles
+                .get(name)
+                .with_context(|| format!("Unknown profile `{}`", name))?;
+            merge_tables(&mut table, profile.clone());
+        }
+
+        let mut config = Self::from_table(table)
+            .with_context(|| format!("Invalid settings in profile `{}`", name))?;
+        // Profiles always apply to the file, not to each other
+        config.source = self.source.clone();
+        Ok(config)
+    }
+
+    fn from_table(table: toml::Table) -> anyhow::Result<Self> {
+        let mut config: Self = toml::Value::Table(table.clone()).try_into()?;
+        config.source = table;
+        Ok(config)
+    }
+
+    /// The configured color of the bar for a value of `percent` in messages of `message_type`
+    pub fn bar_color(&self, message_type: &str, percent: f64) -> Option<String> {
+        self.bar_colors.get(message_type)?.color(percent)
+    }
+
+    /// Whether messages of this type are shown at all
+    pub fn shows(&self, message_type: &str) -> bool {
+        self.show.get(message_type).copied().unwrap_or(true)
+    }
+
+    /// Pick the icon for an audio device from its class hint or name
+    pub fn device_icon(&self, device_class: Option<&str>, device_name: Option<&str>) -> DeviceIcon {
+        let candidates = [device_class, device_name];
+
+        for rule in &self.device_icons {
+            let keyword = rule.keyword.to_lowercase();
+            let matches = candidates
+                .iter()
+                .flatten()
+                .any(|candidate| candidate.to_lowercase().contains(&keyword));
+
+            if matches {
+                return rule.icon;
+            }
+        }
+
+        DeviceIcon::Speaker
+    }
+}
+
+/// Name of the profile that leaves the config unchanged
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Recursively merge `overrides` into `base`
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                merge_tables(base, overrides)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Parse a TOML config file into `T`, falling back to its defaults when the file doesn't exist
+///
+/// Every component reads its own keys from the same file, so unknown keys are ignored.
+pub fn load_file<T: DeserializeOwned + Default>(path: &Path) -> anyhow::Result<T> {
+    if !path.exists() {
+        debug!("No config file at {}, using defaults", path.display());
+        return Ok(T::default());
+    }
+
+    info!("Loading config from {}", path.display());
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse config file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Config {
+        toml::from_str(toml).expect("valid config")
+    }
+
+    fn quiet_hours(from: &str, to: &str) -> QuietHoursConfig {
+        parse(&format!(
+            "[quiet_hours]\nfrom = \"{}\"\nto = \"{}\"",
+            from, to
+        ))
+        .quiet_hours
+    }
+
+    fn stop(at: f64, color: &str) -> GradientStop {
+        GradientStop {
+            at,
+            color: color.to_string(),
+        }
+    }
+
+    #[test]
+    fn device_icon_matches_class_or_name() {
+        let config = Config::default();
+        assert_eq!(config.device_icon(Some("HDMI"), None), DeviceIcon::Tv);
+        assert_eq!(
+            config.device_icon(None, Some("WH-1000XM4 Headphones")),
+            DeviceIcon::Headphones
+        );
+        assert_eq!(
+            config.device_icon(Some("analog"), Some("Built-in Audio")),
+            DeviceIcon::Speaker
+        );
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let config = Config::default();
+        // "bluez" comes before "headset"
+        assert_eq!(
+            config.device_icon(Some("bluez_output"), Some("Headset")),
+            DeviceIcon::Bluetooth
+        );
+    }
+
+    #[test]
+    fn empty_device_icon_keyword_is_rejected() {
+        let rules =
+            |keyword: &str| format!("[[device_icons]]\nmatch = \"{}\"\nicon = \"tv\"", keyword);
+        assert!(toml::from_str::<Config>(&rules("")).is_err());
+        assert_eq!(
+            parse(&rules("hdmi")).device_icon(Some("HDMI"), None),
+            DeviceIcon::Tv
+        );
+    }
+
+    #[test]
+    fn gradient_blends_between_stops() {
+        let stops = [stop(0.0, "#000000"), stop(100.0, "#ffffff")];
+        assert_eq!(gradient_color(&stops, 50.0).as_deref(), Some("#808080"));
+        assert_eq!(gradient_color(&stops, 0.0).as_deref(), Some("#000000"));
+    }
+
+    #[test]
+    fn gradient_keeps_nearest_color_outside_of_stops() {
+        let stops = [stop(20.0, "#f00"), stop(80.0, "#00f")];
+        assert_eq!(gradient_color(&stops, 10.0).as_deref(), Some("#f00"));
+        assert_eq!(gradient_color(&stops, 90.0).as_deref(), Some("#00f"));
+        assert_eq!(gradient_color(&stops, 50.0).as_deref(), Some("#800080"));
+        assert_eq!(gradient_color(&[], 50.0), None);
+    }
+
+    #[test]
+    fn gradient_switches_over_between_named_colors() {
+        let stops = [stop(0.0, "red"), stop(100.0, "#0000ff")];
+        assert_eq!(gradient_color(&stops, 60.0).as_deref(), Some("red"));
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(hex_rgb("#3465a4"), Some([0x34, 0x65, 0xa4]));
+        assert_eq!(hex_rgb("#fa0"), Some([0xff, 0xaa, 0x00]));
+        assert_eq!(hex_rgb("#12345"), None);
+        assert_eq!(hex_rgb("#ggg"), None);
+        assert_eq!(hex_rgb("red"), None);
+    }
+
+    #[test]
+    fn bar_colors() {
+        let config = parse(
+            r##"
+            [bar_colors]
+            brightness = "#ffcc00"
+            volume = [{ above = 100, color = "red" }, { below = 20, color = "gray" }]
+            mic = { gradient = [{ at = 0, color = "#000" }, { at = 100, color = "#fff" }] }
+            "##,
+        );
+        assert_eq!(
+            config.bar_color("brightness", 10.0).as_deref(),
+            Some("#ffcc00")
+        );
+        assert_eq!(config.bar_color("volume", 120.0).as_deref(), Some("red"));
+        assert_eq!(config.bar_color("volume", 10.0).as_deref(), Some("gray"));
+        assert_eq!(config.bar_color("volume", 50.0), None);
+        assert_eq!(config.bar_color("mic", 100.0).as_deref(), Some("#fff"));
+        assert_eq!(config.bar_color("battery", 50.0), None);
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let quiet = quiet_hours("13:00", "14:30");
+        assert!(!quiet.contains(TimeOfDay::new(12, 59)));
+        assert!(quiet.contains(TimeOfDay::new(13, 0)));
+        assert!(quiet.contains(TimeOfDay::new(14, 29)));
+        assert!(!quiet.contains(TimeOfDay::new(14, 30)));
+    }
+
+    #[test]
+    fn quiet_hours_across_midnight() {
+        let quiet = quiet_hours("22:00", "07:00");
+        assert!(!quiet.contains(TimeOfDay::new(21, 59)));
+        assert!(quiet.contains(TimeOfDay::new(22, 0)));
+        assert!(quiet.contains(TimeOfDay::new(23, 59)));
+        assert!(quiet.contains(TimeOfDay::new(0, 0)));
+        assert!(quiet.contains(TimeOfDay::new(6, 59)));
+        assert!(!quiet.contains(TimeOfDay::new(7, 0)));
+        assert!(!quiet.contains(TimeOfDay::new(12, 0)));
+    }
+
+    #[test]
+    fn quiet_hours_off_unless_both_ends_set() {
+        assert!(!QuietHoursConfig::default().contains(TimeOfDay::new(3, 0)));
+        let quiet = parse("[quiet_hours]\nfrom = \"22:00\"").quiet_hours;
+        assert!(!quiet.contains(TimeOfDay::new(23, 0)));
+        // An empty window, not the whole day
+        assert!(!quiet_hours("08:00", "08:00").contains(TimeOfDay::new(8, 0)));
+    }
+
+    #[test]
+    fn invalid_times() {
+        for time in ["24:00", "12:60", "noon", "12", "-1:00"] {
+            assert!(
+                TimeOfDay::try_from(time.to_string()).is_err(),
+                "{} should be rejected",
+                time
+            );
+        }
+        assert_eq!(
+            TimeOfDay::try_from("7:05".to_string()),
+            Ok(TimeOfDay::new(7, 5))
+        );
+    }
+
+    #[test]
+    fn message_types_can_be_turned_off() {
+        let config = parse("[show]\nbrightness = false\nvolume = true");
+        assert!(!config.shows("brightness"));
+        assert!(config.shows("volume"));
+        assert!(config.shows("text"));
+    }
+}
@@ -0,0 +1,101 @@
+use crate::message::OsdMessage;
+
+/// Representative messages for trying out positioning and themes, with a name for each
+pub fn samples() -> Vec<(&'static str, OsdMessage)> {
+    let progress = |message_type: &str, value, max_value| OsdMessage {
+        value: Some(value),
+        max_value: Some(max_value),
+        ..OsdMessage::new(message_type)
+    };
+    let text = |text: &str| OsdMessage {
+        text: Some(text.to_string()),
+        ..OsdMessage::new("text")
+    };
+
+    vec![
+        ("volume", progress("volume", 60, 100)),
+        (
+            "volume-muted",
+            OsdMessage {
+                muted: Some(true),
+                ..progress("volume", 60, 100)
+            },
+        ),
+        (
+            "volume-overamplified",
+            OsdMessage {
+                device_name: Some("Headphones".to_string()),
+                device_class: Some("headphones".to_string()),
+                ..progress("volume", 130, 150)
+            },
+        ),
+        ("mic", progress("mic", 45, 100)),
+        ("brightness", progress("brightness", 80, 100)),
+        (
+            "battery",
+            OsdMessage {
+                status: Some("discharging".to_string()),
+                critical: Some(true),
+                ..progress("battery", 8, 100)
+            },
+        ),
+        (
+            "bluetooth",
+            OsdMessage {
+                device_name: Some("Headphones".to_string()),
+                connected: Some(true),
+                value: Some(80),
+                ..OsdMessage::new("bluetooth")
+            },
+        ),
+        (
+            "network",
+            OsdMessage {
+                connection: Some("wireless".to_string()),
+                ssid: Some("Home".to_string()),
+                value: Some(70),
+                ..OsdMessage::new("network")
+            },
+        ),
+        (
+            "lock",
+            OsdMessage {
+                key: Some("caps".to_string()),
+                enabled: Some(true),
+                ..OsdMessage::new("lock")
+            },
+        ),
+        (
+            "power-profile",
+            OsdMessage {
+                text: Some("power-saver".to_string()),
+                ..OsdMessage::new("power-profile")
+            },
+        ),
+        (
+            "keyboard-layout",
+            OsdMessage {
+                text: Some("English (US)".to_string()),
+                ..OsdMessage::new("keyboard-layout")
+            },
+        ),
+        (
+            "progress",
+            OsdMessage {
+                label: Some("Copying photos".to_string()),
+                ..progress("progress", 42, 120)
+            },
+        ),
+        ("text", text("Meeting in 5 minutes")),
+        ("notification", text("New message from Alex")),
+        (
+            "media",
+            OsdMessage {
+                title: Some("Roygbiv".to_string()),
+                artist: Some("Boards of Canada".to_string()),
+                playback: Some("playing".to_string()),
+                ..OsdMessage::new("media")
+            },
+        ),
+    ]
+}
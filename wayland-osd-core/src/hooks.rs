@@ -0,0 +1,205 @@
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, error};
+use serde::Deserialize;
+
+use crate::message::OsdMessage;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    /// The OSD became visible
+    Show,
+    /// The OSD was hidden again
+    Hide,
+    /// A message was accepted for display
+    Message,
+}
+
+/// A command run when an event happens
+#[derive(Debug, Deserialize, Clone)]
+pub struct HookConfig {
+    pub event: HookEvent,
+    /// Only run for messages of this type
+    #[serde(rename = "type")]
+    pub message_type: Option<String>,
+    /// Run with `sh -c`, `{field}` placeholders are replaced by shell-quoted message fields
+    pub command: String,
+    /// Minimum time between two runs of this hook
+    #[serde(default = "default_min_interval_ms")]
+    pub min_interval_ms: u64,
+}
+
+fn default_min_interval_ms() -> u64 {
+    100
+}
+
+/// Runs the configured hooks without blocking the caller
+#[derive(Debug, Default)]
+pub struct Hooks {
+    hooks: Vec<HookConfig>,
+    last_run: Vec<Option<Instant>>,
+}
+
+impl Hooks {
+    pub fn new(hooks: Vec<HookConfig>) -> Self {
+        let last_run = vec![None; hooks.len()];
+        Self { hooks, last_run }
+    }
+
+    /// Run every hook registered for `event`, `msg` provides the values for placeholders
+    pub fn fire(&mut self, event: HookEvent, msg: Option<&OsdMessage>) {
+        let now = Instant::now();
+
+        for (hook, last_run) in self.hooks.iter().zip(self.last_run.iter_mut()) {
+            if hook.event != event {
+                continue;
+            }
+
+            if let Some(message_type) = &hook.message_type {
+                if msg.map(|msg| &msg.message_type) != Some(message_type) {
+                    continue;
+                }
+            }
+
+            let min_interval = Duration::from_millis(hook.min_interval_ms);
+            if last_run.is_some_and(|last_run| now.duration_since(last_run) < min_interval) {
+                debug!("Rate limiting hook: {}", hook.command);
+                continue;
+            }
+            *last_run = Some(now);
+
+            run(&substitute(&hook.command, msg));
+        }
+    }
+}
+
+/// Run `command` with `sh -c` in the background
+pub fn run(command: &str) {
+    debug!("Running command: {}", command);
+
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .spawn();
+
+    match child {
+        // Reap the child in the background so it doesn't linger as a zombie
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => error!("Failed to run hook `{}`: {}", command, e),
+    }
+}
+
+/// Replace `{field}` placeholders with the message's values, quoted for the shell
+fn substitute(command: &str, msg: Option<&OsdMessage>) -> String {
+    let Some(msg) = msg else {
+        return command.to_string();
+    };
+
+    let fields = [
+        ("type", Some(msg.message_type.clone())),
+        ("tag", Some(msg.tag().to_string())),
+        ("value", msg.value.map(|v| v.to_string())),
+        ("max_value", msg.max_value.map(|v| v.to_string())),
+        ("text", msg.text.clone()),
+        ("muted", msg.muted.map(|v| v.to_string())),
+        ("device_name", msg.device_name.clone()),
+    ];
+
+    // In a single pass, so placeholders inside a value are left alone
+    let mut substituted = String::with_capacity(command.len());
+    let mut rest = command;
+    while let Some(start) = rest.find('{') {
+        substituted.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let field = rest.find('}').and_then(|end| {
+            let (_, value) = fields.iter().find(|(name, _)| *name == &rest[1..end])?;
+            Some((end, value))
+        });
+        match field {
+            Some((end, value)) => {
+                substituted.push_str(&shell_quote(value.as_deref().unwrap_or("")));
+                rest = &rest[end + 1..];
+            }
+            None => {
+                substituted.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    substituted.push_str(rest);
+    substituted
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> OsdMessage {
+        OsdMessage {
+            text: Some(text.to_string()),
+            ..OsdMessage::new("text")
+        }
+    }
+
+    #[test]
+    fn substitutes_quoted_fields() {
+        let msg = OsdMessage {
+            value: Some(40),
+            muted: Some(false),
+            ..OsdMessage::new("volume")
+        };
+        assert_eq!(
+            substitute("notify {type} {value} {muted}", Some(&msg)),
+            "notify 'volume' '40' 'false'"
+        );
+    }
+
+    #[test]
+    fn unset_fields_become_empty_strings() {
+        let msg = OsdMessage::new("volume");
+        assert_eq!(substitute("echo {value} {text}", Some(&msg)), "echo '' ''");
+    }
+
+    #[test]
+    fn without_message_command_is_unchanged() {
+        assert_eq!(substitute("echo {value}", None), "echo {value}");
+    }
+
+    #[test]
+    fn quotes_shell_syntax() {
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(
+            substitute("echo {text}", Some(&text("$(reboot); `id`"))),
+            "echo '$(reboot); `id`'"
+        );
+
+        // A placeholder in a value isn't substituted again, inside its quotes
+        let msg = OsdMessage {
+            device_name: Some("'; reboot; '".to_string()),
+            ..text("{device_name}")
+        };
+        assert_eq!(
+            substitute("echo {text} {device_name} {unknown}", Some(&msg)),
+            r"echo '{device_name}' ''\''; reboot; '\''' {unknown}"
+        );
+    }
+
+    #[test]
+    fn shell_receives_value_unchanged() {
+        let value = "'; echo injected; ' \"$HOME\" \\";
+        let command = substitute("printf %s {text}", Some(&text(value)));
+        let output = Command::new("sh").arg("-c").arg(command).output().unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), value);
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::message::{OsdMessage, Peer};
+use crate::stats::Stats;
+
+/// What to do when messages arrive faster than they can be displayed
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued message to make room, so the latest state always gets shown
+    #[default]
+    DropOldest,
+    /// Discard the incoming message
+    DropNewest,
+    /// Replace a queued message with the same tag and id, falling back to dropping the oldest
+    CoalesceByTag,
+}
+
+struct Queue {
+    messages: Mutex<VecDeque<OsdMessage>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    stats: Arc<Stats>,
+}
+
+/// Sending half of the bounded queue between IPC reader threads and the UI
+///
+/// Sending never blocks, so a flood of input can't stall the readers, and the
+/// bounded capacity keeps it from piling up work for the main loop.
+#[derive(Clone)]
+pub struct IngestSender {
+    queue: Arc<Queue>,
+    /// Wakes the receiver, holds at most one pending notification
+    wake: async_channel::Sender<()>,
+}
+
+/// Receiving half of the queue, consumed on the main loop
+pub struct IngestReceiver {
+    queue: Arc<Queue>,
+    wake: async_channel::Receiver<()>,
+}
+
+/// Create a queue holding at most `capacity` messages
+pub fn channel(
+    capacity: usize,
+    policy: OverflowPolicy,
+    stats: Arc<Stats>,
+) -> (IngestSender, IngestReceiver) {
+    let queue = Arc::new(Queue {
+        messages: Mutex::new(VecDeque::with_capacity(capacity)),
+        capacity: capacity.max(1),
+        policy,
+        stats,
+    });
+    let (wake_sender, wake_receiver) = async_channel::bounded(1);
+
+    (
+        IngestSender {
+            queue: queue.clone(),
+            wake: wake_sender,
+        },
+        IngestReceiver {
+            queue,
+            wake: wake_receiver,
+        },
+    )
+}
+
+impl IngestSender {
+    /// Queue a message, returns `false` once the receiving side has gone away
+    pub fn send(&self, msg: OsdMessage) -> bool {
+        self.send_batch(vec![msg], None)
+    }
+
+    /// Queue several messages at once, waking the receiver only once for all of them
+    ///
+    /// `peer` is the socket client that wrote them, `None` for the pipe.
+    pub fn send_batch(&self, mut msgs: Vec<OsdMessage>, peer: Option<Peer>) -> bool {
+        if self.wake.is_closed() {
+            return false;
+        }
+        if msgs.is_empty() {
+            return true;
+        }
+
+        for msg in &mut msgs {
+            msg.peer = peer;
+        }
+        self.queue.push_all(msgs);
+        // A full wake channel already has a notification pending
+        let _ = self.wake.try_send(());
+        true
+    }
+
+    pub fn stats(&self) -> &Arc<Stats> {
+        &self.queue.stats
+    }
+}
+
+impl IngestReceiver {
+    /// Wait for messages and take everything queued so far, see [`coalesce`] to
+    /// reduce them. Returns `None` once every sender is gone.
+    pub async fn recv_batch(&self) -> Option<Vec<OsdMessage>> {
+        loop {
+            let batch = self.queue.drain();
+            if !batch.is_empty() {
+                return Some(batch);
+            }
+
+            if self.wake.recv().await.is_err() {
+                let batch = self.queue.drain();
+                return (!batch.is_empty()).then_some(batch);
+            }
+        }
+    }
+
+    /// Wait for the next message, returns `None` once every sender is gone
+    pub async fn recv(&self) -> Option<OsdMessage> {
+        loop {
+            if let Some(msg) = self.queue.pop() {
+                return Some(msg);
+            }
+
+            if self.wake.recv().await.is_err() {
+                // Senders may have queued more right before going away
+                return self.queue.pop();
+            }
+        }
+    }
+}
+
+/// Keep only the last message of every tag and id, in the order those last messages arrived
+///
+/// Control messages are all kept, two do-not-disturb toggles don't cancel out
+/// when reduced to one.
+pub fn coalesce(batch: Vec<OsdMessage>) -> Vec<OsdMessage> {
+    let mut latest: Vec<OsdMessage> = Vec::with_capacity(batch.len());
+
+    for msg in batch {
+        latest.retain(|queued| !replaces(&msg, queued));
+        latest.push(msg);
+    }
+
+    latest
+}
+
+/// Whether `msg` makes `queued` obsolete
+fn replaces(msg: &OsdMessage, queued: &OsdMessage) -> bool {
+    !msg.is_control() && !queued.is_control() && msg.tag() == queued.tag() && msg.id == queued.id
+}
+
+impl Queue {
+    fn push_all(&self, msgs: Vec<OsdMessage>) {
+        let mut messages = self.messages.lock().unwrap();
+        for msg in msgs {
+            self.push_locked(&mut messages, msg);
+        }
+    }
+
+    fn push_locked(&self, messages: &mut VecDeque<OsdMessage>, msg: OsdMessage) {
+        if self.policy == OverflowPolicy::CoalesceByTag {
+            if let Some(index) = messages.iter().position(|queued| replaces(&msg, queued)) {
+                messages.remove(index);
+                messages.push_back(msg);
+                self.stats.record_coalesced();
+                return;
+            }
+        }
+
+        if messages.len() >= self.capacity {
+            self.stats.record_queue_full();
+
+            if self.policy == OverflowPolicy::DropNewest {
+                warn!("Ingest queue full, dropping new message: {:?}", msg);
+                return;
+            }
+
+            let dropped = messages.pop_front();
+            warn!("Ingest queue full, dropping oldest message: {:?}", dropped);
+        }
+
+        messages.push_back(msg);
+    }
+
+    fn pop(&self) -> Option<OsdMessage> {
+        self.messages.lock().unwrap().pop_front()
+    }
+
+    fn drain(&self) -> Vec<OsdMessage> {
+        self.messages.lock().unwrap().drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::DND_MESSAGE_TYPE;
+
+    fn tagged(tag: &str, value: i32) -> OsdMessage {
+        OsdMessage {
+            tag: Some(tag.to_string()),
+            value: Some(value),
+            ..OsdMessage::new("volume")
+        }
+    }
+
+    /// Send every message on its own into a queue of `capacity`, returning what ends up queued
+    fn queue_all(
+        policy: OverflowPolicy,
+        capacity: usize,
+        msgs: Vec<OsdMessage>,
+    ) -> (Vec<(String, i32)>, Arc<Stats>) {
+        let stats = Arc::new(Stats::new());
+        let (sender, receiver) = channel(capacity, policy, stats.clone());
+        for msg in msgs {
+            assert!(sender.send(msg));
+        }
+
+        let queued = std::iter::from_fn(|| receiver.queue.pop())
+            .map(|msg| (msg.tag().to_string(), msg.value.unwrap()))
+            .collect();
+        (queued, stats)
+    }
+
+    fn pairs(expected: &[(&str, i32)]) -> Vec<(String, i32)> {
+        expected
+            .iter()
+            .map(|&(tag, value)| (tag.to_string(), value))
+            .collect()
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let msgs = vec![tagged("a", 1), tagged("b", 2), tagged("c", 3)];
+        let (queued, stats) = queue_all(OverflowPolicy::DropOldest, 2, msgs);
+        assert_eq!(queued, pairs(&[("b", 2), ("c", 3)]));
+        assert_eq!(stats.snapshot().dropped_queue_full, 1);
+    }
+
+    #[test]
+    fn drop_newest() {
+        let msgs = vec![tagged("a", 1), tagged("b", 2), tagged("c", 3)];
+        let (queued, stats) = queue_all(OverflowPolicy::DropNewest, 2, msgs);
+        assert_eq!(queued, pairs(&[("a", 1), ("b", 2)]));
+        assert_eq!(stats.snapshot().dropped_queue_full, 1);
+    }
+
+    #[test]
+    fn coalesce_by_tag() {
+        let msgs = vec![tagged("a", 1), tagged("b", 2), tagged("a", 3)];
+        let (queued, stats) = queue_all(OverflowPolicy::CoalesceByTag, 2, msgs);
+        // The replaced message moves to the back
+        assert_eq!(queued, pairs(&[("b", 2), ("a", 3)]));
+        assert_eq!(stats.snapshot().coalesced, 1);
+        assert_eq!(stats.snapshot().dropped_queue_full, 0);
+
+        // Without a match it falls back to dropping the oldest
+        let msgs = vec![tagged("a", 1), tagged("b", 2), tagged("c", 3)];
+        let (queued, _) = queue_all(OverflowPolicy::CoalesceByTag, 2, msgs);
+        assert_eq!(queued, pairs(&[("b", 2), ("c", 3)]));
+    }
+
+    #[test]
+    fn zero_capacity_holds_one_message() {
+        let msgs = vec![tagged("a", 1), tagged("b", 2)];
+        let (queued, _) = queue_all(OverflowPolicy::DropOldest, 0, msgs);
+        assert_eq!(queued, pairs(&[("b", 2)]));
+    }
+
+    #[test]
+    fn send_batch_stamps_peer() {
+        let (sender, receiver) = channel(8, OverflowPolicy::default(), Arc::new(Stats::new()));
+        let peer = Peer { pid: 42, uid: 1000 };
+        assert!(sender.send_batch(vec![tagged("a", 1), tagged("b", 2)], Some(peer)));
+        assert!(sender.send(tagged("c", 3)));
+
+        let peers: Vec<_> = receiver
+            .queue
+            .drain()
+            .into_iter()
+            .map(|msg| msg.peer)
+            .collect();
+        assert_eq!(peers, vec![Some(peer), Some(peer), None]);
+    }
+
+    #[test]
+    fn send_fails_once_receiver_is_gone() {
+        let (sender, receiver) = channel(8, OverflowPolicy::default(), Arc::new(Stats::new()));
+        drop(receiver);
+        assert!(!sender.send(tagged("a", 1)));
+    }
+
+    #[test]
+    fn coalesce_keeps_last_message_per_tag() {
+        let batch = vec![
+            tagged("a", 1),
+            tagged("b", 2),
+            tagged("a", 3),
+            tagged("c", 4),
+        ];
+        let latest: Vec<_> = coalesce(batch)
+            .into_iter()
+            .map(|msg| (msg.tag().to_string(), msg.value.unwrap()))
+            .collect();
+        assert_eq!(latest, pairs(&[("b", 2), ("a", 3), ("c", 4)]));
+    }
+
+    #[test]
+    fn coalesce_falls_back_to_message_type() {
+        let batch = vec![
+            OsdMessage {
+                value: Some(1),
+                ..OsdMessage::new("volume")
+            },
+            OsdMessage {
+                value: Some(2),
+                ..OsdMessage::new("brightness")
+            },
+            OsdMessage {
+                value: Some(3),
+                ..OsdMessage::new("volume")
+            },
+        ];
+        let values: Vec<_> = coalesce(batch).into_iter().map(|msg| msg.value).collect();
+        assert_eq!(values, vec![Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn coalesce_keeps_distinct_ids() {
+        let with_id = |id: &str, value| OsdMessage {
+            id: Some(id.to_string()),
+            ..tagged("a", value)
+        };
+        let batch = vec![with_id("x", 1), with_id("y", 2), with_id("x", 3)];
+        let values: Vec<_> = coalesce(batch).into_iter().map(|msg| msg.value).collect();
+        assert_eq!(values, vec![Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn control_messages_are_never_coalesced() {
+        let batch = vec![
+            OsdMessage::new(DND_MESSAGE_TYPE),
+            tagged("a", 1),
+            OsdMessage::new(DND_MESSAGE_TYPE),
+        ];
+        assert_eq!(coalesce(batch).len(), 3);
+
+        let msgs = vec![
+            OsdMessage::new(DND_MESSAGE_TYPE),
+            OsdMessage::new(DND_MESSAGE_TYPE),
+        ];
+        let stats = Arc::new(Stats::new());
+        let (sender, receiver) = channel(8, OverflowPolicy::CoalesceByTag, stats.clone());
+        for msg in msgs {
+            assert!(sender.send(msg));
+        }
+        assert_eq!(receiver.queue.drain().len(), 2);
+        assert_eq!(stats.snapshot().coalesced, 0);
+    }
+}
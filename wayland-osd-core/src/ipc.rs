@@ -0,0 +1,459 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use serde::{Deserialize, Serialize};
+
+/// Pipe of the default instance when `$XDG_RUNTIME_DIR` isn't set
+pub const PIPE_PATH: &str = "/tmp/wayland-osd.pipe";
+
+/// Socket of the default instance when `$XDG_RUNTIME_DIR` isn't set
+pub const SOCKET_PATH: &str = "/tmp/wayland-osd.sock";
+
+/// Directory for the pipes in `$XDG_RUNTIME_DIR`, only accessible by the user
+pub fn runtime_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join("wayland-osd"))
+}
+
+/// One of possibly several servers running side by side
+///
+/// Every named instance has its own pipe, config file and saved state, so
+/// e.g. one server per output or per purpose can be run from the same binaries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Instance {
+    name: Option<String>,
+}
+
+impl Instance {
+    /// The default instance for `None`, otherwise the named one
+    ///
+    /// Names are limited to ASCII letters, digits, `-` and `_`, starting with a
+    /// letter, since they end up in file names and the application id.
+    pub fn new(name: Option<String>) -> anyhow::Result<Self> {
+        if let Some(name) = &name {
+            let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                bail!("Invalid instance name `{}`", name);
+            }
+        }
+
+        Ok(Self { name })
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// `$XDG_RUNTIME_DIR/wayland-osd/osd.pipe` or `instance-<name>.pipe`, in `/tmp` without a runtime dir
+    pub fn pipe_path(&self) -> PathBuf {
+        self.runtime_path("pipe", PIPE_PATH)
+    }
+
+    /// `$XDG_RUNTIME_DIR/wayland-osd/osd.sock` or `instance-<name>.sock`, in `/tmp` without a runtime dir
+    pub fn socket_path(&self) -> PathBuf {
+        self.runtime_path("sock", SOCKET_PATH)
+    }
+
+    fn runtime_path(&self, extension: &str, fallback: &str) -> PathBuf {
+        let file_name = self.file_name("osd", extension);
+        match (runtime_dir(), &self.name) {
+            (Some(dir), _) => dir.join(file_name),
+            (None, Some(_)) => PathBuf::from(format!("/tmp/wayland-osd-{}", file_name)),
+            (None, None) => PathBuf::from(fallback),
+        }
+    }
+
+    /// Lock file held by the running server, next to its socket
+    pub fn lock_path(&self) -> PathBuf {
+        self.socket_path().with_extension("lock")
+    }
+
+    /// File name used for the instance's files, with the given extension
+    ///
+    /// Named instances get their own `instance-` prefix, so no name can pick
+    /// the files of the default instance.
+    pub fn file_name(&self, default_stem: &str, extension: &str) -> String {
+        match &self.name {
+            Some(name) => format!("instance-{}.{}", name, extension),
+            None => format!("{}.{}", default_stem, extension),
+        }
+    }
+
+    /// GApplication id, distinct per instance so they don't activate each other
+    ///
+    /// Hyphens are valid in application ids, the name is used unchanged.
+    pub fn application_id(&self) -> String {
+        match &self.name {
+            Some(name) => format!("org.wayland.osd.instance.{}", name),
+            None => "org.wayland.osd".to_string(),
+        }
+    }
+}
+
+/// Messages on the pipe and socket are separated by a NUL byte
+pub const MESSAGE_DELIMITER: u8 = 0;
+
+/// Messages larger than this are discarded by the server
+pub const MAX_MESSAGE_SIZE: usize = 8192;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// The message grew past [`MAX_MESSAGE_SIZE`] and was discarded
+    TooLarge(usize),
+    InvalidUtf8,
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::TooLarge(size) => {
+                write!(f, "Message too large ({} bytes), discarding", size)
+            }
+            FrameError::InvalidUtf8 => write!(f, "Invalid UTF-8 in message"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Why the server rejected a message
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// Too large or not UTF-8
+    Frame,
+    /// Not a valid JSON message
+    Parse,
+    /// Parsed, but missing fields or of an unknown type
+    Invalid,
+}
+
+/// Sent back over the socket, NUL-terminated like messages, for every message
+/// the server rejects
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ErrorReply {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ErrorReply {
+    pub fn new(code: ErrorCode, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    /// The reply as written to the socket, delimiter included
+    pub fn to_frame(&self) -> Vec<u8> {
+        let mut frame = serde_json::to_vec(self).expect("ErrorReply is always serializable");
+        frame.push(MESSAGE_DELIMITER);
+        frame
+    }
+}
+
+impl std::fmt::Display for ErrorReply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ErrorReply {}
+
+/// Splits a byte stream into NUL-delimited messages
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    /// Set after an oversized message, skips its remaining bytes up to the next delimiter
+    discarding: bool,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::with_capacity(4096),
+            discarding: false,
+        }
+    }
+
+    /// Feed newly read bytes, returning every message completed by them
+    pub fn push(&mut self, data: &[u8]) -> Vec<Result<String, FrameError>> {
+        let mut frames = Vec::new();
+        let mut start = 0;
+
+        for (i, &byte) in data.iter().enumerate() {
+            if byte != MESSAGE_DELIMITER {
+                continue;
+            }
+
+            if self.discarding {
+                self.discarding = false;
+            } else if !self.buffer.is_empty() || i > start {
+                self.buffer.extend_from_slice(&data[start..i]);
+
+                if self.buffer.len() > MAX_MESSAGE_SIZE {
+                    frames.push(Err(FrameError::TooLarge(self.buffer.len())));
+                } else if !self.buffer.is_empty() {
+                    frames.push(
+                        String::from_utf8(self.buffer.clone()).map_err(|_| FrameError::InvalidUtf8),
+                    );
+                }
+                self.buffer.clear();
+            }
+            start = i + 1;
+        }
+
+        if start < data.len() && !self.discarding {
+            let remaining = &data[start..];
+            if self.buffer.len() + remaining.len() > MAX_MESSAGE_SIZE {
+                frames.push(Err(FrameError::TooLarge(
+                    self.buffer.len() + remaining.len(),
+                )));
+                self.buffer.clear();
+                self.discarding = true;
+            } else {
+                self.buffer.extend_from_slice(remaining);
+            }
+        }
+
+        frames
+    }
+}
+
+/// Open the pipe for writing without blocking when no server is reading it
+///
+/// Fails with `ENXIO` when the pipe exists but nobody has it open for reading,
+/// e.g. after the server crashed.
+fn open_pipe(instance: &Instance) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .write(true)
+        .custom_flags(OFlag::O_NONBLOCK.bits())
+        .open(instance.pipe_path())?;
+
+    // Only the open needs to be non-blocking, writes should wait for room in the pipe
+    fcntl(file.as_raw_fd(), FcntlArg::F_SETFL(OFlag::empty()))?;
+    Ok(file)
+}
+
+/// Whether a server is currently listening on the socket or reading from the pipe
+pub fn server_running(instance: &Instance) -> bool {
+    UnixStream::connect(instance.socket_path()).is_ok() || open_pipe(instance).is_ok()
+}
+
+/// Poll until a server is reading from the pipe, returns whether one showed up in time
+pub fn wait_for_server(instance: &Instance, timeout: Duration) -> bool {
+    let start = Instant::now();
+
+    while start.elapsed() < timeout {
+        if server_running(instance) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    false
+}
+
+/// Send a single message over the server's socket
+///
+/// Every client gets its own connection, so concurrent writers can't interleave.
+fn send_to_socket(instance: &Instance, message: &str) -> io::Result<()> {
+    let mut stream = UnixStream::connect(instance.socket_path())?;
+    stream.write_all(message.as_bytes())?;
+    stream.write_all(&[MESSAGE_DELIMITER])?;
+    stream.shutdown(std::net::Shutdown::Write)
+}
+
+/// Send a single message over the server's socket and wait until it was handled,
+/// returning why the server rejected it if it did
+///
+/// The server closes the connection once it has read everything we sent,
+/// after replying to each rejected message.
+pub fn send_and_wait(instance: &Instance, message: &str) -> anyhow::Result<Vec<ErrorReply>> {
+    let mut stream = UnixStream::connect(instance.socket_path())
+        .context("Failed to connect to the server's socket")?;
+    stream.write_all(message.as_bytes())?;
+    stream.write_all(&[MESSAGE_DELIMITER])?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut data = Vec::new();
+    stream
+        .read_to_end(&mut data)
+        .context("Failed to read the server's reply")?;
+
+    FrameDecoder::new()
+        .push(&data)
+        .into_iter()
+        .map(|frame| {
+            let frame = frame?;
+            serde_json::from_str(&frame).context("Invalid reply from the server")
+        })
+        .collect()
+}
+
+/// Send a single message to the server, over its socket if possible and
+/// otherwise its pipe, retrying while it isn't available yet
+pub fn send_message(instance: &Instance, message: &str) -> anyhow::Result<()> {
+    if send_to_socket(instance, message).is_ok() {
+        return Ok(());
+    }
+
+    // Try to open pipe multiple times
+    let mut attempts = 0;
+    let max_attempts = 5;
+    let mut last_error = None;
+
+    while attempts < max_attempts {
+        match open_pipe(instance) {
+            Ok(mut file) => {
+                // Create a single buffer with message and separator to ensure atomic write
+                let mut buffer = message.as_bytes().to_vec();
+                buffer.push(MESSAGE_DELIMITER);
+                file.write_all(&buffer)
+                    .context("Failed to write message to OSD pipe")?;
+                // Ensure the write is flushed
+                file.flush()
+                    .context("Failed to flush message to OSD pipe")?;
+                // Add a small delay to prevent overwhelming the server
+                std::thread::sleep(Duration::from_millis(5));
+                return Ok(());
+            }
+            Err(e) => {
+                last_error = Some(e);
+                attempts += 1;
+                if attempts < max_attempts {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+            }
+        }
+    }
+
+    Err(last_error.unwrap().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok(frames: &[&str]) -> Vec<Result<String, FrameError>> {
+        frames.iter().map(|frame| Ok(frame.to_string())).collect()
+    }
+
+    #[test]
+    fn splits_frames() {
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decoder.push(b"one\0two\0"), ok(&["one", "two"]));
+    }
+
+    #[test]
+    fn joins_frames_split_across_reads() {
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decoder.push(b"{\"type\":"), ok(&[]));
+        assert_eq!(
+            decoder.push(b"\"text\"}\0{\"ty"),
+            ok(&["{\"type\":\"text\"}"])
+        );
+        assert_eq!(
+            decoder.push(b"pe\":\"volume\"}\0"),
+            ok(&["{\"type\":\"volume\"}"])
+        );
+    }
+
+    #[test]
+    fn skips_empty_frames() {
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decoder.push(b"\0\0one\0\0"), ok(&["one"]));
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(
+            decoder.push(b"\xff\xfe\0ok\0"),
+            vec![Err(FrameError::InvalidUtf8), Ok("ok".to_string())]
+        );
+    }
+
+    #[test]
+    fn discards_oversized_frame_across_reads() {
+        let mut decoder = FrameDecoder::new();
+        let chunk = vec![b'x'; MAX_MESSAGE_SIZE];
+
+        assert_eq!(decoder.push(&chunk), ok(&[]));
+        assert_eq!(
+            decoder.push(b"xx"),
+            vec![Err(FrameError::TooLarge(MAX_MESSAGE_SIZE + 2))]
+        );
+        // The rest of it is skipped without further errors
+        assert_eq!(decoder.push(&chunk), ok(&[]));
+        assert_eq!(decoder.push(b"xx\0ok\0"), ok(&["ok"]));
+    }
+
+    #[test]
+    fn discards_oversized_frame_in_one_read() {
+        let mut decoder = FrameDecoder::new();
+        let mut data = vec![b'x'; MAX_MESSAGE_SIZE + 1];
+        data.extend_from_slice(b"\0ok\0");
+
+        assert_eq!(
+            decoder.push(&data),
+            vec![
+                Err(FrameError::TooLarge(MAX_MESSAGE_SIZE + 1)),
+                Ok("ok".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn instance_names() {
+        assert!(Instance::new(Some("left-1".to_string())).is_ok());
+        assert!(Instance::new(Some("1left".to_string())).is_err());
+        assert!(Instance::new(Some("../etc".to_string())).is_err());
+        assert_eq!(
+            Instance::new(Some("left-1".to_string()))
+                .unwrap()
+                .application_id(),
+            "org.wayland.osd.instance.left-1"
+        );
+    }
+
+    #[test]
+    fn instance_names_do_not_collide() {
+        let named = |name: &str| Instance::new(Some(name.to_string())).unwrap();
+        let default = Instance::default();
+
+        assert_ne!(named("osd").pipe_path(), default.pipe_path());
+        assert_ne!(named("osd").socket_path(), default.socket_path());
+        assert_ne!(named("osd").lock_path(), default.lock_path());
+        assert_ne!(
+            named("state").file_name("state", "json"),
+            default.file_name("state", "json")
+        );
+        assert_ne!(
+            named("config").file_name("config", "toml"),
+            default.file_name("config", "toml")
+        );
+        assert_ne!(named("a-b").application_id(), named("a_b").application_id());
+        assert_ne!(named("preview").application_id(), "org.wayland.osd.preview");
+    }
+
+    #[test]
+    fn error_reply_frame() {
+        let reply = ErrorReply::new(ErrorCode::Parse, "bad");
+        assert_eq!(
+            reply.to_frame(),
+            b"{\"code\":\"parse\",\"message\":\"bad\"}\0".to_vec()
+        );
+    }
+}
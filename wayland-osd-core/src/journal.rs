@@ -0,0 +1,216 @@
+use std::io;
+use std::os::unix::net::UnixDatagram;
+
+use log::kv::{self, VisitSource};
+use log::{debug, warn, Level, LevelFilter, Log, Metadata, Record};
+use serde::Deserialize;
+
+use crate::hooks::HookEvent;
+use crate::message::OsdMessage;
+
+/// Socket of journald's native protocol
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// `SYSLOG_IDENTIFIER` of every entry, for `journalctl -t`
+pub const SYSLOG_IDENTIFIER: &str = "wayland-osd";
+
+/// Informational, see syslog(3)
+const PRIORITY_INFO: &str = "6";
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct JournalConfig {
+    /// Record shown messages and show/hide events in the systemd journal
+    pub events: bool,
+}
+
+/// Writes OSD events to the systemd journal as structured entries
+#[derive(Debug)]
+pub struct Journal {
+    socket: UnixDatagram,
+}
+
+impl Journal {
+    pub fn connect() -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(JOURNAL_SOCKET)?;
+        debug!("Connected to the journal at {}", JOURNAL_SOCKET);
+        Ok(Self { socket })
+    }
+
+    /// Record an event, with the fields of the message that caused it
+    ///
+    /// Messages from socket clients also name the process and user that sent them.
+    pub fn record(&self, event: HookEvent, msg: Option<&OsdMessage>) {
+        let event = match event {
+            HookEvent::Show => "show",
+            HookEvent::Hide => "hide",
+            HookEvent::Message => "message",
+        };
+
+        let text = match msg {
+            Some(msg) => format!("OSD {} {}", event, msg.message_type),
+            None => format!("OSD {}", event),
+        };
+
+        let mut fields = vec![
+            ("MESSAGE", text),
+            ("PRIORITY", PRIORITY_INFO.to_string()),
+            ("SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER.to_string()),
+            ("OSD_EVENT", event.to_string()),
+        ];
+
+        if let Some(msg) = msg {
+            fields.push(("MESSAGE_TYPE", msg.message_type.clone()));
+            fields.push(("TAG", msg.tag().to_string()));
+            if let Some(id) = &msg.id {
+                fields.push(("OSD_ID", id.clone()));
+            }
+            if let Some(value) = msg.value {
+                fields.push(("VALUE", value.to_string()));
+            }
+            if let Some(max_value) = msg.max_value {
+                fields.push(("MAX_VALUE", max_value.to_string()));
+            }
+            if let Some(text) = &msg.text {
+                fields.push(("TEXT", text.clone()));
+            }
+            if let Some(device_name) = &msg.device_name {
+                fields.push(("DEVICE_NAME", device_name.clone()));
+            }
+        }
+
+        // Only socket messages know their sender, the pipe has no way to tell
+        if let Some(peer) = msg.and_then(|msg| msg.peer) {
+            fields.push(("CLIENT_PID", peer.pid.to_string()));
+            fields.push(("CLIENT_UID", peer.uid.to_string()));
+        }
+
+        if let Err(e) = self.socket.send(&encode(&fields)) {
+            warn!("Failed to write to the journal: {}", e);
+        }
+    }
+}
+
+/// Sends log records to the journal instead of stderr
+///
+/// Key-values of a record become fields of the entry, so e.g.
+/// `debug!(message_type = "volume"; ...)` can be found with `MESSAGE_TYPE=volume`.
+#[derive(Debug)]
+pub struct JournalLogger {
+    journal: Journal,
+    level: LevelFilter,
+}
+
+impl JournalLogger {
+    /// Install as the global logger, for records up to `level`
+    pub fn init(level: LevelFilter) -> io::Result<()> {
+        let logger = Self {
+            journal: Journal::connect()?,
+            level,
+        };
+        log::set_boxed_logger(Box::new(logger)).map_err(io::Error::other)?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl Log for JournalLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // See syslog(3)
+        let priority = match record.level() {
+            Level::Error => "3",
+            Level::Warn => "4",
+            Level::Info => "6",
+            Level::Debug | Level::Trace => "7",
+        };
+
+        let mut fields = vec![
+            ("MESSAGE".to_string(), record.args().to_string()),
+            ("PRIORITY".to_string(), priority.to_string()),
+            (
+                "SYSLOG_IDENTIFIER".to_string(),
+                SYSLOG_IDENTIFIER.to_string(),
+            ),
+            ("CODE_MODULE".to_string(), record.target().to_string()),
+        ];
+        if let Some(file) = record.file() {
+            fields.push(("CODE_FILE".to_string(), file.to_string()));
+        }
+        if let Some(line) = record.line() {
+            fields.push(("CODE_LINE".to_string(), line.to_string()));
+        }
+        let _ = record.key_values().visit(&mut FieldVisitor(&mut fields));
+
+        // Logging the failure would only end up here again
+        if let Err(e) = self.journal.socket.send(&encode(&fields)) {
+            eprintln!("Failed to write to the journal: {}", e);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Collects key-values as journal fields with upper-case names
+struct FieldVisitor<'a>(&'a mut Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for FieldVisitor<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0
+            .push((key.as_str().to_ascii_uppercase(), value.to_string()));
+        Ok(())
+    }
+}
+
+/// Serialize fields in journald's native format
+///
+/// Values containing a newline are sent length-prefixed instead of after `=`.
+fn encode(fields: &[(impl AsRef<str>, String)]) -> Vec<u8> {
+    let mut entry = Vec::new();
+
+    for (key, value) in fields {
+        entry.extend_from_slice(key.as_ref().as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_plain_fields() {
+        let fields = [
+            ("MESSAGE", "OSD show volume".to_string()),
+            ("VALUE", "40".to_string()),
+        ];
+        assert_eq!(encode(&fields), b"MESSAGE=OSD show volume\nVALUE=40\n");
+    }
+
+    #[test]
+    fn length_prefixes_values_with_newlines() {
+        let fields = [("TEXT", "two\nlines".to_string())];
+
+        let mut expected = b"TEXT\n".to_vec();
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"two\nlines\n");
+        assert_eq!(encode(&fields), expected);
+    }
+}
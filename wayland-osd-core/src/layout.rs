@@ -0,0 +1,200 @@
+use serde::Deserialize;
+
+/// A part of the OSD that can be placed in a layout
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutElement {
+    Icon,
+    /// The progress bar, including its marker line
+    Bar,
+    /// Main text of text messages
+    Label,
+    /// Small secondary text, e.g. the audio device name
+    Caption,
+    /// The bar's value as text, when enabled in `[bar]`
+    Value,
+    /// Cover image of media messages
+    Artwork,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutAlign {
+    Start,
+    #[default]
+    Center,
+    End,
+    /// Stretch the row's items to the full width
+    Fill,
+}
+
+/// An element with an optional size, written either as `"bar"` or `{ element = "bar", width = 200 }`
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum LayoutItem {
+    Element(LayoutElement),
+    Sized {
+        element: LayoutElement,
+        /// Requested size in pixels, unset keeps the natural size
+        width: Option<i32>,
+        height: Option<i32>,
+    },
+}
+
+impl LayoutItem {
+    pub fn element(&self) -> LayoutElement {
+        match self {
+            LayoutItem::Element(element) | LayoutItem::Sized { element, .. } => *element,
+        }
+    }
+
+    /// Requested `(width, height)`, -1 for the natural size like GTK expects
+    pub fn size(&self) -> (i32, i32) {
+        match self {
+            LayoutItem::Element(_) => (-1, -1),
+            LayoutItem::Sized { width, height, .. } => (width.unwrap_or(-1), height.unwrap_or(-1)),
+        }
+    }
+}
+
+/// Elements placed next to each other
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct LayoutRow {
+    pub items: Vec<LayoutItem>,
+    pub align: LayoutAlign,
+    /// Space between the items in pixels
+    pub spacing: i32,
+}
+
+impl Default for LayoutRow {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            align: LayoutAlign::default(),
+            spacing: 10,
+        }
+    }
+}
+
+/// Visual structure of the OSD as rows stacked from top to bottom
+///
+/// Elements a view doesn't use stay hidden, so one layout can serve several
+/// message types. Elements left out of a layout are never shown.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Layout {
+    pub rows: Vec<LayoutRow>,
+    /// Space between the rows in pixels
+    pub spacing: i32,
+}
+
+fn row(items: &[LayoutElement]) -> LayoutRow {
+    LayoutRow {
+        items: items.iter().copied().map(LayoutItem::Element).collect(),
+        ..LayoutRow::default()
+    }
+}
+
+impl Layout {
+    /// The layout of message types that aren't configured
+    pub fn default_for(message_type: &str) -> Self {
+        match message_type {
+            "media" => Self::media(),
+            _ => Self::default(),
+        }
+    }
+
+    /// A card with the cover on top, the title next to the playback icon and the artist below
+    fn media() -> Self {
+        Self {
+            rows: vec![
+                LayoutRow {
+                    items: vec![LayoutItem::Sized {
+                        element: LayoutElement::Artwork,
+                        width: Some(160),
+                        height: Some(160),
+                    }],
+                    ..LayoutRow::default()
+                },
+                row(&[LayoutElement::Icon, LayoutElement::Label]),
+                row(&[LayoutElement::Caption]),
+            ],
+            spacing: 10,
+        }
+    }
+}
+
+impl Default for Layout {
+    /// Icon, bar and value side by side, with the caption and label below
+    fn default() -> Self {
+        Self {
+            rows: vec![
+                row(&[
+                    LayoutElement::Icon,
+                    LayoutElement::Bar,
+                    LayoutElement::Value,
+                ]),
+                row(&[LayoutElement::Caption]),
+                row(&[LayoutElement::Label]),
+            ],
+            spacing: 10,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_and_sized_items() {
+        let layout: Layout = toml::from_str(
+            r#"
+            spacing = 4
+            rows = [
+                { items = ["caption"], align = "start" },
+                { items = ["icon", { element = "bar", width = 300 }], align = "fill", spacing = 8 },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(layout.spacing, 4);
+        assert_eq!(layout.rows[0].align, LayoutAlign::Start);
+        assert_eq!(layout.rows[0].spacing, 10);
+        assert_eq!(
+            layout.rows[0].items,
+            [LayoutItem::Element(LayoutElement::Caption)]
+        );
+
+        let bar = &layout.rows[1].items[1];
+        assert_eq!(bar.element(), LayoutElement::Bar);
+        assert_eq!(bar.size(), (300, -1));
+        assert_eq!(layout.rows[1].items[0].size(), (-1, -1));
+    }
+
+    #[test]
+    fn rejects_unknown_element() {
+        assert!(toml::from_str::<Layout>(r#"rows = [{ items = ["clock"] }]"#).is_err());
+    }
+
+    #[test]
+    fn default_shows_every_element() {
+        let elements: Vec<_> = Layout::default()
+            .rows
+            .iter()
+            .flat_map(|row| row.items.iter().map(LayoutItem::element))
+            .collect();
+        assert_eq!(
+            elements,
+            [
+                LayoutElement::Icon,
+                LayoutElement::Bar,
+                LayoutElement::Value,
+                LayoutElement::Caption,
+                LayoutElement::Label
+            ]
+        );
+    }
+}
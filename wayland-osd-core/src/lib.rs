@@ -0,0 +1,30 @@
+//! Display-independent logic shared by the wayland-osd server, client and monitors.
+//!
+//! Frontends feed raw bytes into [`ipc::FrameDecoder`], parse them into
+//! [`OsdMessage`]s and hand those to [`OsdState`], which decides what to show
+//! ([`View`]) and for how long. Rendering the result is left to the frontend.
+
+pub mod animation;
+pub mod capture;
+pub mod config;
+pub mod demo;
+pub mod hooks;
+pub mod ingest;
+pub mod ipc;
+pub mod journal;
+pub mod layout;
+pub mod message;
+pub mod persist;
+pub mod router;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod state;
+pub mod stats;
+
+pub use config::Config;
+pub use message::{MessageError, OsdMessage};
+pub use router::{
+    CustomIcon, IconId, MediaView, MessageHandler, ProgressView, Router, TextView, View,
+};
+pub use state::{OsdState, Update};
+pub use stats::Stats;
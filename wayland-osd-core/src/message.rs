@@ -0,0 +1,412 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Control message switching the server to the profile named in `text`
+pub const PROFILE_MESSAGE_TYPE: &str = "profile";
+
+/// Control message making the server re-read its config file and stylesheet
+pub const RELOAD_MESSAGE_TYPE: &str = "reload";
+
+/// Control message hiding the OSD right away
+pub const DISMISS_MESSAGE_TYPE: &str = "dismiss";
+
+/// Control message turning do-not-disturb `on` or `off` as given in `text`,
+/// or toggling it without `text`
+pub const DND_MESSAGE_TYPE: &str = "dnd";
+
+/// Version of the message format, raised whenever types or fields change
+///
+/// Messages without a version are from clients predating it, which speak version 1.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A single request sent by a client to the server
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct OsdMessage {
+    #[serde(rename = "type")]
+    pub message_type: String,
+    /// [`PROTOCOL_VERSION`] of the sender
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_value: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Render the text of a `text` message as Pango markup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub markup: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub muted: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_class: Option<String>,
+    /// Which lock key a `lock` message is about: `caps`, `num` or `scroll`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Whether the lock of a `lock` message is on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Draw lines across the bar at these values, e.g. the previous volume
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marker: Option<Markers>,
+    /// Draw the bar as this many blocks, overriding the configured `segments`, 0 for a continuous bar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segments: Option<u32>,
+    /// Overrides the configured `normal_max` of the bar for this message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normal_max: Option<i32>,
+    /// Whether the battery of a `battery` message is `charging`, `discharging` or `full`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Draws attention to the message with a red bar and a longer timeout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub critical: Option<bool>,
+    /// Whether the device of a `bluetooth` message is connected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connected: Option<bool>,
+    /// How a `network` message is connected: `wireless`, `wired` or `disconnected`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection: Option<String>,
+    /// Name of the Wi-Fi network of a `network` message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssid: Option<String>,
+    /// Track title of a `media` message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    /// Whether the player of a `media` message is `playing`, `paused` or `stopped`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playback: Option<String>,
+    /// Cover of a `media` message, an absolute path or a URI like MPRIS `mpris:artUrl`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artwork: Option<String>,
+    /// How long a `countdown` message counts down
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// What a `progress` or `countdown` message is about, shown under the bar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Extra CSS class of a `custom` message, for styling it from the stylesheet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub css_class: Option<String>,
+    /// Extra CSS class of the window, for styling this message from the stylesheet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    /// Icon theme name or absolute path to an SVG or PNG, shown instead of the built-in icon
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// Connector name of the output to show this message on, e.g. `DP-1`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// How long to show this message, defaults to the configured timeout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Keep the message up until it is dismissed or replaced, ignoring the timeout
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sticky: Option<bool>,
+    /// Groups messages that update the same thing, defaults to the message type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Identifies a popup: messages with the id of the one on screen update it
+    /// in place, a different id starts a new one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Messages only replace one on screen with the same or a lower priority, defaults to 0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// The socket client that sent the message, set by the server and never part of the JSON
+    #[serde(skip)]
+    pub peer: Option<Peer>,
+}
+
+/// Credentials of the process on the other end of the socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peer {
+    pub pid: i32,
+    pub uid: u32,
+}
+
+/// One or several values, written as `60` or `[60, 85]`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Markers {
+    One(i32),
+    Many(Vec<i32>),
+}
+
+impl Markers {
+    pub fn into_vec(self) -> Vec<i32> {
+        match self {
+            Markers::One(value) => vec![value],
+            Markers::Many(values) => values,
+        }
+    }
+}
+
+impl OsdMessage {
+    /// A message of the given type, stamped with the current protocol version
+    pub fn new(message_type: &str) -> Self {
+        Self {
+            message_type: message_type.to_string(),
+            version: Some(PROTOCOL_VERSION),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the sender speaks a newer protocol than this build, so parts of
+    /// the message may have been ignored
+    pub fn is_newer_protocol(&self) -> bool {
+        self.version
+            .is_some_and(|version| version > PROTOCOL_VERSION)
+    }
+
+    /// Whether the message controls the server rather than being shown
+    pub fn is_control(&self) -> bool {
+        [
+            PROFILE_MESSAGE_TYPE,
+            RELOAD_MESSAGE_TYPE,
+            DISMISS_MESSAGE_TYPE,
+            DND_MESSAGE_TYPE,
+        ]
+        .contains(&self.message_type.as_str())
+    }
+
+    /// How long the countdown of a `countdown` message runs, `None` for other messages
+    pub fn countdown(&self) -> Option<Duration> {
+        if self.message_type != "countdown" {
+            return None;
+        }
+        self.duration_ms.map(Duration::from_millis)
+    }
+
+    /// The tag used to group messages, falling back to the message type
+    pub fn tag(&self) -> &str {
+        self.tag.as_deref().unwrap_or(&self.message_type)
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority.unwrap_or(0)
+    }
+
+    pub fn critical(&self) -> bool {
+        self.critical.unwrap_or(false)
+    }
+
+    pub fn sticky(&self) -> bool {
+        self.sticky.unwrap_or(false)
+    }
+
+    /// CSS classes of the window while the message is shown: `osd-<type>` and its `style`
+    pub fn window_classes(&self) -> Vec<String> {
+        std::iter::once(format!("osd-{}", self.message_type))
+            .chain(self.style.clone())
+            .collect()
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Like [`from_json`](Self::from_json), but rejects fields the message
+    /// doesn't have and names the field that failed to parse
+    pub fn from_json_strict(json: &str) -> Result<Self, ParseError> {
+        let fields: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(json).map_err(ParseError::Json)?;
+
+        // Parse every field on its own, next to a placeholder type, so an
+        // error can be pinned to it
+        for (field, value) in &fields {
+            let mut single = serde_json::Map::new();
+            single.insert("type".to_string(), "".into());
+            single.insert(field.clone(), value.clone());
+            let msg: OsdMessage = serde_json::from_value(single.into()).map_err(|error| {
+                ParseError::InvalidField {
+                    field: field.clone(),
+                    error,
+                }
+            })?;
+
+            // Unknown fields are skipped while parsing, so they don't come back out
+            let known = serde_json::to_value(&msg)
+                .ok()
+                .is_some_and(|parsed| parsed.get(field).is_some());
+            if !known && !value.is_null() {
+                return Err(ParseError::UnknownField(field.clone()));
+            }
+        }
+
+        serde_json::from_value(fields.into()).map_err(ParseError::Json)
+    }
+
+    /// Whether both messages show the same, whoever sent them
+    pub fn shows_same(&self, other: &OsdMessage) -> bool {
+        let anonymous = |msg: &OsdMessage| OsdMessage {
+            peer: None,
+            ..msg.clone()
+        };
+        anonymous(self) == anonymous(other)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("OsdMessage is always serializable")
+    }
+}
+
+/// Why a message was rejected by [`OsdMessage::from_json_strict`]
+#[derive(Debug)]
+pub enum ParseError {
+    /// Not a JSON object, or missing its type
+    Json(serde_json::Error),
+    UnknownField(String),
+    InvalidField {
+        field: String,
+        error: serde_json::Error,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Json(error) => write!(f, "{}", error),
+            ParseError::UnknownField(field) => write!(f, "unknown field `{}`", field),
+            ParseError::InvalidField { field, error } => {
+                write!(f, "invalid `{}`: {}", field, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Why a parsed message could not be turned into something to display
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageError {
+    /// A field required by the message type was not set
+    MissingField {
+        message_type: String,
+        field: &'static str,
+    },
+    /// A field has a value the message type doesn't know
+    InvalidValue {
+        message_type: String,
+        field: &'static str,
+        value: String,
+    },
+    UnknownType(String),
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::MissingField {
+                message_type,
+                field,
+            } => write!(f, "{} message is missing `{}`", message_type, field),
+            MessageError::InvalidValue {
+                message_type,
+                field,
+                value,
+            } => write!(
+                f,
+                "{} message has an invalid `{}`: {}",
+                message_type, field, value
+            ),
+            MessageError::UnknownType(message_type) => {
+                write!(f, "unknown message type: {}", message_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strict_error(json: &str) -> String {
+        OsdMessage::from_json_strict(json)
+            .expect_err("message should be rejected")
+            .to_string()
+    }
+
+    #[test]
+    fn strict_accepts_valid_message() {
+        let msg = OsdMessage::from_json_strict(
+            r#"{"type":"volume","value":40,"max_value":100,"muted":null}"#,
+        )
+        .unwrap();
+        assert_eq!(msg.value, Some(40));
+        assert_eq!(msg.muted, None);
+    }
+
+    #[test]
+    fn strict_names_invalid_field() {
+        let error = strict_error(r#"{"type":"volume","value":"40","max_value":100}"#);
+        assert!(error.starts_with("invalid `value`: "), "{}", error);
+    }
+
+    #[test]
+    fn strict_names_unknown_field() {
+        assert_eq!(
+            strict_error(r#"{"type":"volume","valeu":40}"#),
+            "unknown field `valeu`"
+        );
+    }
+
+    #[test]
+    fn strict_rejects_sender_from_json() {
+        assert_eq!(
+            strict_error(r#"{"type":"text","text":"hi","peer":{"pid":1,"uid":0}}"#),
+            "unknown field `peer`"
+        );
+    }
+
+    #[test]
+    fn strict_requires_type() {
+        assert!(matches!(
+            OsdMessage::from_json_strict(r#"{"value":40}"#),
+            Err(ParseError::Json(_))
+        ));
+        assert!(matches!(
+            OsdMessage::from_json_strict("[1, 2]"),
+            Err(ParseError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn lenient_ignores_unknown_field() {
+        let msg = OsdMessage::from_json(r#"{"type":"volume","valeu":40}"#).unwrap();
+        assert_eq!(msg.value, None);
+    }
+
+    #[test]
+    fn json_round_trip_leaves_out_unset_fields() {
+        let msg = OsdMessage {
+            text: Some("hi".to_string()),
+            peer: Some(Peer { pid: 1, uid: 1000 }),
+            ..OsdMessage::new("text")
+        };
+        assert_eq!(
+            msg.to_json(),
+            format!(
+                r#"{{"type":"text","version":{},"text":"hi"}}"#,
+                PROTOCOL_VERSION
+            )
+        );
+        assert!(OsdMessage::from_json(&msg.to_json())
+            .unwrap()
+            .shows_same(&msg));
+    }
+
+    #[test]
+    fn control_messages() {
+        assert!(OsdMessage::new(DISMISS_MESSAGE_TYPE).is_control());
+        assert!(!OsdMessage::new("volume").is_control());
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::ipc::Instance;
+use crate::message::OsdMessage;
+use crate::state::History;
+
+/// Runtime state kept across restarts of the server
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PersistedState {
+    /// Profile switched to at runtime
+    pub profile: Option<String>,
+    /// Whether do-not-disturb was on
+    pub dnd: bool,
+    /// The last message shown for every tag
+    pub last_messages: BTreeMap<String, OsdMessage>,
+    /// The most recently shown messages
+    pub history: History,
+}
+
+impl PersistedState {
+    /// Location of the state file, `$XDG_STATE_HOME/wayland-osd/state.json`
+    ///
+    /// Named instances use `instance-<name>.json` instead.
+    pub fn path(instance: &Instance) -> Option<PathBuf> {
+        let state_dir = std::env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+            })?;
+
+        Some(
+            state_dir
+                .join("wayland-osd")
+                .join(instance.file_name("state", "json")),
+        )
+    }
+
+    /// Read the state saved by the last run, empty if there is none
+    pub fn load(instance: &Instance) -> anyhow::Result<Self> {
+        let Some(path) = Self::path(instance).filter(|path| path.exists()) else {
+            debug!("No saved state, starting fresh");
+            return Ok(Self::default());
+        };
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read state file {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse state file {}", path.display()))
+    }
+
+    pub fn save(&self, instance: &Instance) -> anyhow::Result<()> {
+        let path = Self::path(instance).context("Could not determine state directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        // Write to a temporary file first, so a crash can't leave a truncated file behind
+        let temp = path.with_extension("json.tmp");
+        let contents = serde_json::to_string_pretty(self).context("Failed to serialize state")?;
+        fs::write(&temp, contents)
+            .with_context(|| format!("Failed to write state file {}", temp.display()))?;
+        fs::rename(&temp, &path)
+            .with_context(|| format!("Failed to write state file {}", path.display()))?;
+
+        debug!("Saved state to {}", path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_missing_from_older_files_default() {
+        let state: PersistedState = serde_json::from_str(r#"{"profile":"work"}"#).unwrap();
+        assert_eq!(state.profile.as_deref(), Some("work"));
+        assert!(state.last_messages.is_empty());
+        assert_eq!(state.history.messages().count(), 0);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = std::env::temp_dir().join(format!("wayland-osd-state-{}", std::process::id()));
+        // The only test reading the state directory, so changing it doesn't race
+        std::env::set_var("XDG_STATE_HOME", &dir);
+        let instance = Instance::new(None).unwrap();
+
+        let mut state = PersistedState {
+            profile: Some("presentation".to_string()),
+            ..PersistedState::default()
+        };
+        state.last_messages.insert(
+            "volume".to_string(),
+            OsdMessage {
+                value: Some(40),
+                ..OsdMessage::new("volume")
+            },
+        );
+        state.history.push(OsdMessage::new("dismiss"));
+        state.save(&instance).unwrap();
+
+        let loaded = PersistedState::load(&instance).unwrap();
+        assert_eq!(loaded.profile.as_deref(), Some("presentation"));
+        assert_eq!(loaded.last_messages["volume"].value, Some(40));
+        assert_eq!(loaded.history, state.history);
+        assert!(!PersistedState::path(&instance)
+            .unwrap()
+            .with_extension("json.tmp")
+            .exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1200 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::config::{Config, DeviceIcon};
+use crate::message::{Markers, MessageError, OsdMessage};
+
+/// Built-in icons a view can ask the frontend to display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IconId {
+    VolumeHigh,
+    VolumeMedium,
+    VolumeLow,
+    VolumeMuted,
+    VolumeOveramplified,
+    BrightnessHigh,
+    BrightnessMedium,
+    BrightnessLow,
+    Headphones,
+    Headset,
+    Tv,
+    UsbDac,
+    Bluetooth,
+    CapsLock,
+    NumLock,
+    ScrollLock,
+    Keyboard,
+    MicHigh,
+    MicMedium,
+    MicLow,
+    MicMuted,
+    BatteryFull,
+    BatteryCharging,
+    BatteryDischarging,
+    MediaPlaying,
+    MediaPaused,
+    MediaStopped,
+    NetworkWirelessExcellent,
+    NetworkWirelessGood,
+    NetworkWirelessOk,
+    NetworkWirelessWeak,
+    NetworkWired,
+    NetworkOffline,
+    BluetoothDisconnected,
+    PowerPerformance,
+    PowerBalanced,
+    PowerSaver,
+}
+
+/// An icon a message asked for, shown instead of the built-in one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CustomIcon {
+    /// Looked up in the icon theme, e.g. `network-wireless-symbolic`
+    Named(String),
+    /// An SVG or PNG file
+    File(PathBuf),
+}
+
+impl CustomIcon {
+    /// An absolute path is a file, anything else an icon name
+    pub fn parse(icon: &str) -> Self {
+        if Path::new(icon).is_absolute() {
+            CustomIcon::File(PathBuf::from(icon))
+        } else {
+            CustomIcon::Named(icon.to_string())
+        }
+    }
+}
+
+/// An icon next to a progress bar, e.g. volume or brightness
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressView {
+    /// Built-in icon next to the bar, if any
+    pub icon: Option<IconId>,
+    /// Fill of the bar, may exceed 1.0 when overamplified
+    pub fraction: f64,
+    pub overamplified: bool,
+    /// Position of the 100% marker line as a fraction of the bar width
+    pub marker: Option<f64>,
+    /// Further lines the message asked for as fractions of the bar width, filled in by the router
+    pub markers: Vec<f64>,
+    /// Positions of the configured tick marks as fractions of the bar width, filled in by the router
+    pub ticks: Vec<f64>,
+    /// Draw the bar as this many blocks instead of a continuous fill, filled in by the router
+    pub segments: Option<u32>,
+    /// Dims the bar and value so a muted device stands out
+    pub muted: bool,
+    pub device_label: Option<String>,
+    /// Fill color picked by the configured thresholds, filled in by the router
+    pub color: Option<String>,
+    /// Replaces `icon` when the message has an `icon`, filled in by the router
+    pub custom_icon: Option<CustomIcon>,
+    /// The value written out as configured in `[bar]`, filled in by the router
+    /// unless the message type has its own
+    pub value_label: Option<String>,
+    /// Extra CSS class of the bar, e.g. `mic` to tell microphone levels apart
+    pub css_class: Option<String>,
+}
+
+impl ProgressView {
+    /// The value in percent of the full scale, which is where the marker sits if there is one
+    pub fn percent(&self) -> f64 {
+        self.fraction / self.marker.unwrap_or(1.0) * 100.0
+    }
+
+    /// Shrink the bar of a countdown to `remaining` of `total` and write it out as `mm:ss`
+    pub fn set_countdown(&mut self, remaining: Duration, total: Duration) {
+        self.fraction = if total.is_zero() {
+            0.0
+        } else {
+            remaining.as_secs_f64() / total.as_secs_f64()
+        };
+
+        // Rounded up, so it reads 0:00 only once it ran out
+        let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+        self.value_label = Some(if seconds >= 3600 {
+            format!(
+                "{}:{:02}:{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            )
+        } else {
+            format!("{}:{:02}", seconds / 60, seconds % 60)
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextView {
+    pub text: String,
+    /// Shown next to the text, if any
+    pub icon: Option<IconId>,
+    /// Replaces `icon` when the message has an `icon`, filled in by the router
+    pub custom_icon: Option<CustomIcon>,
+    /// Extra CSS class of the label
+    pub css_class: Option<String>,
+    /// Render `text` as Pango markup instead of plain text
+    pub markup: bool,
+}
+
+/// A card for the track a media player switched to
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaView {
+    pub title: String,
+    pub artist: Option<String>,
+    /// Playback state of the player
+    pub icon: IconId,
+    /// Cover image, an absolute path or a URI
+    pub artwork: Option<String>,
+    /// Replaces `icon` when the message has an `icon`, filled in by the router
+    pub custom_icon: Option<CustomIcon>,
+}
+
+/// Everything the frontend needs to render a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum View {
+    Progress(ProgressView),
+    Text(TextView),
+    Media(MediaView),
+}
+
+impl View {
+    /// What screen readers say when the view is shown, e.g. "Volume 60 percent"
+    ///
+    /// Markup of text views is left in, the frontend knows how to strip it.
+    pub fn announcement(&self, message_type: &str) -> String {
+        match self {
+            View::Progress(progress) => {
+                // Custom and progress bars are named by their text
+                let (name, device) = match &progress.device_label {
+                    Some(text) if matches!(message_type, "custom" | "progress" | "countdown") => {
+                        (text.clone(), None)
+                    }
+                    device => (spoken_name(message_type), device.as_ref()),
+                };
+                let mut announcement =
+                    if matches!(progress.icon, Some(IconId::VolumeMuted | IconId::MicMuted)) {
+                        format!("{} muted", name)
+                    } else {
+                        format!("{} {:.0} percent", name, progress.percent())
+                    };
+                if let Some(device) = device {
+                    announcement.push_str(", ");
+                    announcement.push_str(device);
+                }
+                announcement
+            }
+            View::Text(text) => text.text.clone(),
+            View::Media(media) => match &media.artist {
+                Some(artist) => format!("{} by {}", media.title, artist),
+                None => media.title.clone(),
+            },
+        }
+    }
+}
+
+/// A message type as it is read out, e.g. "Power profile" for `power-profile`
+fn spoken_name(message_type: &str) -> String {
+    let mut chars = message_type.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars)
+            .collect::<String>()
+            .replace('-', " "),
+        None => String::new(),
+    }
+}
+
+/// Turns messages of one type into views
+///
+/// Implement this (or pass a closure) and register it with
+/// [`Router::register`] to support a new message `type` without touching the
+/// built-in ones.
+pub trait MessageHandler {
+    fn route(&self, msg: OsdMessage, config: &Config) -> Result<View, MessageError>;
+}
+
+impl<F> MessageHandler for F
+where
+    F: Fn(OsdMessage, &Config) -> Result<View, MessageError>,
+{
+    fn route(&self, msg: OsdMessage, config: &Config) -> Result<View, MessageError> {
+        self(msg, config)
+    }
+}
+
+/// Dispatches messages to the handler registered for their type
+pub struct Router {
+    handlers: HashMap<String, Box<dyn MessageHandler>>,
+}
+
+impl Default for Router {
+    /// A router with the built-in message types registered
+    fn default() -> Self {
+        let mut router = Self::empty();
+        router.register("volume", route_volume);
+        router.register("mic", route_mic);
+        router.register("brightness", |msg, _: &Config| route_brightness(msg));
+        router.register("text", |msg, _: &Config| route_text(msg));
+        router.register("battery", |msg, _: &Config| route_battery(msg));
+        router.register("network", |msg, _: &Config| route_network(msg));
+        router.register("bluetooth", |msg, _: &Config| route_bluetooth(msg));
+        router.register("media", |msg, _: &Config| route_media(msg));
+        router.register("power-profile", |msg, _: &Config| route_power_profile(msg));
+        router.register("custom", |msg, _: &Config| route_custom(msg));
+        router.register("progress", |msg, _: &Config| route_progress(msg));
+        router.register("countdown", |msg, _: &Config| route_countdown(msg));
+        router.register("lock", |msg, _: &Config| route_lock(msg));
+        router.register("keyboard-layout", |msg, _: &Config| {
+            route_keyboard_layout(msg)
+        });
+        router
+    }
+}
+
+impl std::fmt::Debug for Router {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Router")
+            .field("types", &self.handlers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Router {
+    /// A router without any message types
+    pub fn empty() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Handle messages of `message_type` with `handler`, replacing any previous handler
+    pub fn register(&mut self, message_type: &str, handler: impl MessageHandler + 'static) {
+        self.handlers
+            .insert(message_type.to_string(), Box::new(handler));
+    }
+
+    pub fn handles(&self, message_type: &str) -> bool {
+        self.handlers.contains_key(message_type)
+    }
+
+    /// Turn a message into the view that should be displayed for it
+    pub fn route(&self, msg: OsdMessage, config: &Config) -> Result<View, MessageError> {
+        debug!("Routing message: {:?}", msg);
+
+        let message_type = msg.message_type.clone();
+        let custom_icon = msg.icon.as_deref().map(CustomIcon::parse);
+        let value = msg.value.zip(msg.max_value);
+        let markers = msg
+            .marker
+            .clone()
+            .map(Markers::into_vec)
+            .unwrap_or_default();
+        // Handlers without a bar may not have checked it
+        let max = msg.max_value.unwrap_or(100).max(1) as f64;
+        let segments = msg.segments;
+        let mut view = match self.handlers.get(&message_type) {
+            Some(handler) => handler.route(msg, config)?,
+            None => return Err(MessageError::UnknownType(message_type)),
+        };
+
+        match &mut view {
+            View::Progress(progress) => {
+                progress.color = config.bar_color(&message_type, progress.percent());
+                progress.custom_icon = custom_icon;
+                if progress.value_label.is_none() {
+                    progress.value_label = config.bar.value.format(progress.percent(), value);
+                }
+                progress.markers = markers.iter().map(|&m| m as f64 / max).collect();
+                progress.ticks = config
+                    .bar_ticks
+                    .get(&message_type)
+                    .map(|&step| tick_positions(step, progress.marker.unwrap_or(1.0)))
+                    .unwrap_or_default();
+                progress.segments = segments.or(config.bar.segments).filter(|&n| n > 0);
+            }
+            View::Text(text) => text.custom_icon = custom_icon,
+            View::Media(media) => media.custom_icon = custom_icon,
+        }
+        Ok(view)
+    }
+}
+
+/// More tick marks than this would merge into a solid bar
+const MAX_TICKS: usize = 100;
+
+/// Every `step` percent of the full scale, which ends at `full` of the bar width
+fn tick_positions(step: u32, full: f64) -> Vec<f64> {
+    if step == 0 || full <= 0.0 {
+        return Vec::new();
+    }
+
+    (1..)
+        .map(|i: u32| f64::from(i) * f64::from(step) / 100.0 * full)
+        .take_while(|&position| position < 1.0)
+        .take(MAX_TICKS)
+        .collect()
+}
+
+/// Helper for handlers: take a field the message type can't do without
+pub fn require<T>(
+    msg: &OsdMessage,
+    value: Option<T>,
+    field: &'static str,
+) -> Result<T, MessageError> {
+    value.ok_or_else(|| MessageError::MissingField {
+        message_type: msg.message_type.clone(),
+        field,
+    })
+}
+
+/// Helper for handlers: check the value of a full bar, which is divided by
+pub fn positive_max(msg: &OsdMessage, max: i32) -> Result<i32, MessageError> {
+    if max > 0 {
+        return Ok(max);
+    }
+    Err(MessageError::InvalidValue {
+        message_type: msg.message_type.clone(),
+        field: "max_value",
+        value: max.to_string(),
+    })
+}
+
+/// The value counted as 100%, anything above is overamplified
+fn normal_max(msg: &OsdMessage, config: &Config) -> i32 {
+    msg.normal_max.unwrap_or(config.bar.normal_max).max(1)
+}
+
+/// `value` in percent of `normal_max`, computed wide enough for any value a client sends
+fn percent_of(value: i32, normal_max: i32) -> i32 {
+    let percent = i64::from(value) * 100 / i64::from(normal_max.max(1));
+    percent.clamp(i32::MIN.into(), i32::MAX.into()) as i32
+}
+
+fn route_volume(msg: OsdMessage, config: &Config) -> Result<View, MessageError> {
+    let value = require(&msg, msg.value, "value")?;
+    let max = positive_max(&msg, require(&msg, msg.max_value, "max_value")?)?;
+    debug!(
+        "Volume update - level: {}, max: {}, muted: {:?}",
+        value, max, msg.muted
+    );
+
+    let device_icon = config.device_icon(msg.device_class.as_deref(), msg.device_name.as_deref());
+    let normal_max = normal_max(&msg, config);
+    let muted = msg.muted.unwrap_or(false);
+
+    Ok(View::Progress(ProgressView {
+        icon: Some(volume_icon(
+            percent_of(value, normal_max),
+            muted,
+            device_icon,
+        )),
+        fraction: value as f64 / max as f64,
+        overamplified: value > normal_max,
+        // Only show the 100% marker when the bar extends past it
+        marker: (max > normal_max).then(|| normal_max as f64 / max as f64),
+        markers: Vec::new(),
+        ticks: Vec::new(),
+        segments: None,
+        muted,
+        device_label: msg.device_name,
+        color: None,
+        custom_icon: None,
+        value_label: None,
+        css_class: None,
+    }))
+}
+
+fn route_mic(msg: OsdMessage, config: &Config) -> Result<View, MessageError> {
+    let value = require(&msg, msg.value, "value")?;
+    let max = positive_max(&msg, require(&msg, msg.max_value, "max_value")?)?;
+    debug!(
+        "Mic update - level: {}, max: {}, muted: {:?}",
+        value, max, msg.muted
+    );
+
+    let normal_max = normal_max(&msg, config);
+    let muted = msg.muted.unwrap_or(false);
+
+    Ok(View::Progress(ProgressView {
+        icon: Some(mic_icon(percent_of(value, normal_max), muted)),
+        fraction: value as f64 / max as f64,
+        overamplified: value > normal_max,
+        marker: (max > normal_max).then(|| normal_max as f64 / max as f64),
+        markers: Vec::new(),
+        ticks: Vec::new(),
+        segments: None,
+        muted,
+        device_label: msg.device_name,
+        color: None,
+        custom_icon: None,
+        value_label: None,
+        css_class: Some("mic".to_string()),
+    }))
+}
+
+fn route_brightness(msg: OsdMessage) -> Result<View, MessageError> {
+    let value = require(&msg, msg.value, "value")?;
+    let max = positive_max(&msg, require(&msg, msg.max_value, "max_value")?)?;
+    debug!("Brightness update - level: {}, max: {}", value, max);
+
+    Ok(View::Progress(ProgressView {
+        icon: Some(brightness_icon(value as f64 / max as f64)),
+        fraction: value as f64 / max as f64,
+        overamplified: false,
+        marker: None,
+        markers: Vec::new(),
+        ticks: Vec::new(),
+        segments: None,
+        muted: false,
+        device_label: None,
+        color: None,
+        custom_icon: None,
+        value_label: None,
+        css_class: None,
+    }))
+}
+
+fn route_battery(msg: OsdMessage) -> Result<View, MessageError> {
+    let value = require(&msg, msg.value, "value")?;
+    let max = positive_max(&msg, msg.max_value.unwrap_or(100))?;
+    let status = require(&msg, msg.status.clone(), "status")?;
+    debug!(
+        "Battery update - level: {}, max: {}, status: {}, critical: {}",
+        value,
+        max,
+        status,
+        msg.critical()
+    );
+
+    let icon = match status.as_str() {
+        "charging" => IconId::BatteryCharging,
+        "discharging" => IconId::BatteryDischarging,
+        "full" => IconId::BatteryFull,
+        _ => {
+            return Err(MessageError::InvalidValue {
+                message_type: msg.message_type,
+                field: "status",
+                value: status,
+            })
+        }
+    };
+
+    Ok(View::Progress(ProgressView {
+        icon: Some(icon),
+        fraction: value as f64 / max as f64,
+        overamplified: false,
+        marker: None,
+        markers: Vec::new(),
+        ticks: Vec::new(),
+        segments: None,
+        muted: false,
+        device_label: None,
+        color: None,
+        custom_icon: None,
+        value_label: None,
+        css_class: msg.critical().then(|| "critical".to_string()),
+    }))
+}
+
+fn route_text(msg: OsdMessage) -> Result<View, MessageError> {
+    let text = require(&msg, msg.text.clone(), "text")?;
+    debug!("Text message update: {}", text);
+
+    Ok(View::Text(TextView {
+        text,
+        icon: None,
+        custom_icon: None,
+        css_class: None,
+        markup: msg.markup.unwrap_or(false),
+    }))
+}
+
+fn route_network(msg: OsdMessage) -> Result<View, MessageError> {
+    let connection = require(&msg, msg.connection.clone(), "connection")?;
+    debug!(
+        "Network update - connection: {}, ssid: {:?}, signal: {:?}",
+        connection, msg.ssid, msg.value
+    );
+
+    let (icon, text) = match connection.as_str() {
+        "wireless" => (
+            wireless_icon(msg.value.unwrap_or(100)),
+            msg.ssid.unwrap_or_else(|| "Wi-Fi".to_string()),
+        ),
+        "wired" => (IconId::NetworkWired, "Wired".to_string()),
+        "disconnected" => (IconId::NetworkOffline, "Disconnected".to_string()),
+        _ => {
+            return Err(MessageError::InvalidValue {
+                message_type: msg.message_type,
+                field: "connection",
+                value: connection,
+            })
+        }
+    };
+
+    Ok(View::Text(TextView {
+        text,
+        icon: Some(icon),
+        custom_icon: None,
+        css_class: None,
+        markup: false,
+    }))
+}
+
+fn route_bluetooth(msg: OsdMessage) -> Result<View, MessageError> {
+    let name = require(&msg, msg.device_name.clone(), "device_name")?;
+    let connected = require(&msg, msg.connected, "connected")?;
+    debug!(
+        "Bluetooth update - device: {}, connected: {}, battery: {:?}",
+        name, connected, msg.value
+    );
+
+    let (icon, state) = if connected {
+        (IconId::Bluetooth, "connected")
+    } else {
+        (IconId::BluetoothDisconnected, "disconnected")
+    };
+    let text = format!("{} {}", name, state);
+
+    // The battery level only makes sense while the device is around
+    match msg.value.filter(|_| connected) {
+        Some(battery) => Ok(View::Progress(ProgressView {
+            icon: Some(icon),
+            fraction: battery as f64 / positive_max(&msg, msg.max_value.unwrap_or(100))? as f64,
+            overamplified: false,
+            marker: None,
+            markers: Vec::new(),
+            ticks: Vec::new(),
+            segments: None,
+            muted: false,
+            device_label: Some(text),
+            color: None,
+            custom_icon: None,
+            value_label: None,
+            css_class: None,
+        })),
+        None => Ok(View::Text(TextView {
+            text,
+            icon: Some(icon),
+            custom_icon: None,
+            css_class: None,
+            markup: false,
+        })),
+    }
+}
+
+fn route_media(msg: OsdMessage) -> Result<View, MessageError> {
+    let title = require(&msg, msg.title.clone(), "title")?;
+    let playback = msg.playback.as_deref().unwrap_or("playing");
+    debug!(
+        "Media update - title: {}, artist: {:?}, playback: {}",
+        title, msg.artist, playback
+    );
+
+    let icon = match playback {
+        "playing" => IconId::MediaPlaying,
+        "paused" => IconId::MediaPaused,
+        "stopped" => IconId::MediaStopped,
+        _ => {
+            return Err(MessageError::InvalidValue {
+                message_type: msg.message_type,
+                field: "playback",
+                value: playback.to_string(),
+            })
+        }
+    };
+
+    Ok(View::Media(MediaView {
+        title,
+        artist: msg.artist,
+        icon,
+        artwork: msg.artwork,
+        custom_icon: None,
+    }))
+}
+
+/// Whatever the message brings: an icon (filled in by the router), a bar
+/// with the label below it if there's a value, otherwise just the label
+fn route_custom(msg: OsdMessage) -> Result<View, MessageError> {
+    debug!(
+        "Custom update - value: {:?}, max: {:?}, text: {:?}, class: {:?}",
+        msg.value, msg.max_value, msg.text, msg.css_class
+    );
+
+    match msg.value {
+        Some(value) => Ok(View::Progress(ProgressView {
+            icon: None,
+            fraction: value as f64 / positive_max(&msg, msg.max_value.unwrap_or(100))? as f64,
+            overamplified: false,
+            marker: None,
+            markers: Vec::new(),
+            ticks: Vec::new(),
+            segments: None,
+            muted: false,
+            device_label: msg.text,
+            color: None,
+            custom_icon: None,
+            value_label: None,
+            css_class: msg.css_class,
+        })),
+        None => Ok(View::Text(TextView {
+            text: msg.text.unwrap_or_default(),
+            icon: None,
+            custom_icon: None,
+            css_class: msg.css_class,
+            markup: false,
+        })),
+    }
+}
+
+fn route_progress(msg: OsdMessage) -> Result<View, MessageError> {
+    let value = require(&msg, msg.value, "value")?;
+    let max = positive_max(&msg, msg.max_value.unwrap_or(100))?;
+    debug!(
+        "Progress update - value: {}, max: {}, label: {:?}",
+        value, max, msg.label
+    );
+
+    Ok(View::Progress(ProgressView {
+        icon: None,
+        fraction: (value as f64 / max as f64).clamp(0.0, 1.0),
+        overamplified: false,
+        marker: None,
+        markers: Vec::new(),
+        ticks: Vec::new(),
+        segments: None,
+        muted: false,
+        device_label: msg.label,
+        color: None,
+        custom_icon: None,
+        value_label: None,
+        css_class: Some("progress".to_string()),
+    }))
+}
+
+/// The countdown as it starts, the frontend shrinks it as time passes
+fn route_countdown(msg: OsdMessage) -> Result<View, MessageError> {
+    let duration = require(&msg, msg.countdown(), "duration_ms")?;
+    debug!(
+        "Countdown update - duration: {:?}, label: {:?}",
+        duration, msg.label
+    );
+
+    let mut progress = ProgressView {
+        icon: None,
+        fraction: 1.0,
+        overamplified: false,
+        marker: None,
+        markers: Vec::new(),
+        ticks: Vec::new(),
+        segments: None,
+        muted: false,
+        device_label: msg.label,
+        color: None,
+        custom_icon: None,
+        value_label: None,
+        css_class: Some("countdown".to_string()),
+    };
+    progress.set_countdown(duration, duration);
+    Ok(View::Progress(progress))
+}
+
+fn route_lock(msg: OsdMessage) -> Result<View, MessageError> {
+    let key = require(&msg, msg.key.clone(), "key")?;
+    let enabled = require(&msg, msg.enabled, "enabled")?;
+    debug!("Lock update - key: {}, enabled: {}", key, enabled);
+
+    let (icon, name) = match key.as_str() {
+        "caps" => (IconId::CapsLock, "Caps Lock"),
+        "num" => (IconId::NumLock, "Num Lock"),
+        "scroll" => (IconId::ScrollLock, "Scroll Lock"),
+        _ => {
+            return Err(MessageError::InvalidValue {
+                message_type: msg.message_type,
+                field: "key",
+                value: key,
+            })
+        }
+    };
+
+    Ok(View::Text(TextView {
+        text: format!("{} {}", name, if enabled { "On" } else { "Off" }),
+        icon: Some(icon),
+        custom_icon: None,
+        css_class: None,
+        markup: false,
+    }))
+}
+
+fn route_power_profile(msg: OsdMessage) -> Result<View, MessageError> {
+    let profile = require(&msg, msg.text.clone(), "text")?;
+    debug!("Power profile update: {}", profile);
+
+    let (icon, text) = match profile.as_str() {
+        "performance" => (IconId::PowerPerformance, "Performance"),
+        "balanced" => (IconId::PowerBalanced, "Balanced"),
+        "power-saver" => (IconId::PowerSaver, "Power Saver"),
+        _ => {
+            return Err(MessageError::InvalidValue {
+                message_type: msg.message_type,
+                field: "text",
+                value: profile,
+            })
+        }
+    };
+
+    Ok(View::Text(TextView {
+        text: text.to_string(),
+        icon: Some(icon),
+        custom_icon: None,
+        css_class: None,
+        markup: false,
+    }))
+}
+
+fn route_keyboard_layout(msg: OsdMessage) -> Result<View, MessageError> {
+    let layout = require(&msg, msg.text.clone(), "text")?;
+    debug!("Keyboard layout update: {}", layout);
+
+    Ok(View::Text(TextView {
+        text: layout,
+        icon: Some(IconId::Keyboard),
+        custom_icon: None,
+        css_class: None,
+        markup: false,
+    }))
+}
+
+fn volume_icon(value: i32, muted: bool, device_icon: DeviceIcon) -> IconId {
+    if muted {
+        return IconId::VolumeMuted;
+    }
+
+    match device_icon {
+        DeviceIcon::Headphones => IconId::Headphones,
+        DeviceIcon::Headset => IconId::Headset,
+        DeviceIcon::Tv => IconId::Tv,
+        DeviceIcon::UsbDac => IconId::UsbDac,
+        DeviceIcon::Bluetooth => IconId::Bluetooth,
+        DeviceIcon::Speaker => speaker_icon(value),
+    }
+}
+
+/// Picks the speaker icon for a value in percent of the normal maximum
+fn speaker_icon(value: i32) -> IconId {
+    if value > 100 {
+        IconId::VolumeOveramplified
+    } else if value > 66 {
+        IconId::VolumeHigh
+    } else if value > 33 {
+        IconId::VolumeMedium
+    } else {
+        IconId::VolumeLow
+    }
+}
+
+/// Signal strength bars for a signal in percent
+fn wireless_icon(signal: i32) -> IconId {
+    if signal > 75 {
+        IconId::NetworkWirelessExcellent
+    } else if signal > 50 {
+        IconId::NetworkWirelessGood
+    } else if signal > 25 {
+        IconId::NetworkWirelessOk
+    } else {
+        IconId::NetworkWirelessWeak
+    }
+}
+
+fn brightness_icon(fraction: f64) -> IconId {
+    if fraction > 0.66 {
+        IconId::BrightnessHigh
+    } else if fraction > 0.33 {
+        IconId::BrightnessMedium
+    } else {
+        IconId::BrightnessLow
+    }
+}
+
+fn mic_icon(value: i32, muted: bool) -> IconId {
+    if muted {
+        IconId::MicMuted
+    } else if value > 66 {
+        IconId::MicHigh
+    } else if value > 33 {
+        IconId::MicMedium
+    } else {
+        IconId::MicLow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(msg: OsdMessage) -> Result<View, MessageError> {
+        Router::default().route(msg, &Config::default())
+    }
+
+    fn progress(msg: OsdMessage) -> ProgressView {
+        match route(msg) {
+            Ok(View::Progress(progress)) => progress,
+            other => panic!("expected a progress view, got {:?}", other),
+        }
+    }
+
+    fn text(msg: OsdMessage) -> TextView {
+        match route(msg) {
+            Ok(View::Text(text)) => text,
+            other => panic!("expected a text view, got {:?}", other),
+        }
+    }
+
+    fn bar(message_type: &str, value: i32, max_value: i32) -> OsdMessage {
+        OsdMessage {
+            value: Some(value),
+            max_value: Some(max_value),
+            ..OsdMessage::new(message_type)
+        }
+    }
+
+    #[test]
+    fn volume() {
+        let view = progress(bar("volume", 50, 100));
+        assert_eq!(view.icon, Some(IconId::VolumeMedium));
+        assert_eq!(view.fraction, 0.5);
+        assert!(!view.overamplified);
+        assert_eq!(view.marker, None);
+
+        let view = progress(OsdMessage {
+            muted: Some(true),
+            ..bar("volume", 50, 100)
+        });
+        assert_eq!(view.icon, Some(IconId::VolumeMuted));
+        assert!(view.muted);
+    }
+
+    #[test]
+    fn overamplified_volume() {
+        let view = progress(bar("volume", 120, 150));
+        assert_eq!(view.icon, Some(IconId::VolumeOveramplified));
+        assert!(view.overamplified);
+        assert_eq!(view.marker, Some(100.0 / 150.0));
+    }
+
+    #[test]
+    fn device_icon() {
+        let view = progress(OsdMessage {
+            device_name: Some("USB Headset".to_string()),
+            ..bar("volume", 50, 100)
+        });
+        assert_eq!(view.icon, Some(IconId::Headset));
+        assert_eq!(view.device_label.as_deref(), Some("USB Headset"));
+    }
+
+    #[test]
+    fn brightness() {
+        let view = progress(bar("brightness", 900, 1000));
+        assert_eq!(view.icon, Some(IconId::BrightnessHigh));
+        assert_eq!(view.fraction, 0.9);
+    }
+
+    #[test]
+    fn text_message() {
+        let view = text(OsdMessage {
+            text: Some("Hello".to_string()),
+            ..OsdMessage::new("text")
+        });
+        assert_eq!(view.text, "Hello");
+        assert_eq!(view.icon, None);
+        assert!(!view.markup);
+    }
+
+    #[test]
+    fn message_icon() {
+        let view = progress(OsdMessage {
+            icon: Some("/tmp/fan.svg".to_string()),
+            ..bar("brightness", 50, 100)
+        });
+        assert_eq!(
+            view.custom_icon,
+            Some(CustomIcon::File(PathBuf::from("/tmp/fan.svg")))
+        );
+
+        let view = text(OsdMessage {
+            text: Some("Hello".to_string()),
+            icon: Some("network-wireless-symbolic".to_string()),
+            ..OsdMessage::new("text")
+        });
+        assert_eq!(
+            view.custom_icon,
+            Some(CustomIcon::Named("network-wireless-symbolic".to_string()))
+        );
+    }
+
+    #[test]
+    fn lock() {
+        let view = text(OsdMessage {
+            key: Some("caps".to_string()),
+            enabled: Some(true),
+            ..OsdMessage::new("lock")
+        });
+        assert_eq!(view.text, "Caps Lock On");
+        assert_eq!(view.icon, Some(IconId::CapsLock));
+    }
+
+    #[test]
+    fn keyboard_layout() {
+        let view = text(OsdMessage {
+            text: Some("de".to_string()),
+            ..OsdMessage::new("keyboard-layout")
+        });
+        assert_eq!(view.text, "de");
+        assert_eq!(view.icon, Some(IconId::Keyboard));
+    }
+
+    #[test]
+    fn huge_volume_does_not_overflow() {
+        let view = progress(OsdMessage {
+            normal_max: Some(0),
+            ..bar("volume", i32::MAX, 100)
+        });
+        assert_eq!(view.icon, Some(IconId::VolumeOveramplified));
+
+        let view = progress(bar("mic", 30_000_000, 100));
+        assert_eq!(view.icon, Some(IconId::MicHigh));
+    }
+
+    #[test]
+    fn message_normal_max() {
+        let view = progress(OsdMessage {
+            normal_max: Some(150),
+            ..bar("volume", 120, 150)
+        });
+        assert_eq!(view.icon, Some(IconId::VolumeHigh));
+        assert!(!view.overamplified);
+        assert_eq!(view.marker, None);
+    }
+
+    #[test]
+    fn max_value_must_be_positive() {
+        for message_type in [
+            "volume",
+            "mic",
+            "brightness",
+            "battery",
+            "custom",
+            "progress",
+        ] {
+            let result = route(OsdMessage {
+                status: Some("charging".to_string()),
+                ..bar(message_type, 5, 0)
+            });
+            assert!(
+                matches!(
+                    result,
+                    Err(MessageError::InvalidValue {
+                        field: "max_value",
+                        ..
+                    })
+                ),
+                "{} accepted max_value 0",
+                message_type
+            );
+        }
+        assert!(route(OsdMessage {
+            device_name: Some("Earbuds".to_string()),
+            connected: Some(true),
+            ..bar("bluetooth", 80, -100)
+        })
+        .is_err());
+
+        // Only read for the markers here
+        let view = progress(OsdMessage {
+            marker: Some(Markers::Many(vec![50])),
+            max_value: Some(0),
+            duration_ms: Some(1000),
+            ..OsdMessage::new("countdown")
+        });
+        assert!(view.markers.iter().all(|marker| marker.is_finite()));
+    }
+
+    #[test]
+    fn mic() {
+        let view = progress(bar("mic", 20, 100));
+        assert_eq!(view.icon, Some(IconId::MicLow));
+        assert_eq!(view.css_class.as_deref(), Some("mic"));
+    }
+
+    #[test]
+    fn battery() {
+        let view = progress(OsdMessage {
+            status: Some("discharging".to_string()),
+            critical: Some(true),
+            ..bar("battery", 5, 100)
+        });
+        assert_eq!(view.icon, Some(IconId::BatteryDischarging));
+        assert_eq!(view.css_class.as_deref(), Some("critical"));
+
+        let result = route(OsdMessage {
+            status: Some("exploding".to_string()),
+            ..bar("battery", 5, 100)
+        });
+        assert!(matches!(
+            result,
+            Err(MessageError::InvalidValue {
+                field: "status",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn media() {
+        let view = match route(OsdMessage {
+            title: Some("Roygbiv".to_string()),
+            playback: Some("paused".to_string()),
+            ..OsdMessage::new("media")
+        }) {
+            Ok(View::Media(media)) => media,
+            other => panic!("expected a media view, got {:?}", other),
+        };
+        assert_eq!(view.title, "Roygbiv");
+        assert_eq!(view.icon, IconId::MediaPaused);
+    }
+
+    #[test]
+    fn network() {
+        let view = text(OsdMessage {
+            connection: Some("wireless".to_string()),
+            ssid: Some("home".to_string()),
+            value: Some(30),
+            ..OsdMessage::new("network")
+        });
+        assert_eq!(view.text, "home");
+        assert_eq!(view.icon, Some(IconId::NetworkWirelessOk));
+
+        let view = text(OsdMessage {
+            connection: Some("disconnected".to_string()),
+            ..OsdMessage::new("network")
+        });
+        assert_eq!(view.icon, Some(IconId::NetworkOffline));
+    }
+
+    #[test]
+    fn bluetooth() {
+        let connected = OsdMessage {
+            device_name: Some("Earbuds".to_string()),
+            connected: Some(true),
+            ..bar("bluetooth", 80, 100)
+        };
+        let view = progress(connected.clone());
+        assert_eq!(view.device_label.as_deref(), Some("Earbuds connected"));
+        assert_eq!(view.fraction, 0.8);
+
+        // No battery level once the device is gone
+        let view = text(OsdMessage {
+            connected: Some(false),
+            ..connected
+        });
+        assert_eq!(view.text, "Earbuds disconnected");
+        assert_eq!(view.icon, Some(IconId::BluetoothDisconnected));
+    }
+
+    #[test]
+    fn power_profile() {
+        let view = text(OsdMessage {
+            text: Some("power-saver".to_string()),
+            ..OsdMessage::new("power-profile")
+        });
+        assert_eq!(view.text, "Power Saver");
+        assert_eq!(view.icon, Some(IconId::PowerSaver));
+    }
+
+    #[test]
+    fn custom() {
+        let view = progress(OsdMessage {
+            text: Some("Fan".to_string()),
+            css_class: Some("fan".to_string()),
+            ..bar("custom", 25, 100)
+        });
+        assert_eq!(view.device_label.as_deref(), Some("Fan"));
+        assert_eq!(view.css_class.as_deref(), Some("fan"));
+
+        let view = text(OsdMessage {
+            text: Some("Fan".to_string()),
+            icon: Some("/tmp/fan.svg".to_string()),
+            ..OsdMessage::new("custom")
+        });
+        assert_eq!(
+            view.custom_icon,
+            Some(CustomIcon::File(PathBuf::from("/tmp/fan.svg")))
+        );
+    }
+
+    #[test]
+    fn progress_message() {
+        let view = progress(OsdMessage {
+            label: Some("Copying".to_string()),
+            ..bar("progress", 150, 100)
+        });
+        assert_eq!(view.fraction, 1.0);
+        assert_eq!(view.device_label.as_deref(), Some("Copying"));
+        assert_eq!(view.css_class.as_deref(), Some("progress"));
+    }
+
+    #[test]
+    fn countdown() {
+        let view = progress(OsdMessage {
+            duration_ms: Some(90_000),
+            ..OsdMessage::new("countdown")
+        });
+        assert_eq!(view.fraction, 1.0);
+        assert_eq!(view.value_label.as_deref(), Some("1:30"));
+    }
+
+    #[test]
+    fn missing_field() {
+        assert_eq!(
+            route(OsdMessage::new("brightness")),
+            Err(MessageError::MissingField {
+                message_type: "brightness".to_string(),
+                field: "value",
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_type() {
+        assert_eq!(
+            route(OsdMessage::new("teleport")),
+            Err(MessageError::UnknownType("teleport".to_string()))
+        );
+    }
+
+    #[test]
+    fn registered_type() {
+        let mut router = Router::empty();
+        router.register("clock", |msg: OsdMessage, _: &Config| route_text(msg));
+        assert!(router.handles("clock"));
+        assert!(!router.handles("volume"));
+        assert!(router
+            .route(OsdMessage::new("clock"), &Config::default())
+            .is_err());
+    }
+
+    #[test]
+    fn ticks() {
+        assert_eq!(tick_positions(25, 1.0), vec![0.25, 0.5, 0.75]);
+        assert_eq!(tick_positions(50, 0.5), vec![0.25, 0.5, 0.75]);
+        assert!(tick_positions(0, 1.0).is_empty());
+        assert!(tick_positions(10, 0.0).is_empty());
+        assert_eq!(tick_positions(1, 1e-9).len(), MAX_TICKS);
+    }
+}
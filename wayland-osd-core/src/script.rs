@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use log::{debug, info};
+use rhai::{Dynamic, Engine, Scope, AST};
+
+use crate::message::OsdMessage;
+
+/// A user script that can rewrite or drop messages before they are displayed
+///
+/// The script must define `fn transform(msg)`, which receives the message as
+/// an object map with the same fields as the JSON protocol. Returning a map
+/// replaces the message, returning `()` drops it.
+///
+/// ```rhai
+/// fn transform(msg) {
+///     if msg.type == "volume" && msg.device_name == "HDMI" { return (); }
+///     msg
+/// }
+/// ```
+pub struct Transformer {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Transformer {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        info!("Loading script from {}", path.display());
+
+        let engine = Engine::new();
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("Failed to compile script {}", path.display()))?;
+
+        if !ast.iter_functions().any(|f| f.name == "transform") {
+            return Err(anyhow!(
+                "Script {} does not define `fn transform(msg)`",
+                path.display()
+            ));
+        }
+
+        Ok(Self { engine, ast })
+    }
+
+    pub fn transform(&self, msg: OsdMessage) -> anyhow::Result<Option<OsdMessage>> {
+        let input = rhai::serde::to_dynamic(&msg).map_err(|e| anyhow!("{}", e))?;
+
+        let output: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, "transform", (input,))
+            .map_err(|e| anyhow!("Script failed: {}", e))?;
+
+        if output.is_unit() {
+            debug!("Script dropped message: {:?}", msg);
+            return Ok(None);
+        }
+
+        let transformed: OsdMessage = rhai::serde::from_dynamic(&output)
+            .map_err(|e| anyhow!("Script returned an invalid message: {}", e))?;
+        // The script never sees the sender, so it can't change it either
+        Ok(Some(OsdMessage {
+            peer: msg.peer,
+            ..transformed
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::message::Peer;
+
+    fn load(name: &str, source: &str) -> anyhow::Result<Transformer> {
+        let path =
+            std::env::temp_dir().join(format!("wayland-osd-{}-{}.rhai", std::process::id(), name));
+        fs::write(&path, source).unwrap();
+        let transformer = Transformer::load(&path);
+        fs::remove_file(&path).unwrap();
+        transformer
+    }
+
+    fn volume(device_name: &str) -> OsdMessage {
+        OsdMessage {
+            value: Some(40),
+            max_value: Some(100),
+            device_name: Some(device_name.to_string()),
+            ..OsdMessage::new("volume")
+        }
+    }
+
+    #[test]
+    fn rewrites_and_drops_messages() {
+        let transformer = load(
+            "rewrite",
+            r#"
+            fn transform(msg) {
+                if msg.device_name == "HDMI" { return (); }
+                msg.value = msg.value * 2;
+                msg
+            }
+            "#,
+        )
+        .unwrap();
+
+        let msg = transformer.transform(volume("Speakers")).unwrap().unwrap();
+        assert_eq!(msg.value, Some(80));
+        assert_eq!(msg.device_name.as_deref(), Some("Speakers"));
+        assert!(transformer.transform(volume("HDMI")).unwrap().is_none());
+    }
+
+    #[test]
+    fn keeps_sender() {
+        let transformer = load(
+            "sender",
+            r#"fn transform(msg) { #{ type: "text", text: "replaced" } }"#,
+        )
+        .unwrap();
+
+        let peer = Peer { pid: 42, uid: 1000 };
+        let msg = transformer
+            .transform(OsdMessage {
+                peer: Some(peer),
+                ..volume("Speakers")
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg.text.as_deref(), Some("replaced"));
+        assert_eq!(msg.peer, Some(peer));
+    }
+
+    #[test]
+    fn requires_transform_function() {
+        let error = load("missing", "fn other(msg) { msg }").err().unwrap();
+        assert!(error.to_string().contains("does not define"), "{}", error);
+        assert!(load("invalid", "fn transform(msg) {").is_err());
+    }
+
+    #[test]
+    fn invalid_result_is_an_error() {
+        let transformer = load("invalid-result", "fn transform(msg) { 42 }").unwrap();
+        assert!(transformer.transform(volume("Speakers")).is_err());
+    }
+}
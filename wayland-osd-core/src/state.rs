@@ -0,0 +1,515 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, LowerPriority, QueueMode, TimeoutMode};
+use crate::message::{MessageError, OsdMessage};
+use crate::router::{Router, View};
+
+/// How long an OSD stays on screen after the last message, unless configured otherwise
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Critical messages stay at least this long, unless they set their own timeout
+pub const CRITICAL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many messages [`History`] remembers
+pub const HISTORY_LEN: usize = 50;
+
+/// Result of handling a message: what to show and when to hide it again
+#[derive(Debug, Clone)]
+pub struct Update {
+    pub view: View,
+    pub hide_after: Duration,
+    /// Pass back to [`OsdState::expire`] once `hide_after` has elapsed
+    pub generation: u64,
+    /// Whether this starts a new popup rather than updating the one on screen
+    pub new_popup: bool,
+    /// Whether the message is the same as the one on screen, so only the
+    /// timeout needs to start over
+    pub repeated: bool,
+    /// Stays up until dismissed or replaced, `hide_after` doesn't apply
+    pub sticky: bool,
+}
+
+/// The most recently shown messages, oldest first
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct History {
+    messages: VecDeque<OsdMessage>,
+}
+
+impl History {
+    /// Remember a shown message, forgetting the oldest beyond [`HISTORY_LEN`]
+    pub fn push(&mut self, msg: OsdMessage) {
+        if self.messages.len() >= HISTORY_LEN {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(msg);
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = &OsdMessage> {
+        self.messages.iter()
+    }
+}
+
+/// Tracks what the OSD is currently showing
+///
+/// Every shown message starts a new generation, so a hide timeout scheduled
+/// for an older message can never hide a newer one.
+#[derive(Debug, Default)]
+pub struct OsdState {
+    router: Router,
+    current: Option<View>,
+    /// `id` of the message that started the popup on screen
+    current_id: Option<String>,
+    /// Priority of the message on screen
+    current_priority: i32,
+    /// Tag of the message on screen
+    current_tag: String,
+    /// When the popup on screen appeared
+    shown_at: Option<Instant>,
+    /// When what is on screen last changed
+    changed_at: Option<Instant>,
+    /// Messages waiting for the one on screen to time out, at most one per tag
+    pending: VecDeque<OsdMessage>,
+    generation: u64,
+    /// The last message shown for every tag
+    last_messages: BTreeMap<String, OsdMessage>,
+}
+
+impl OsdState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a router with additional or replaced message types
+    pub fn with_router(router: Router) -> Self {
+        Self {
+            router,
+            ..Self::default()
+        }
+    }
+
+    pub fn router_mut(&mut self) -> &mut Router {
+        &mut self.router
+    }
+
+    /// Show a message, returns `None` when it was queued or dropped instead
+    ///
+    /// Messages wait for one of a higher priority to time out, and in
+    /// [`QueueMode::Sequential`] also for any other tag of the same priority.
+    pub fn handle(
+        &mut self,
+        msg: OsdMessage,
+        config: &Config,
+    ) -> Result<Option<Update>, MessageError> {
+        let tag = msg.tag().to_string();
+        // Countdowns stay up until they run out
+        let hide_after = msg
+            .timeout_ms
+            .map(Duration::from_millis)
+            .or_else(|| msg.countdown())
+            .unwrap_or_else(|| {
+                if msg.critical() {
+                    config.timeout().max(CRITICAL_TIMEOUT)
+                } else {
+                    config.timeout()
+                }
+            });
+        let view = self.router.route(msg.clone(), config)?;
+
+        if self.current.is_some() {
+            let priority = msg.priority();
+            if priority < self.current_priority {
+                if config.priority.lower == LowerPriority::Queue {
+                    self.queue(msg);
+                }
+                return Ok(None);
+            }
+            if config.queue_mode == QueueMode::Sequential
+                && priority == self.current_priority
+                && tag != self.current_tag
+            {
+                self.queue(msg);
+                return Ok(None);
+            }
+        }
+        let new_popup = self.current.is_none() || self.current_id != msg.id;
+        // Every key press is a new client process, so the sender doesn't count
+        let repeated = !new_popup
+            && self
+                .current_message()
+                .is_some_and(|current| current.shows_same(&msg));
+        let sticky = msg.sticky();
+
+        let now = Instant::now();
+        if new_popup {
+            self.shown_at = Some(now);
+        }
+        if !repeated {
+            self.changed_at = Some(now);
+        }
+        // Counted from then, what has passed since is already used up
+        let since = match config.timeout_mode {
+            TimeoutMode::Extend => None,
+            TimeoutMode::Fixed => self.shown_at,
+            TimeoutMode::LastChange => self.changed_at,
+        };
+        let hide_after = since.map_or(hide_after, |since| {
+            hide_after.saturating_sub(now.duration_since(since))
+        });
+        self.current_id = msg.id.clone();
+        self.current_priority = msg.priority();
+        self.current_tag = tag.clone();
+        self.last_messages.insert(tag, msg);
+
+        self.generation += 1;
+        self.current = Some(view.clone());
+
+        Ok(Some(Update {
+            view,
+            hide_after,
+            generation: self.generation,
+            new_popup,
+            repeated,
+            sticky,
+        }))
+    }
+
+    /// Hold a message back, replacing a queued one with the same tag in its place
+    fn queue(&mut self, msg: OsdMessage) {
+        match self
+            .pending
+            .iter_mut()
+            .find(|pending| pending.tag() == msg.tag())
+        {
+            Some(pending) => *pending = msg,
+            None => self.pending.push_back(msg),
+        }
+    }
+
+    pub fn last_messages(&self) -> &BTreeMap<String, OsdMessage> {
+        &self.last_messages
+    }
+
+    /// Bring back the last messages of a previous run, without showing them
+    pub fn restore(&mut self, last_messages: BTreeMap<String, OsdMessage>) {
+        self.last_messages = last_messages;
+    }
+
+    /// Called when a hide timeout fires, returns whether the OSD should be hidden
+    pub fn expire(&mut self, generation: u64) -> bool {
+        if generation != self.generation || self.current.is_none() {
+            return false;
+        }
+
+        self.current = None;
+        true
+    }
+
+    /// The next queued message to show after a hide timeout fired
+    pub fn next_pending(&mut self) -> Option<OsdMessage> {
+        self.pending.pop_front()
+    }
+
+    /// Hide right away regardless of the timeout, also dropping queued
+    /// messages. Returns whether anything was shown.
+    pub fn hide(&mut self) -> bool {
+        self.pending.clear();
+        self.current.take().is_some()
+    }
+
+    pub fn current(&self) -> Option<&View> {
+        self.current.as_ref()
+    }
+
+    /// The message on screen
+    pub fn current_message(&self) -> Option<&OsdMessage> {
+        self.current
+            .as_ref()
+            .and(self.last_messages.get(&self.current_tag))
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.current.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn volume(value: i32) -> OsdMessage {
+        OsdMessage {
+            value: Some(value),
+            max_value: Some(100),
+            ..OsdMessage::new("volume")
+        }
+    }
+
+    fn text(text: &str) -> OsdMessage {
+        OsdMessage {
+            text: Some(text.to_string()),
+            ..OsdMessage::new("text")
+        }
+    }
+
+    fn with_priority(msg: OsdMessage, priority: i32) -> OsdMessage {
+        OsdMessage {
+            priority: Some(priority),
+            ..msg
+        }
+    }
+
+    fn shown(state: &mut OsdState, msg: OsdMessage, config: &Config) -> Update {
+        state
+            .handle(msg, config)
+            .expect("valid message")
+            .expect("message shown")
+    }
+
+    #[test]
+    fn shows_and_expires_message() {
+        let config = Config::default();
+        let mut state = OsdState::new();
+
+        let update = shown(&mut state, volume(40), &config);
+        assert!(update.new_popup);
+        assert!(!update.repeated);
+        assert_eq!(update.hide_after, config.timeout());
+        assert!(state.is_visible());
+
+        assert!(state.expire(update.generation));
+        assert!(!state.is_visible());
+    }
+
+    #[test]
+    fn stale_timeout_does_not_hide_newer_message() {
+        let config = Config::default();
+        let mut state = OsdState::new();
+
+        let first = shown(&mut state, volume(40), &config);
+        let second = shown(&mut state, volume(50), &config);
+        assert!(!second.new_popup);
+
+        assert!(!state.expire(first.generation));
+        assert!(state.is_visible());
+        assert!(state.expire(second.generation));
+    }
+
+    #[test]
+    fn invalid_message_is_an_error() {
+        let config = Config::default();
+        let mut state = OsdState::new();
+
+        let result = state.handle(OsdMessage::new("volume"), &config);
+        assert!(matches!(
+            result,
+            Err(MessageError::MissingField { field: "value", .. })
+        ));
+        assert!(!state.is_visible());
+    }
+
+    #[test]
+    fn hide_cancels_pending_timeout() {
+        let config = Config::default();
+        let mut state = OsdState::new();
+
+        let update = shown(&mut state, volume(40), &config);
+        assert!(state.hide());
+        assert!(!state.hide());
+        assert!(!state.expire(update.generation));
+    }
+
+    #[test]
+    fn lower_priority_is_queued_until_expired() {
+        let config = Config::default();
+        let mut state = OsdState::new();
+
+        let urgent = shown(&mut state, with_priority(text("Battery low"), 10), &config);
+        assert!(state.handle(volume(40), &config).unwrap().is_none());
+        assert_eq!(
+            state.current_message().and_then(|msg| msg.text.as_deref()),
+            Some("Battery low")
+        );
+
+        assert!(state.expire(urgent.generation));
+        assert_eq!(state.next_pending().and_then(|msg| msg.value), Some(40));
+        assert!(state.next_pending().is_none());
+    }
+
+    #[test]
+    fn lower_priority_is_dropped_when_configured() {
+        let mut config = Config::default();
+        config.priority.lower = LowerPriority::Drop;
+        let mut state = OsdState::new();
+
+        let urgent = shown(&mut state, with_priority(text("Battery low"), 10), &config);
+        assert!(state.handle(volume(40), &config).unwrap().is_none());
+
+        assert!(state.expire(urgent.generation));
+        assert!(state.next_pending().is_none());
+    }
+
+    #[test]
+    fn higher_priority_replaces_message_on_screen() {
+        let config = Config::default();
+        let mut state = OsdState::new();
+
+        shown(&mut state, volume(40), &config);
+        shown(&mut state, with_priority(text("Battery low"), 10), &config);
+        assert_eq!(
+            state.current_message().map(|msg| msg.message_type.as_str()),
+            Some("text")
+        );
+    }
+
+    #[test]
+    fn id_starts_new_popup() {
+        let config = Config::default();
+        let mut state = OsdState::new();
+        let with_id = |id: &str, value| OsdMessage {
+            id: Some(id.to_string()),
+            ..volume(value)
+        };
+
+        assert!(shown(&mut state, with_id("a", 10), &config).new_popup);
+        assert!(!shown(&mut state, with_id("a", 20), &config).new_popup);
+        assert!(shown(&mut state, with_id("b", 20), &config).new_popup);
+    }
+
+    #[test]
+    fn message_timeout_overrides_config() {
+        let config = Config::default();
+        let mut state = OsdState::new();
+
+        let update = shown(
+            &mut state,
+            OsdMessage {
+                timeout_ms: Some(500),
+                ..volume(40)
+            },
+            &config,
+        );
+        assert_eq!(update.hide_after, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn sequential_mode_queues_other_tags_once_each() {
+        let mut config = Config::default();
+        config.queue_mode = QueueMode::Sequential;
+        let mut state = OsdState::new();
+
+        let first = shown(&mut state, volume(40), &config);
+        // The same tag still updates in place
+        shown(&mut state, volume(50), &config);
+        assert!(state.handle(text("one"), &config).unwrap().is_none());
+        assert!(state.handle(text("two"), &config).unwrap().is_none());
+
+        assert!(!state.expire(first.generation));
+        assert!(state.hide());
+        // Hiding drops whatever was queued
+        assert!(state.next_pending().is_none());
+
+        let update = shown(&mut state, volume(40), &config);
+        state.handle(text("one"), &config).unwrap();
+        state.handle(text("two"), &config).unwrap();
+        assert!(state.expire(update.generation));
+        // Only the latest message of the tag waits
+        assert_eq!(
+            state.next_pending().and_then(|msg| msg.text),
+            Some("two".to_string())
+        );
+        assert!(state.next_pending().is_none());
+    }
+
+    #[test]
+    fn sticky_and_critical_messages() {
+        let config = Config::default();
+        let mut state = OsdState::new();
+
+        let sticky = shown(
+            &mut state,
+            OsdMessage {
+                sticky: Some(true),
+                ..text("Microphone live")
+            },
+            &config,
+        );
+        assert!(sticky.sticky);
+
+        let critical = shown(
+            &mut state,
+            OsdMessage {
+                critical: Some(true),
+                ..text("Battery low")
+            },
+            &config,
+        );
+        assert!(!critical.sticky);
+        assert_eq!(critical.hide_after, config.timeout().max(CRITICAL_TIMEOUT));
+    }
+
+    #[test]
+    fn repeated_message_is_detected_whoever_sent_it() {
+        let config = Config::default();
+        let mut state = OsdState::new();
+        let from = |pid| OsdMessage {
+            peer: Some(crate::message::Peer { pid, uid: 1000 }),
+            ..volume(100)
+        };
+
+        assert!(!shown(&mut state, from(1), &config).repeated);
+        assert!(shown(&mut state, from(2), &config).repeated);
+        assert!(!shown(&mut state, volume(90), &config).repeated);
+    }
+
+    fn timeouts(mode: TimeoutMode, second: OsdMessage) -> Duration {
+        let mut config = Config::default();
+        config.timeout_ms = 1000;
+        config.timeout_mode = mode;
+        let mut state = OsdState::new();
+
+        shown(&mut state, volume(40), &config);
+        thread::sleep(Duration::from_millis(20));
+        shown(&mut state, second, &config).hide_after
+    }
+
+    #[test]
+    fn extend_mode_restarts_timeout() {
+        let full = Duration::from_millis(1000);
+        assert_eq!(timeouts(TimeoutMode::Extend, volume(40)), full);
+        assert_eq!(timeouts(TimeoutMode::Extend, volume(50)), full);
+    }
+
+    #[test]
+    fn fixed_mode_counts_from_first_message() {
+        let full = Duration::from_millis(1000);
+        assert!(timeouts(TimeoutMode::Fixed, volume(40)) < full);
+        assert!(timeouts(TimeoutMode::Fixed, volume(50)) < full);
+    }
+
+    #[test]
+    fn last_change_mode_ignores_repeats() {
+        let full = Duration::from_millis(1000);
+        assert!(timeouts(TimeoutMode::LastChange, volume(40)) < full);
+        assert_eq!(timeouts(TimeoutMode::LastChange, volume(50)), full);
+    }
+
+    #[test]
+    fn history_forgets_oldest_messages() {
+        let mut history = History::default();
+        for value in 0..HISTORY_LEN as i32 + 5 {
+            history.push(volume(value));
+        }
+        assert_eq!(history.messages().count(), HISTORY_LEN);
+        assert_eq!(history.messages().next().and_then(|msg| msg.value), Some(5));
+        assert_eq!(
+            history.messages().last().and_then(|msg| msg.value),
+            Some(HISTORY_LEN as i32 + 4)
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::message::OsdMessage;
+use crate::state::History;
+
+/// Counters shared between the IPC threads and the UI, exposed for debugging
+#[derive(Debug, Default)]
+pub struct Stats {
+    dropped_queue_full: AtomicU64,
+    dropped_oversized: AtomicU64,
+    coalesced: AtomicU64,
+    rejected: AtomicU64,
+    handled: Mutex<BTreeMap<String, u64>>,
+}
+
+/// A point-in-time copy of [`Stats`]
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct StatsSnapshot {
+    /// Messages discarded because the queue to the UI was full
+    pub dropped_queue_full: u64,
+    /// Messages discarded because they exceeded the maximum message size
+    pub dropped_oversized: u64,
+    /// Queued messages replaced by a newer one with the same tag
+    pub coalesced: u64,
+    /// Messages that couldn't be parsed or were invalid for their type
+    pub rejected: u64,
+    /// Messages handled by the UI, by type
+    pub handled: BTreeMap<String, u64>,
+}
+
+/// What the server reports when asked how it is doing
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    pub uptime_secs: u64,
+    #[serde(flatten)]
+    pub stats: StatsSnapshot,
+    pub profile: Option<String>,
+    pub dnd: bool,
+    /// The message on screen in every visible window, by category, `main` for the main window
+    pub shown: BTreeMap<String, OsdMessage>,
+    /// The last message shown for every tag, including those restored from the last run
+    pub last_messages: BTreeMap<String, OsdMessage>,
+    /// The most recently shown messages, oldest first
+    pub history: History,
+}
+
+impl Status {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Status is always serializable")
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_queue_full(&self) {
+        self.dropped_queue_full.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_oversized(&self) {
+        self.dropped_oversized.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_coalesced(&self) {
+        self.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_handled(&self, message_type: &str) {
+        *self
+            .handled
+            .lock()
+            .unwrap()
+            .entry(message_type.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            dropped_queue_full: self.dropped_queue_full.load(Ordering::Relaxed),
+            dropped_oversized: self.dropped_oversized.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            handled: self.handled.lock().unwrap().clone(),
+        }
+    }
+}
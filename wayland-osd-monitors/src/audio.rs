@@ -0,0 +1,196 @@
+use std::io::BufReader;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context};
+use log::{debug, info, warn};
+use wayland_osd_core::OsdMessage;
+
+use crate::backend::{Backend, Sink};
+use crate::config::AudioConfig;
+
+/// WirePlumber alias for whichever sink is currently the default
+const DEFAULT_SINK: &str = "@DEFAULT_AUDIO_SINK@";
+
+/// Watches the default PipeWire sink for volume and mute changes
+///
+/// `pw-dump --monitor` is used to get notified about changes to nodes and the
+/// default-sink metadata, `wpctl` is then queried for the resulting state.
+pub struct AudioBackend {
+    config: AudioConfig,
+    last: Option<SinkState>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SinkState {
+    volume: i32,
+    muted: bool,
+    node_name: Option<String>,
+    description: Option<String>,
+}
+
+impl AudioBackend {
+    pub fn new(config: AudioConfig) -> Self {
+        Self { config, last: None }
+    }
+
+    fn query_sink() -> anyhow::Result<SinkState> {
+        let output = run_wpctl(&["get-volume", DEFAULT_SINK])?;
+        let (volume, muted) = parse_volume(&output)
+            .with_context(|| format!("Unexpected wpctl output: {}", output.trim()))?;
+
+        let inspect = run_wpctl(&["inspect", DEFAULT_SINK]).unwrap_or_default();
+
+        Ok(SinkState {
+            volume,
+            muted,
+            node_name: inspect_property(&inspect, "node.name"),
+            description: inspect_property(&inspect, "node.description"),
+        })
+    }
+
+    fn display_name(&self, state: &SinkState) -> Option<String> {
+        let node_name = state.node_name.as_deref()?;
+
+        self.config
+            .device_names
+            .iter()
+            .find(|mapping| node_name.contains(&mapping.pattern))
+            .map(|mapping| mapping.name.clone())
+            .or_else(|| state.description.clone())
+            .or_else(|| Some(node_name.to_string()))
+    }
+
+    fn on_change(&mut self, sink: &Sink) -> anyhow::Result<()> {
+        let state = Self::query_sink()?;
+
+        let Some(last) = self.last.replace(state.clone()) else {
+            // The initial dump only establishes the baseline
+            debug!("Initial sink state: {:?}", state);
+            return Ok(());
+        };
+
+        if last == state {
+            return Ok(());
+        }
+
+        info!("Volume: {}, muted: {}", state.volume, state.muted);
+        let device_name = self
+            .config
+            .show_device_name
+            .then(|| self.display_name(&state))
+            .flatten();
+
+        sink(OsdMessage {
+            value: Some(state.volume),
+            max_value: Some(100),
+            muted: Some(state.muted),
+            device_name,
+            // The raw node name carries hints like "bluez" or "hdmi" for the server's icon rules
+            device_class: state.node_name,
+            ..OsdMessage::new("volume")
+        })
+    }
+}
+
+impl Backend for AudioBackend {
+    fn name(&self) -> &'static str {
+        "audio"
+    }
+
+    fn run(&mut self, sink: &Sink) -> anyhow::Result<()> {
+        let mut child = Command::new("pw-dump")
+            .args(["--monitor", "--no-colors"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to start pw-dump")?;
+
+        let stdout = child.stdout.take().context("pw-dump has no stdout")?;
+        let updates = serde_json::Deserializer::from_reader(BufReader::new(stdout))
+            .into_iter::<Vec<serde_json::Value>>();
+
+        for update in updates {
+            let update = update.context("Failed to parse pw-dump output")?;
+            if !update.iter().any(is_relevant) {
+                continue;
+            }
+
+            if let Err(e) = self.on_change(sink) {
+                warn!("Failed to update volume: {:#}", e);
+            }
+        }
+
+        let status = child.wait()?;
+        bail!("pw-dump exited with {}", status)
+    }
+}
+
+/// Node changes carry volume updates, metadata changes a new default sink
+fn is_relevant(object: &serde_json::Value) -> bool {
+    matches!(
+        object.get("type").and_then(|t| t.as_str()),
+        Some("PipeWire:Interface:Node" | "PipeWire:Interface:Metadata")
+    )
+}
+
+fn run_wpctl(args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("wpctl")
+        .args(args)
+        .output()
+        .context("Failed to run wpctl")?;
+
+    if !output.status.success() {
+        bail!("wpctl {} failed with {}", args.join(" "), output.status);
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse `Volume: 0.40 [MUTED]` into a percentage and mute state
+fn parse_volume(output: &str) -> Option<(i32, bool)> {
+    let rest = output.trim().strip_prefix("Volume:")?;
+    let mut parts = rest.split_whitespace();
+    let volume: f64 = parts.next()?.parse().ok()?;
+    let muted = parts.any(|part| part == "[MUTED]");
+
+    Some(((volume * 100.0).round() as i32, muted))
+}
+
+/// Find `key = "value"` in the output of `wpctl inspect`
+fn inspect_property(output: &str, key: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let line = line.trim_start().trim_start_matches('*').trim();
+        let value = line.strip_prefix(key)?.trim_start().strip_prefix('=')?;
+        Some(value.trim().trim_matches('"').to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_volume() {
+        assert_eq!(parse_volume("Volume: 0.40\n"), Some((40, false)));
+        assert_eq!(parse_volume("Volume: 1.25 [MUTED]\n"), Some((125, true)));
+        assert_eq!(parse_volume("Volume:"), None);
+        assert_eq!(parse_volume("Error: no such node"), None);
+    }
+
+    #[test]
+    fn finds_inspect_property() {
+        let output = r#"id 52, type PipeWire:Interface:Node
+    alsa.card_name = "HDA Intel PCH"
+  * node.description = "Built-in Audio Analog Stereo"
+    node.name = "alsa_output.pci-0000_00_1f.3.analog-stereo"
+"#;
+        assert_eq!(
+            inspect_property(output, "node.description").as_deref(),
+            Some("Built-in Audio Analog Stereo")
+        );
+        assert_eq!(
+            inspect_property(output, "node.name").as_deref(),
+            Some("alsa_output.pci-0000_00_1f.3.analog-stereo")
+        );
+        assert_eq!(inspect_property(output, "node.nick"), None);
+    }
+}
@@ -0,0 +1,15 @@
+use wayland_osd_core::OsdMessage;
+
+/// Where backends deliver the messages they produce
+pub type Sink = dyn Fn(OsdMessage) -> anyhow::Result<()> + Send + Sync;
+
+/// A source of system state changes that should be shown on the OSD
+///
+/// Each enabled backend runs on its own thread. `run` is expected to block for
+/// as long as the backend has something to watch and only return on error or
+/// when the watched device goes away.
+pub trait Backend: Send {
+    fn name(&self) -> &'static str;
+
+    fn run(&mut self, sink: &Sink) -> anyhow::Result<()>;
+}
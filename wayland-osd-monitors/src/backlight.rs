@@ -0,0 +1,63 @@
+use std::thread;
+
+use log::{debug, info, warn};
+use wayland_osd_core::OsdMessage;
+
+use crate::backend::{Backend, Sink};
+use crate::config::BacklightConfig;
+use crate::sysfs;
+
+const BACKLIGHT_CLASS: &str = "/sys/class/backlight";
+
+/// Polls a backlight device in sysfs for brightness changes
+///
+/// sysfs doesn't reliably emit inotify events for brightness, so the value is polled.
+pub struct BacklightBackend {
+    config: BacklightConfig,
+}
+
+impl BacklightBackend {
+    pub fn new(config: BacklightConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Backend for BacklightBackend {
+    fn name(&self) -> &'static str {
+        "backlight"
+    }
+
+    fn run(&mut self, sink: &Sink) -> anyhow::Result<()> {
+        let Some(device) =
+            sysfs::find_device(BACKLIGHT_CLASS, self.config.device.as_deref(), |_| true)?
+        else {
+            info!("No backlight device found, stopping");
+            return Ok(());
+        };
+
+        info!("Watching backlight {}", device.display());
+        let max = sysfs::read_number(&device, "max_brightness")?;
+        let mut last = sysfs::read_number(&device, "brightness")?;
+        debug!("Initial brightness: {}/{}", last, max);
+
+        loop {
+            thread::sleep(self.config.poll_interval());
+
+            let brightness = sysfs::read_number(&device, "brightness")?;
+            if brightness == last {
+                continue;
+            }
+            last = brightness;
+
+            info!("Brightness: {}/{}", brightness, max);
+            let result = sink(OsdMessage {
+                value: Some(brightness),
+                max_value: Some(max),
+                ..OsdMessage::new("brightness")
+            });
+            if let Err(e) = result {
+                warn!("Failed to send brightness: {:#}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::Path;
+use std::thread;
+
+use log::{debug, info, warn};
+use wayland_osd_core::OsdMessage;
+
+use crate::backend::{Backend, Sink};
+use crate::config::BatteryConfig;
+use crate::sysfs;
+
+const POWER_SUPPLY_CLASS: &str = "/sys/class/power_supply";
+
+/// Polls a battery in sysfs, reporting charger changes and low charge levels
+pub struct BatteryBackend {
+    config: BatteryConfig,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BatteryState {
+    capacity: i32,
+    /// `Charging`, `Discharging`, `Full` or `Not charging`
+    status: String,
+}
+
+impl BatteryState {
+    /// The `status` of a `battery` message, a charger that isn't charging counts as full
+    fn message_status(&self) -> &'static str {
+        match self.status.as_str() {
+            "Charging" => "charging",
+            "Full" | "Not charging" => "full",
+            _ => "discharging",
+        }
+    }
+}
+
+impl BatteryBackend {
+    pub fn new(config: BatteryConfig) -> Self {
+        Self { config }
+    }
+
+    fn read_state(device: &Path) -> anyhow::Result<BatteryState> {
+        Ok(BatteryState {
+            capacity: sysfs::read_number(device, "capacity")?,
+            status: sysfs::read_attribute(device, "status")?,
+        })
+    }
+
+    /// Returns the warning level crossed when discharging from `last` to `current`
+    fn crossed_warning_level(&self, last: &BatteryState, current: &BatteryState) -> Option<u8> {
+        if current.status != "Discharging" {
+            return None;
+        }
+
+        self.config
+            .warning_levels
+            .iter()
+            .copied()
+            .filter(|&level| current.capacity <= level as i32 && last.capacity > level as i32)
+            .min()
+    }
+}
+
+impl Backend for BatteryBackend {
+    fn name(&self) -> &'static str {
+        "battery"
+    }
+
+    fn run(&mut self, sink: &Sink) -> anyhow::Result<()> {
+        let is_battery = |device: &Path| {
+            sysfs::read_attribute(device, "type").is_ok_and(|kind| kind == "Battery")
+        };
+        let Some(device) = sysfs::find_device(
+            POWER_SUPPLY_CLASS,
+            self.config.device.as_deref(),
+            is_battery,
+        )?
+        else {
+            info!("No battery found, stopping");
+            return Ok(());
+        };
+
+        info!("Watching battery {}", device.display());
+        let mut last = Self::read_state(&device)?;
+        debug!("Initial battery state: {:?}", last);
+
+        loop {
+            thread::sleep(self.config.poll_interval());
+
+            let state = Self::read_state(&device)?;
+            if state == last {
+                continue;
+            }
+
+            let status_changed = state.status != last.status;
+            let critical = self.crossed_warning_level(&last, &state).is_some();
+            last = state;
+
+            if status_changed || critical {
+                info!("Battery: {} ({}%)", last.status, last.capacity);
+                let result = sink(OsdMessage {
+                    value: Some(last.capacity),
+                    max_value: Some(100),
+                    status: Some(last.message_status().to_string()),
+                    critical: Some(critical),
+                    ..OsdMessage::new("battery")
+                });
+                if let Err(e) = result {
+                    warn!("Failed to send battery state: {:#}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(capacity: i32, status: &str) -> BatteryState {
+        BatteryState {
+            capacity,
+            status: status.to_string(),
+        }
+    }
+
+    #[test]
+    fn warns_once_per_level_crossed() {
+        let backend = BatteryBackend::new(BatteryConfig::default());
+        let crossed = |last, current| backend.crossed_warning_level(&last, &current);
+
+        assert_eq!(
+            crossed(state(21, "Discharging"), state(20, "Discharging")),
+            Some(20)
+        );
+        assert_eq!(
+            crossed(state(20, "Discharging"), state(19, "Discharging")),
+            None
+        );
+        // Dropping past several levels at once warns about the lowest
+        assert_eq!(
+            crossed(state(12, "Discharging"), state(4, "Discharging")),
+            Some(5)
+        );
+        assert_eq!(crossed(state(21, "Charging"), state(20, "Charging")), None);
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use wayland_osd_core::config::load_file;
+
+/// The `[monitors]` section of the shared config file
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+struct ConfigFile {
+    monitors: MonitorsConfig,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct MonitorsConfig {
+    pub audio: AudioConfig,
+    pub backlight: BacklightConfig,
+    pub battery: BatteryConfig,
+    pub mpris: MprisConfig,
+}
+
+impl MonitorsConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(load_file::<ConfigFile>(path)?.monitors)
+    }
+}
+
+/// Renames a device whose name contains `match`
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeviceName {
+    #[serde(rename = "match")]
+    pub pattern: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub enabled: bool,
+    /// Include the default sink's name in volume messages
+    pub show_device_name: bool,
+    /// Display names for sinks, the first matching pattern wins
+    pub device_names: Vec<DeviceName>,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            show_device_name: false,
+            device_names: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BacklightConfig {
+    pub enabled: bool,
+    /// Name of the device under /sys/class/backlight, the first one found when unset
+    pub device: Option<String>,
+    pub poll_interval_ms: u64,
+}
+
+impl Default for BacklightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            device: None,
+            poll_interval_ms: 100,
+        }
+    }
+}
+
+impl BacklightConfig {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BatteryConfig {
+    pub enabled: bool,
+    /// Name of the device under /sys/class/power_supply, the first battery found when unset
+    pub device: Option<String>,
+    /// Charge levels in percent at which to warn while discharging
+    pub warning_levels: Vec<u8>,
+    pub poll_interval_ms: u64,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            device: None,
+            warning_levels: vec![20, 10, 5],
+            poll_interval_ms: 5000,
+        }
+    }
+}
+
+impl BatteryConfig {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MprisConfig {
+    pub enabled: bool,
+    /// Only follow this player, as named by `playerctl --list-all`, the active one when unset
+    pub player: Option<String>,
+}
+
+impl Default for MprisConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            player: None,
+        }
+    }
+}
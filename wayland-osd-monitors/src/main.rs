@@ -0,0 +1,94 @@
+mod audio;
+mod backend;
+mod backlight;
+mod battery;
+mod config;
+mod mpris;
+mod sysfs;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::Context;
+use clap::Parser;
+use env_logger::Env;
+use log::{error, info};
+use wayland_osd_core::ipc::{self, Instance};
+use wayland_osd_core::Config;
+
+use audio::AudioBackend;
+use backend::{Backend, Sink};
+use backlight::BacklightBackend;
+use battery::BatteryBackend;
+use config::MonitorsConfig;
+use mpris::MprisBackend;
+
+/// Watch system state and show changes on the wayland-osd server
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Config file to read the `[monitors]` section from
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Send to the server running as this named instance, and read its config file
+    #[arg(long)]
+    instance: Option<String>,
+}
+
+fn backends(config: MonitorsConfig) -> Vec<Box<dyn Backend>> {
+    let mut backends: Vec<Box<dyn Backend>> = Vec::new();
+
+    if config.audio.enabled {
+        backends.push(Box::new(AudioBackend::new(config.audio)));
+    }
+    if config.backlight.enabled {
+        backends.push(Box::new(BacklightBackend::new(config.backlight)));
+    }
+    if config.battery.enabled {
+        backends.push(Box::new(BatteryBackend::new(config.battery)));
+    }
+    if config.mpris.enabled {
+        backends.push(Box::new(MprisBackend::new(config.mpris)));
+    }
+
+    backends
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info"))
+        .format_timestamp_millis()
+        .format_module_path(true)
+        .init();
+
+    let cli = Cli::parse();
+    let instance = Instance::new(cli.instance)?;
+    let config = match cli.config.or_else(|| Config::instance_path(&instance)) {
+        Some(path) => MonitorsConfig::load(&path)?,
+        None => MonitorsConfig::default(),
+    };
+
+    let sink: Arc<Sink> = Arc::new(move |msg| ipc::send_message(&instance, &msg.to_json()));
+
+    let handles = backends(config)
+        .into_iter()
+        .map(|mut backend| {
+            let sink = sink.clone();
+            thread::Builder::new()
+                .name(backend.name().to_string())
+                .spawn(move || {
+                    info!("Starting {} monitor", backend.name());
+                    if let Err(e) = backend.run(&*sink) {
+                        error!("{} monitor stopped: {:#}", backend.name(), e);
+                    }
+                })
+                .context("Failed to spawn monitor thread")
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context};
+use log::{debug, info, warn};
+use wayland_osd_core::OsdMessage;
+
+use crate::backend::{Backend, Sink};
+use crate::config::MprisConfig;
+
+/// Metadata printed by `playerctl` for every change, separated by tabs
+const FORMAT: &str = "{{status}}\t{{title}}\t{{artist}}\t{{mpris:artUrl}}";
+
+/// Follows MPRIS media players for track and playback changes
+///
+/// `playerctl --follow` prints the metadata of the active player whenever it
+/// changes, so players coming and going on D-Bus don't have to be tracked here.
+pub struct MprisBackend {
+    config: MprisConfig,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Track {
+    playback: String,
+    title: String,
+    artist: Option<String>,
+    artwork: Option<String>,
+}
+
+impl MprisBackend {
+    pub fn new(config: MprisConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Backend for MprisBackend {
+    fn name(&self) -> &'static str {
+        "mpris"
+    }
+
+    fn run(&mut self, sink: &Sink) -> anyhow::Result<()> {
+        let mut command = Command::new("playerctl");
+        if let Some(player) = &self.config.player {
+            command.args(["--player", player]);
+        }
+        let mut child = command
+            .args(["--follow", "metadata", "--format", FORMAT])
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to start playerctl")?;
+
+        let stdout = child.stdout.take().context("playerctl has no stdout")?;
+        let mut lines = BufReader::new(stdout).lines();
+
+        // The first line only establishes the baseline
+        let mut last = match lines.next() {
+            Some(line) => parse_track(&line.context("Failed to read playerctl output")?),
+            None => None,
+        };
+        debug!("Initial track: {:?}", last);
+
+        for line in lines {
+            let track = parse_track(&line.context("Failed to read playerctl output")?);
+            if track == last {
+                continue;
+            }
+            last = track.clone();
+
+            let Some(track) = track else {
+                debug!("No player has a track anymore");
+                continue;
+            };
+            info!("Media: {} ({})", track.title, track.playback);
+            let result = sink(OsdMessage {
+                title: Some(track.title),
+                artist: track.artist,
+                artwork: track.artwork,
+                playback: Some(track.playback),
+                ..OsdMessage::new("media")
+            });
+            if let Err(e) = result {
+                warn!("Failed to send track: {:#}", e);
+            }
+        }
+
+        let status = child.wait()?;
+        bail!("playerctl exited with {}", status)
+    }
+}
+
+/// Parse a line of [`FORMAT`], `None` while no player has a track
+fn parse_track(line: &str) -> Option<Track> {
+    let mut fields = line.split('\t');
+    let non_empty =
+        |field: Option<&str>| field.filter(|field| !field.is_empty()).map(str::to_string);
+
+    // `Playing`, `Paused` or `Stopped`
+    let playback = fields.next()?.to_lowercase();
+    let title = non_empty(fields.next())?;
+
+    Some(Track {
+        playback,
+        title,
+        artist: non_empty(fields.next()),
+        artwork: non_empty(fields.next()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_track() {
+        assert_eq!(
+            parse_track("Playing\tRoygbiv\tBoards of Canada\tfile:///tmp/cover.jpg"),
+            Some(Track {
+                playback: "playing".to_string(),
+                title: "Roygbiv".to_string(),
+                artist: Some("Boards of Canada".to_string()),
+                artwork: Some("file:///tmp/cover.jpg".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_none() {
+        let track = parse_track("Paused\tRadio stream\t\t").unwrap();
+        assert_eq!(track.playback, "paused");
+        assert_eq!(track.artist, None);
+        assert_eq!(track.artwork, None);
+
+        let track = parse_track("Stopped\tUntitled").unwrap();
+        assert_eq!(track.artist, None);
+    }
+
+    #[test]
+    fn no_track_without_title() {
+        assert_eq!(parse_track("Stopped\t\t\t"), None);
+        assert_eq!(parse_track(""), None);
+    }
+}
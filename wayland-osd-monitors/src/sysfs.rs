@@ -0,0 +1,45 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// Read a sysfs attribute, trimming the trailing newline
+pub fn read_attribute(device: &Path, attribute: &str) -> anyhow::Result<String> {
+    let path = device.join(attribute);
+    let value =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(value.trim().to_string())
+}
+
+pub fn read_number(device: &Path, attribute: &str) -> anyhow::Result<i32> {
+    let value = read_attribute(device, attribute)?;
+    value
+        .parse()
+        .with_context(|| format!("Invalid {} value: {}", attribute, value))
+}
+
+/// Resolve a device in a sysfs class, either by name or as the first one accepted by `filter`
+pub fn find_device(
+    class_dir: &str,
+    name: Option<&str>,
+    filter: impl Fn(&Path) -> bool,
+) -> anyhow::Result<Option<PathBuf>> {
+    let class_dir = Path::new(class_dir);
+
+    if let Some(name) = name {
+        let device = class_dir.join(name);
+        return Ok(device.exists().then_some(device));
+    }
+
+    let Ok(entries) = fs::read_dir(class_dir) else {
+        return Ok(None);
+    };
+
+    let mut devices = entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to list {}", class_dir.display()))?;
+    devices.sort();
+
+    Ok(devices.into_iter().find(|device| filter(device)))
+}
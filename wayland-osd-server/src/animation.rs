@@ -0,0 +1,130 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk::{glib, prelude::*};
+use log::trace;
+use wayland_osd_core::animation::{Easing, Tween};
+
+struct Running {
+    tween: Tween,
+    /// Frame time of the first tick, in microseconds
+    start: Option<i64>,
+    last_frame: Option<i64>,
+}
+
+struct Shared {
+    running: Option<Running>,
+    ticking: bool,
+    apply: Box<dyn Fn(f64)>,
+}
+
+/// Animates a single value on a widget's frame clock
+///
+/// Updates happen in step with the compositor's refresh instead of on a timer,
+/// and can be capped to a maximum rate to save power.
+pub struct Animation {
+    widget: gtk::Widget,
+    min_frame_interval: Option<Duration>,
+    easing: Easing,
+    shared: Rc<RefCell<Shared>>,
+}
+
+impl Animation {
+    pub fn new(
+        widget: &impl IsA<gtk::Widget>,
+        min_frame_interval: Option<Duration>,
+        apply: impl Fn(f64) + 'static,
+    ) -> Self {
+        Self {
+            widget: widget.clone().upcast(),
+            min_frame_interval,
+            easing: Easing::Linear,
+            shared: Rc::new(RefCell::new(Shared {
+                running: None,
+                ticking: false,
+                apply: Box::new(apply),
+            })),
+        }
+    }
+
+    pub fn with_easing(self, easing: Easing) -> Self {
+        Self { easing, ..self }
+    }
+
+    /// Transition from `from` to `to`, replacing any animation in progress
+    ///
+    /// Passing the currently shown value as `from` keeps rapid updates fluid,
+    /// the value just heads for the new target.
+    ///
+    /// Unmapped widgets don't receive frame ticks, so they jump straight to the target.
+    pub fn animate(&self, from: f64, to: f64, duration: Duration) {
+        if duration.is_zero() || !self.widget.is_mapped() {
+            self.set(to);
+            return;
+        }
+
+        let mut shared = self.shared.borrow_mut();
+        shared.running = Some(Running {
+            tween: Tween::new(from, to, duration).with_easing(self.easing),
+            start: None,
+            last_frame: None,
+        });
+
+        if !shared.ticking {
+            shared.ticking = true;
+            self.add_tick_callback();
+        }
+    }
+
+    /// Stop any animation in progress and apply `value` right away
+    pub fn set(&self, value: f64) {
+        let mut shared = self.shared.borrow_mut();
+        shared.running = None;
+        (shared.apply)(value);
+    }
+
+    fn add_tick_callback(&self) {
+        let shared = self.shared.clone();
+        let min_frame_interval = self
+            .min_frame_interval
+            .map(|interval| interval.as_micros() as i64);
+
+        self.widget.add_tick_callback(move |_widget, clock| {
+            let mut shared = shared.borrow_mut();
+            let frame_time = clock.frame_time();
+
+            let Some(running) = shared.running.as_mut() else {
+                shared.ticking = false;
+                return glib::ControlFlow::Break;
+            };
+
+            let start = *running.start.get_or_insert(frame_time);
+            let elapsed = Duration::from_micros((frame_time - start).max(0) as u64);
+            let finished = running.tween.is_finished(elapsed);
+
+            // Skip frames above the cap, but always land on the final value
+            if let (Some(interval), Some(last_frame)) = (min_frame_interval, running.last_frame) {
+                if !finished && frame_time - last_frame < interval {
+                    return glib::ControlFlow::Continue;
+                }
+            }
+            running.last_frame = Some(frame_time);
+
+            let value = running.tween.value_at(elapsed);
+            trace!("Animation frame at {:?}: {}", elapsed, value);
+
+            if finished {
+                shared.running = None;
+                shared.ticking = false;
+            }
+            (shared.apply)(value);
+
+            if finished {
+                glib::ControlFlow::Break
+            } else {
+                glib::ControlFlow::Continue
+            }
+        });
+    }
+}
@@ -0,0 +1,73 @@
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, error, warn};
+
+/// Layer-shell namespace of the OSD windows, which compositor rules can match
+pub const NAMESPACE: &str = "wayland-osd";
+
+/// How long Hyprland gets to answer a command
+const HYPRLAND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Ask the compositor to blur what is behind the OSD
+///
+/// Only Hyprland can be asked at runtime, through its IPC socket. Elsewhere
+/// it is up to the compositor's own rules for [`NAMESPACE`], if it has any.
+/// KDE's `org_kde_kwin_blur` protocol isn't supported, it would need Wayland
+/// bindings the server doesn't have.
+///
+/// The request is made on a thread of its own, so a stuck compositor can't
+/// hold up the UI.
+pub fn request_blur() {
+    let Some(socket) = hyprland_socket() else {
+        debug!("Not running on Hyprland, leaving blur to the compositor");
+        return;
+    };
+
+    let spawned = thread::Builder::new()
+        .name("hyprland-blur".to_string())
+        .spawn(move || {
+            // `ignorezero` keeps the transparent surface of a hidden OSD from being blurred
+            for rule in ["blur", "ignorezero"] {
+                let command = format!("keyword layerrule {},{}", rule, NAMESPACE);
+                if let Err(e) = hyprland_command(&socket, &command) {
+                    warn!("Failed to request blur from Hyprland: {}", e);
+                    return;
+                }
+            }
+            debug!("Requested blur from Hyprland");
+        });
+    if let Err(e) = spawned {
+        error!("Failed to spawn Hyprland blur thread: {}", e);
+    }
+}
+
+/// The command socket of the Hyprland instance we run on, if any
+fn hyprland_socket() -> Option<PathBuf> {
+    let signature = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE")?;
+
+    // In the runtime dir since Hyprland 0.40, in /tmp before
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|dir| PathBuf::from(dir).join("hypr"))
+        .into_iter()
+        .chain([PathBuf::from("/tmp/hypr")])
+        .map(|dir| dir.join(&signature).join(".socket.sock"))
+        .find(|path| path.exists())
+}
+
+fn hyprland_command(socket: &Path, command: &str) -> io::Result<()> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(HYPRLAND_TIMEOUT))?;
+    stream.set_write_timeout(Some(HYPRLAND_TIMEOUT))?;
+    stream.write_all(command.as_bytes())?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    if reply.trim() != "ok" {
+        return Err(io::Error::other(reply));
+    }
+    Ok(())
+}
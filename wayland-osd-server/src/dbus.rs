@@ -0,0 +1,130 @@
+use gtk::gio::{prelude::DBusMethodCall, DBusError, DBusNodeInfo};
+use gtk::{glib, prelude::*};
+use log::{debug, error};
+use wayland_osd_core::OsdMessage;
+
+/// Interface exported next to GApplication's own on the application's object path
+pub const INTERFACE_NAME: &str = "org.wayland.osd";
+
+const INTERFACE_XML: &str = r#"
+<node>
+  <interface name="org.wayland.osd">
+    <method name="ShowVolume">
+      <arg type="i" name="value" direction="in"/>
+      <arg type="i" name="max" direction="in"/>
+      <arg type="b" name="muted" direction="in"/>
+      <arg type="s" name="device" direction="in"/>
+    </method>
+    <method name="ShowBrightness">
+      <arg type="i" name="value" direction="in"/>
+      <arg type="i" name="max" direction="in"/>
+    </method>
+    <method name="ShowText">
+      <arg type="s" name="text" direction="in"/>
+    </method>
+    <method name="Hide"/>
+    <method name="GetStats">
+      <arg type="s" name="stats" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+/// A method called on the D-Bus interface
+#[derive(Debug)]
+pub enum Request {
+    Show(Box<OsdMessage>),
+    Hide,
+    /// Answered with the server's status as JSON
+    GetStats,
+}
+
+impl DBusMethodCall for Request {
+    fn parse_call(
+        _obj_path: &str,
+        _interface: Option<&str>,
+        method: &str,
+        params: glib::Variant,
+    ) -> Result<Self, glib::Error> {
+        let invalid_args = || {
+            glib::Error::new(
+                DBusError::InvalidArgs,
+                &format!("Invalid arguments for {}: {}", method, params.type_()),
+            )
+        };
+
+        let request = match method {
+            "ShowVolume" => {
+                let (value, max, muted, device) = params
+                    .get::<(i32, i32, bool, String)>()
+                    .ok_or_else(invalid_args)?;
+                Request::Show(Box::new(OsdMessage {
+                    value: Some(value),
+                    max_value: Some(max),
+                    muted: Some(muted),
+                    // An empty device leaves out the caption
+                    device_name: (!device.is_empty()).then_some(device),
+                    ..OsdMessage::new("volume")
+                }))
+            }
+            "ShowBrightness" => {
+                let (value, max) = params.get::<(i32, i32)>().ok_or_else(invalid_args)?;
+                Request::Show(Box::new(OsdMessage {
+                    value: Some(value),
+                    max_value: Some(max),
+                    ..OsdMessage::new("brightness")
+                }))
+            }
+            "ShowText" => {
+                let (text,) = params.get::<(String,)>().ok_or_else(invalid_args)?;
+                Request::Show(Box::new(OsdMessage {
+                    text: Some(text),
+                    ..OsdMessage::new("text")
+                }))
+            }
+            "Hide" => Request::Hide,
+            "GetStats" => Request::GetStats,
+            _ => {
+                return Err(glib::Error::new(
+                    DBusError::UnknownMethod,
+                    &format!("Unknown method {}", method),
+                ))
+            }
+        };
+
+        Ok(request)
+    }
+}
+
+/// Export the interface on the application's session bus connection
+///
+/// Calls are dispatched on the main loop, so `on_request` runs in order with
+/// everything else touching the UI. It returns the out arguments of the method, if any.
+pub fn export(
+    app: &gtk::Application,
+    on_request: impl Fn(Request) -> Option<glib::Variant> + 'static,
+) {
+    let (Some(connection), Some(path)) = (app.dbus_connection(), app.dbus_object_path()) else {
+        debug!("Not on the session bus, skipping D-Bus interface");
+        return;
+    };
+
+    let interface = DBusNodeInfo::for_xml(INTERFACE_XML)
+        .ok()
+        .and_then(|node| node.lookup_interface(INTERFACE_NAME))
+        .expect("D-Bus interface description is valid");
+
+    let registered = connection
+        .register_object(&path, &interface)
+        .typed_method_call::<Request>()
+        .invoke_and_return(move |_, sender, request| {
+            debug!("D-Bus call from {:?}: {:?}", sender, request);
+            Ok(on_request(request))
+        })
+        .build();
+
+    match registered {
+        Ok(_) => debug!("Exported {} on {}", INTERFACE_NAME, path),
+        Err(e) => error!("Failed to export D-Bus interface: {}", e),
+    }
+}
@@ -0,0 +1,403 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+use gtk::{gdk, gdk_pixbuf, gio, glib, prelude::*};
+use log::warn;
+use wayland_osd_core::config::IconPack;
+use wayland_osd_core::{CustomIcon, IconId};
+
+// Embed SVG files, the default pack is always included
+const ICON_VOLUME_HIGH: &str = include_str!("../assets/sink-volume-high-symbolic.svg");
+const ICON_VOLUME_MEDIUM: &str = include_str!("../assets/sink-volume-medium-symbolic.svg");
+const ICON_VOLUME_LOW: &str = include_str!("../assets/sink-volume-low-symbolic.svg");
+const ICON_VOLUME_MUTED: &str = include_str!("../assets/sink-volume-muted-symbolic.svg");
+const ICON_VOLUME_OVERAMPLIFIED: &str =
+    include_str!("../assets/sink-volume-overamplified-symbolic.svg");
+const ICON_BRIGHTNESS_HIGH: &str = include_str!("../assets/display-brightness-high-symbolic.svg");
+const ICON_BRIGHTNESS_MEDIUM: &str =
+    include_str!("../assets/display-brightness-medium-symbolic.svg");
+const ICON_BRIGHTNESS_LOW: &str = include_str!("../assets/display-brightness-low-symbolic.svg");
+const ICON_HEADPHONES: &str = include_str!("../assets/headphones-symbolic.svg");
+const ICON_HEADSET: &str = include_str!("../assets/headset-symbolic.svg");
+const ICON_TV: &str = include_str!("../assets/video-display-symbolic.svg");
+const ICON_USB_DAC: &str = include_str!("../assets/audio-card-symbolic.svg");
+const ICON_BLUETOOTH: &str = include_str!("../assets/bluetooth-symbolic.svg");
+const ICON_CAPS_LOCK: &str = include_str!("../assets/caps-lock-symbolic.svg");
+const ICON_NUM_LOCK: &str = include_str!("../assets/num-lock-symbolic.svg");
+const ICON_SCROLL_LOCK: &str = include_str!("../assets/scroll-lock-symbolic.svg");
+const ICON_KEYBOARD: &str = include_str!("../assets/input-keyboard-symbolic.svg");
+const ICON_MIC_HIGH: &str = include_str!("../assets/source-volume-high-symbolic.svg");
+const ICON_MIC_MEDIUM: &str = include_str!("../assets/source-volume-medium-symbolic.svg");
+const ICON_MIC_LOW: &str = include_str!("../assets/source-volume-low-symbolic.svg");
+const ICON_MIC_MUTED: &str = include_str!("../assets/source-volume-muted-symbolic.svg");
+const ICON_BATTERY_FULL: &str = include_str!("../assets/battery-full-symbolic.svg");
+const ICON_BATTERY_CHARGING: &str = include_str!("../assets/battery-good-charging-symbolic.svg");
+const ICON_BATTERY_DISCHARGING: &str = include_str!("../assets/battery-good-symbolic.svg");
+const ICON_MEDIA_PLAYING: &str = include_str!("../assets/media-playback-start-symbolic.svg");
+const ICON_MEDIA_PAUSED: &str = include_str!("../assets/media-playback-pause-symbolic.svg");
+const ICON_MEDIA_STOPPED: &str = include_str!("../assets/media-playback-stop-symbolic.svg");
+const ICON_WIRELESS_EXCELLENT: &str =
+    include_str!("../assets/network-wireless-signal-excellent-symbolic.svg");
+const ICON_WIRELESS_GOOD: &str =
+    include_str!("../assets/network-wireless-signal-good-symbolic.svg");
+const ICON_WIRELESS_OK: &str = include_str!("../assets/network-wireless-signal-ok-symbolic.svg");
+const ICON_WIRELESS_WEAK: &str =
+    include_str!("../assets/network-wireless-signal-weak-symbolic.svg");
+const ICON_WIRED: &str = include_str!("../assets/network-wired-symbolic.svg");
+const ICON_OFFLINE: &str = include_str!("../assets/network-offline-symbolic.svg");
+const ICON_BLUETOOTH_DISCONNECTED: &str = include_str!("../assets/bluetooth-disabled-symbolic.svg");
+const ICON_POWER_PERFORMANCE: &str =
+    include_str!("../assets/power-profile-performance-symbolic.svg");
+const ICON_POWER_BALANCED: &str = include_str!("../assets/power-profile-balanced-symbolic.svg");
+const ICON_POWER_SAVER: &str = include_str!("../assets/power-profile-power-saver-symbolic.svg");
+
+fn icon_data(icon: IconId) -> &'static str {
+    match icon {
+        IconId::VolumeHigh => ICON_VOLUME_HIGH,
+        IconId::VolumeMedium => ICON_VOLUME_MEDIUM,
+        IconId::VolumeLow => ICON_VOLUME_LOW,
+        IconId::VolumeMuted => ICON_VOLUME_MUTED,
+        IconId::VolumeOveramplified => ICON_VOLUME_OVERAMPLIFIED,
+        IconId::BrightnessHigh => ICON_BRIGHTNESS_HIGH,
+        IconId::BrightnessMedium => ICON_BRIGHTNESS_MEDIUM,
+        IconId::BrightnessLow => ICON_BRIGHTNESS_LOW,
+        IconId::Headphones => ICON_HEADPHONES,
+        IconId::Headset => ICON_HEADSET,
+        IconId::Tv => ICON_TV,
+        IconId::UsbDac => ICON_USB_DAC,
+        IconId::Bluetooth => ICON_BLUETOOTH,
+        IconId::CapsLock => ICON_CAPS_LOCK,
+        IconId::NumLock => ICON_NUM_LOCK,
+        IconId::ScrollLock => ICON_SCROLL_LOCK,
+        IconId::Keyboard => ICON_KEYBOARD,
+        IconId::MicHigh => ICON_MIC_HIGH,
+        IconId::MicMedium => ICON_MIC_MEDIUM,
+        IconId::MicLow => ICON_MIC_LOW,
+        IconId::MicMuted => ICON_MIC_MUTED,
+        IconId::BatteryFull => ICON_BATTERY_FULL,
+        IconId::BatteryCharging => ICON_BATTERY_CHARGING,
+        IconId::BatteryDischarging => ICON_BATTERY_DISCHARGING,
+        IconId::MediaPlaying => ICON_MEDIA_PLAYING,
+        IconId::MediaPaused => ICON_MEDIA_PAUSED,
+        IconId::MediaStopped => ICON_MEDIA_STOPPED,
+        IconId::NetworkWirelessExcellent => ICON_WIRELESS_EXCELLENT,
+        IconId::NetworkWirelessGood => ICON_WIRELESS_GOOD,
+        IconId::NetworkWirelessOk => ICON_WIRELESS_OK,
+        IconId::NetworkWirelessWeak => ICON_WIRELESS_WEAK,
+        IconId::NetworkWired => ICON_WIRED,
+        IconId::NetworkOffline => ICON_OFFLINE,
+        IconId::BluetoothDisconnected => ICON_BLUETOOTH_DISCONNECTED,
+        IconId::PowerPerformance => ICON_POWER_PERFORMANCE,
+        IconId::PowerBalanced => ICON_POWER_BALANCED,
+        IconId::PowerSaver => ICON_POWER_SAVER,
+    }
+}
+
+/// Name of the icon in freedesktop icon themes
+fn theme_name(icon: IconId) -> &'static str {
+    match icon {
+        IconId::VolumeHigh => "audio-volume-high-symbolic",
+        IconId::VolumeMedium => "audio-volume-medium-symbolic",
+        IconId::VolumeLow => "audio-volume-low-symbolic",
+        IconId::VolumeMuted => "audio-volume-muted-symbolic",
+        IconId::VolumeOveramplified => "audio-volume-overamplified-symbolic",
+        IconId::BrightnessHigh => "display-brightness-high-symbolic",
+        IconId::BrightnessMedium => "display-brightness-medium-symbolic",
+        IconId::BrightnessLow => "display-brightness-low-symbolic",
+        IconId::Headphones => "audio-headphones-symbolic",
+        IconId::Headset => "audio-headset-symbolic",
+        IconId::Tv => "video-display-symbolic",
+        IconId::UsbDac => "audio-card-symbolic",
+        IconId::Bluetooth => "bluetooth-active-symbolic",
+        IconId::CapsLock => "caps-lock-symbolic",
+        IconId::NumLock => "num-lock-symbolic",
+        IconId::ScrollLock => "scroll-lock-symbolic",
+        IconId::Keyboard => "input-keyboard-symbolic",
+        IconId::MicHigh => "microphone-sensitivity-high-symbolic",
+        IconId::MicMedium => "microphone-sensitivity-medium-symbolic",
+        IconId::MicLow => "microphone-sensitivity-low-symbolic",
+        IconId::MicMuted => "microphone-sensitivity-muted-symbolic",
+        IconId::BatteryFull => "battery-full-charged-symbolic",
+        IconId::BatteryCharging => "battery-good-charging-symbolic",
+        IconId::BatteryDischarging => "battery-good-symbolic",
+        IconId::MediaPlaying => "media-playback-start-symbolic",
+        IconId::MediaPaused => "media-playback-pause-symbolic",
+        IconId::MediaStopped => "media-playback-stop-symbolic",
+        IconId::NetworkWirelessExcellent => "network-wireless-signal-excellent-symbolic",
+        IconId::NetworkWirelessGood => "network-wireless-signal-good-symbolic",
+        IconId::NetworkWirelessOk => "network-wireless-signal-ok-symbolic",
+        IconId::NetworkWirelessWeak => "network-wireless-signal-weak-symbolic",
+        IconId::NetworkWired => "network-wired-symbolic",
+        IconId::NetworkOffline => "network-offline-symbolic",
+        IconId::BluetoothDisconnected => "bluetooth-disabled-symbolic",
+        IconId::PowerPerformance => "power-profile-performance-symbolic",
+        IconId::PowerBalanced => "power-profile-balanced-symbolic",
+        IconId::PowerSaver => "power-profile-power-saver-symbolic",
+    }
+}
+
+/// Angular icons with straight volume bars
+#[cfg(feature = "icon-pack-sharp")]
+mod sharp {
+    use wayland_osd_core::IconId;
+
+    const ICON_VOLUME_HIGH: &str = include_str!("../assets/sharp/sink-volume-high-symbolic.svg");
+    const ICON_VOLUME_MEDIUM: &str =
+        include_str!("../assets/sharp/sink-volume-medium-symbolic.svg");
+    const ICON_VOLUME_LOW: &str = include_str!("../assets/sharp/sink-volume-low-symbolic.svg");
+    const ICON_VOLUME_MUTED: &str = include_str!("../assets/sharp/sink-volume-muted-symbolic.svg");
+    const ICON_VOLUME_OVERAMPLIFIED: &str =
+        include_str!("../assets/sharp/sink-volume-overamplified-symbolic.svg");
+    const ICON_BRIGHTNESS_HIGH: &str =
+        include_str!("../assets/sharp/display-brightness-high-symbolic.svg");
+    const ICON_BRIGHTNESS_MEDIUM: &str =
+        include_str!("../assets/sharp/display-brightness-medium-symbolic.svg");
+    const ICON_BRIGHTNESS_LOW: &str =
+        include_str!("../assets/sharp/display-brightness-low-symbolic.svg");
+    const ICON_HEADPHONES: &str = include_str!("../assets/sharp/headphones-symbolic.svg");
+    const ICON_HEADSET: &str = include_str!("../assets/sharp/headset-symbolic.svg");
+    const ICON_TV: &str = include_str!("../assets/sharp/video-display-symbolic.svg");
+    const ICON_USB_DAC: &str = include_str!("../assets/sharp/audio-card-symbolic.svg");
+    const ICON_BLUETOOTH: &str = include_str!("../assets/sharp/bluetooth-symbolic.svg");
+
+    pub fn icon_data(icon: IconId) -> &'static str {
+        match icon {
+            IconId::VolumeHigh => ICON_VOLUME_HIGH,
+            IconId::VolumeMedium => ICON_VOLUME_MEDIUM,
+            IconId::VolumeLow => ICON_VOLUME_LOW,
+            IconId::VolumeMuted => ICON_VOLUME_MUTED,
+            IconId::VolumeOveramplified => ICON_VOLUME_OVERAMPLIFIED,
+            IconId::BrightnessHigh => ICON_BRIGHTNESS_HIGH,
+            IconId::BrightnessMedium => ICON_BRIGHTNESS_MEDIUM,
+            IconId::BrightnessLow => ICON_BRIGHTNESS_LOW,
+            IconId::Headphones => ICON_HEADPHONES,
+            IconId::Headset => ICON_HEADSET,
+            IconId::Tv => ICON_TV,
+            IconId::UsbDac => ICON_USB_DAC,
+            IconId::Bluetooth => ICON_BLUETOOTH,
+            // Not part of the pack
+            IconId::CapsLock
+            | IconId::NumLock
+            | IconId::ScrollLock
+            | IconId::Keyboard
+            | IconId::MicHigh
+            | IconId::MicMedium
+            | IconId::MicLow
+            | IconId::MicMuted
+            | IconId::BatteryFull
+            | IconId::BatteryCharging
+            | IconId::BatteryDischarging
+            | IconId::MediaPlaying
+            | IconId::MediaPaused
+            | IconId::MediaStopped
+            | IconId::NetworkWirelessExcellent
+            | IconId::NetworkWirelessGood
+            | IconId::NetworkWirelessOk
+            | IconId::NetworkWirelessWeak
+            | IconId::NetworkWired
+            | IconId::NetworkOffline
+            | IconId::BluetoothDisconnected
+            | IconId::PowerPerformance
+            | IconId::PowerBalanced
+            | IconId::PowerSaver => super::icon_data(icon),
+        }
+    }
+}
+
+/// The pack icons are actually taken from, falling back to the default one
+/// when the configured pack wasn't compiled in
+fn available_pack(pack: IconPack) -> IconPack {
+    match pack {
+        IconPack::Default => IconPack::Default,
+        #[cfg(feature = "icon-pack-sharp")]
+        IconPack::Sharp => IconPack::Sharp,
+        #[cfg(not(feature = "icon-pack-sharp"))]
+        IconPack::Sharp => {
+            warn!("Icon pack `sharp` needs the `icon-pack-sharp` feature, using the default icons");
+            IconPack::Default
+        }
+    }
+}
+
+/// Built-in icons decoded so far, reused while volume keys repeat
+///
+/// Icons are keyed by the color and scale they were loaded for, which only
+/// change with the stylesheet or the output.
+pub struct IconCache {
+    pack: IconPack,
+    use_theme: bool,
+    /// Size in pixels icons are drawn at
+    size: i32,
+    icons: RefCell<HashMap<(IconId, String, i32), gdk::Paintable>>,
+}
+
+impl IconCache {
+    /// Take icons from `pack`, or from the icon theme first if `use_theme`
+    pub fn new(pack: IconPack, use_theme: bool, size: i32) -> Self {
+        Self {
+            pack: available_pack(pack),
+            use_theme,
+            size,
+            icons: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The icon as it should be drawn in `widget`
+    pub fn get(&self, icon: IconId, widget: &impl IsA<gtk::Widget>) -> gdk::Paintable {
+        #[allow(deprecated)]
+        let color = widget.style_context().color().to_string();
+        let key = (icon, color, widget.scale_factor());
+
+        self.icons
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with_key(|(icon, color, _)| {
+                load_icon(self.pack, *icon, self.use_theme, color, self.size, widget)
+            })
+            .clone()
+    }
+
+    /// Resolve an icon a message asked for, `None` if it can't be found
+    pub fn custom(
+        &self,
+        icon: &CustomIcon,
+        widget: &impl IsA<gtk::Widget>,
+    ) -> Option<gdk::Paintable> {
+        load_custom_icon(icon, self.size, widget)
+    }
+}
+
+/// A built-in icon, from the icon theme if `use_theme` and the theme has it
+///
+/// Theme icons are symbolic and follow the CSS `color` of the widget on their
+/// own, the embedded ones are recolored to `color` when decoded.
+fn load_icon(
+    pack: IconPack,
+    icon: IconId,
+    use_theme: bool,
+    color: &str,
+    size: i32,
+    widget: &impl IsA<gtk::Widget>,
+) -> gdk::Paintable {
+    // Packs are chosen for their look, so only the default one gives way to the theme
+    if use_theme && pack == IconPack::Default {
+        if let Some(paintable) = lookup_theme_icon(theme_name(icon), size, widget) {
+            return paintable;
+        }
+    }
+
+    let data = match pack {
+        #[cfg(feature = "icon-pack-sharp")]
+        IconPack::Sharp => sharp::icon_data(icon),
+        _ => icon_data(icon),
+    };
+    let bytes = glib::Bytes::from_owned(recolor(data, color).into_bytes());
+    let stream = gio::MemoryInputStream::from_bytes(&bytes);
+    let size = pixel_size(size, widget);
+    let pixbuf =
+        gdk_pixbuf::Pixbuf::from_stream_at_scale(&stream, size, size, true, gio::Cancellable::NONE)
+            .expect("Failed to load icon");
+    gdk::Texture::for_pixbuf(&pixbuf).upcast()
+}
+
+/// Size in device pixels icons are rendered at, so they stay sharp on scaled outputs
+///
+/// Fractional scales report the next integer scale, the compositor scales the
+/// surface down from there.
+fn pixel_size(size: i32, widget: &impl IsA<gtk::Widget>) -> i32 {
+    size * widget.scale_factor()
+}
+
+/// Foreground colors the embedded icons are drawn in
+const ICON_COLORS: [&str; 5] = ["#ffffff", "white", "#666666", "#555761", "#2e3436"];
+
+/// Replace the foreground color of an embedded icon, in attributes and styles
+fn recolor(svg: &str, color: &str) -> String {
+    let mut svg = svg.to_string();
+    for property in ["fill", "stroke"] {
+        for from in ICON_COLORS {
+            svg = svg
+                .replace(
+                    &format!("{}=\"{}\"", property, from),
+                    &format!("{}=\"{}\"", property, color),
+                )
+                .replace(
+                    &format!("{}:{}", property, from),
+                    &format!("{}:{}", property, color),
+                );
+        }
+    }
+    svg
+}
+
+fn lookup_theme_icon(
+    name: &str,
+    size: i32,
+    widget: &impl IsA<gtk::Widget>,
+) -> Option<gdk::Paintable> {
+    let theme = gtk::IconTheme::for_display(&widget.display());
+    if !theme.has_icon(name) {
+        return None;
+    }
+
+    let paintable = theme.lookup_icon(
+        name,
+        &[],
+        size,
+        widget.scale_factor(),
+        widget.direction(),
+        gtk::IconLookupFlags::empty(),
+    );
+    Some(paintable.upcast())
+}
+
+fn load_custom_icon(
+    icon: &CustomIcon,
+    size: i32,
+    widget: &impl IsA<gtk::Widget>,
+) -> Option<gdk::Paintable> {
+    match icon {
+        CustomIcon::Named(name) => {
+            let paintable = lookup_theme_icon(name, size, widget);
+            if paintable.is_none() {
+                warn!("Icon `{}` isn't in the icon theme", name);
+            }
+            paintable
+        }
+        CustomIcon::File(path) => {
+            let size = pixel_size(size, widget);
+            match gdk_pixbuf::Pixbuf::from_file_at_scale(path, size, size, true) {
+                Ok(pixbuf) => Some(gdk::Texture::for_pixbuf(&pixbuf).upcast()),
+                Err(e) => {
+                    warn!("Failed to load icon {}: {}", path.display(), e);
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Load the cover of a media message from a path or URI, `None` if it can't be read
+pub async fn load_artwork(artwork: &str) -> Option<gdk::Texture> {
+    let file = if Path::new(artwork).is_absolute() {
+        gio::File::for_path(artwork)
+    } else {
+        gio::File::for_uri(artwork)
+    };
+
+    let bytes = match file.load_bytes_future().await {
+        Ok((bytes, _)) => bytes,
+        Err(e) => {
+            warn!("Failed to load artwork {}: {}", artwork, e);
+            return None;
+        }
+    };
+    match gdk::Texture::from_bytes(&bytes) {
+        Ok(texture) => Some(texture),
+        Err(e) => {
+            warn!("Failed to decode artwork {}: {}", artwork, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use log::{debug, info};
+use nix::errno::Errno;
+use nix::fcntl::{flock, FlockArg};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+
+/// How long `--replace` waits for the old server to exit
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Held for as long as the server runs, so a second one can't take over its endpoints
+///
+/// The lock file contains the pid of the server holding it.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Lock the instance, failing if another server holds it
+    ///
+    /// With `replace`, the other server is asked to exit with `SIGTERM` instead
+    /// and the lock is taken over once it has.
+    pub fn acquire(path: &Path, replace: bool) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+
+        if !try_lock(&file)? {
+            let pid = read_pid(&mut file);
+            let running = pid
+                .map(|pid| format!("(pid {})", pid))
+                .unwrap_or_else(|| "(unknown pid)".to_string());
+
+            let Some(pid) = pid.filter(|_| replace) else {
+                bail!(
+                    "Another server is already running {}, use --replace to take over",
+                    running
+                );
+            };
+
+            info!("Replacing the running server {}", running);
+            kill(Pid::from_raw(pid), Signal::SIGTERM)
+                .with_context(|| format!("Failed to stop the running server {}", running))?;
+            wait_for_lock(&file)
+                .with_context(|| format!("The running server {} didn't exit", running))?;
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        debug!("Locked {}", path.display());
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Try to lock without blocking, returns whether the lock was taken
+fn try_lock(file: &File) -> anyhow::Result<bool> {
+    match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+        Ok(()) => Ok(true),
+        Err(Errno::EWOULDBLOCK) => Ok(false),
+        Err(e) => Err(e).context("Failed to lock the instance"),
+    }
+}
+
+fn wait_for_lock(file: &File) -> anyhow::Result<()> {
+    let start = Instant::now();
+
+    while start.elapsed() < REPLACE_TIMEOUT {
+        if try_lock(file)? {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    bail!("Timed out after {:?}", REPLACE_TIMEOUT)
+}
+
+fn read_pid(file: &mut File) -> Option<i32> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
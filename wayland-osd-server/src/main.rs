@@ -1,480 +1,943 @@
-use std::fs;
-use std::io::ErrorKind;
-use std::os::fd::{FromRawFd, RawFd};
-use std::path::Path;
-use std::sync::Arc;
-use std::sync::Mutex;
-
+mod animation;
+mod blur;
+mod dbus;
+mod icons;
+mod lock;
+mod pipe;
+mod popup;
+mod preview;
+mod socket;
+mod style;
+mod surfaces;
+mod ui;
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use clap::Parser;
 use env_logger::Env;
-use gtk::{
-    glib::{self, result_from_gboolean},
-    prelude::*,
+use gtk::{gio, glib, prelude::*};
+use log::{debug, error, info, warn, LevelFilter};
+use nix::libc;
+use wayland_osd_core::capture::CaptureWriter;
+use wayland_osd_core::config::{QueueMode, QuietMode, TimeOfDay};
+use wayland_osd_core::hooks::{self, HookEvent, Hooks};
+use wayland_osd_core::ipc::Instance;
+use wayland_osd_core::journal::{Journal, JournalLogger};
+use wayland_osd_core::message::{
+    DISMISS_MESSAGE_TYPE, DND_MESSAGE_TYPE, PROFILE_MESSAGE_TYPE, PROTOCOL_VERSION,
+    RELOAD_MESSAGE_TYPE,
 };
-use gtk4_layer_shell::{Edge, Layer, LayerShell};
-use log::{debug, error, info, trace, warn};
-use nix::sys::stat;
-use nix::fcntl::{OFlag, open};
-use serde::{Deserialize, Serialize};
-
-const PIPE_PATH: &str = "/tmp/wayland-osd.pipe";
-
-// Embed SVG files
-const ICON_VOLUME_HIGH: &str = include_str!("../assets/sink-volume-high-symbolic.svg");
-const ICON_VOLUME_MEDIUM: &str = include_str!("../assets/sink-volume-medium-symbolic.svg");
-const ICON_VOLUME_LOW: &str = include_str!("../assets/sink-volume-low-symbolic.svg");
-const ICON_VOLUME_MUTED: &str = include_str!("../assets/sink-volume-muted-symbolic.svg");
-const ICON_VOLUME_OVERAMPLIFIED: &str =
-    include_str!("../assets/sink-volume-overamplified-symbolic.svg");
-const ICON_BRIGHTNESS: &str = include_str!("../assets/display-brightness-symbolic.svg");
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct OsdMessage {
-    #[serde(rename = "type")]
-    message_type: String,
-    value: Option<i32>,
-    max_value: Option<i32>,
-    text: Option<String>,
-    muted: Option<bool>,
-    device_name: Option<String>,
+use wayland_osd_core::persist::PersistedState;
+#[cfg(feature = "scripting")]
+use wayland_osd_core::script::Transformer;
+use wayland_osd_core::state::History;
+use wayland_osd_core::stats::Status;
+use wayland_osd_core::{demo, ingest};
+use wayland_osd_core::{Config, OsdMessage, Stats, Update, View};
+
+use lock::InstanceLock;
+use pipe::{ReadSettings, SharedSettings};
+use popup::Popup;
+use style::Stylesheet;
+
+/// Glue between the core state machine and the GTK widgets
+struct Server {
+    app: gtk::Application,
+    stylesheet: RefCell<Stylesheet>,
+    config: RefCell<Config>,
+    /// Windows by category, `None` for the main one, created on first use
+    popups: RefCell<HashMap<Option<String>, Rc<Popup>>>,
+    hooks: RefCell<Hooks>,
+    journal: RefCell<Option<Journal>>,
+    #[cfg(feature = "scripting")]
+    transformer: RefCell<Option<Transformer>>,
+    /// Profile switched to at runtime, remembered across restarts
+    profile: RefCell<Option<String>>,
+    /// Do-not-disturb, suppresses every message while set
+    dnd: Cell<bool>,
+    /// Messages shown in any window, kept across restarts
+    history: RefCell<History>,
+    /// A message of a newer protocol version was already warned about
+    warned_version: Cell<bool>,
+    /// Shared with the IPC threads
+    stats: Arc<Stats>,
+    /// Shared with the IPC threads, follows the config
+    read_settings: SharedSettings,
+    instance: Instance,
+    started: Instant,
+    /// Keeps the application running while no window exists yet
+    _hold: gio::ApplicationHoldGuard,
 }
 
-struct UiElements {
-    window: gtk::ApplicationWindow,
-    progress_bar: gtk::ProgressBar,
-    label: gtk::Label,
-    device_label: gtk::Label,
-    icon: gtk::Image,
-    drawing_area: gtk::DrawingArea,
-    max_value: Arc<Mutex<i32>>,
-    timeout_source_id: Arc<Mutex<Option<glib::SourceId>>>,
-}
+impl Server {
+    fn new(
+        app: &gtk::Application,
+        instance: Instance,
+        config: Config,
+        started: Instant,
+        stats: Arc<Stats>,
+        read_settings: SharedSettings,
+    ) -> Rc<Self> {
+        if config.window.blur {
+            blur::request_blur();
+        }
+        let server = Rc::new(Self {
+            app: app.clone(),
+            stylesheet: RefCell::new(Stylesheet::install(&config.style)),
+            popups: RefCell::new(HashMap::new()),
+            hooks: RefCell::new(Hooks::new(config.hooks.clone())),
+            journal: RefCell::new(connect_journal(&config)),
+            #[cfg(feature = "scripting")]
+            transformer: RefCell::new(load_transformer(&config)),
+            profile: RefCell::new(None),
+            dnd: Cell::new(false),
+            history: RefCell::new(History::default()),
+            warned_version: Cell::new(false),
+            stats,
+            read_settings,
+            instance,
+            started,
+            _hold: app.hold(),
+            config: RefCell::new(config),
+        });
+        server.restore_state();
+        server
+    }
 
-fn load_icon_from_string(svg_data: &str) -> gtk::Image {
-    // Add white fill color to SVG content
-    let bytes = glib::Bytes::from_owned(svg_data.as_bytes().to_vec());
-    let texture = gtk::gdk::Texture::from_bytes(&bytes).expect("Failed to load icon");
-    gtk::Image::from_paintable(Some(&texture))
-}
+    fn restore_state(&self) {
+        let persisted = match PersistedState::load(&self.instance) {
+            Ok(persisted) => persisted,
+            Err(e) => {
+                error!("{:#}, starting fresh", e);
+                return;
+            }
+        };
 
-fn get_volume_icon(value: i32, muted: bool) -> gtk::Image {
-    if muted {
-        return load_icon_from_string(ICON_VOLUME_MUTED);
+        if let Some(profile) = &persisted.profile {
+            self.switch_profile(profile);
+        }
+        self.dnd.set(persisted.dnd);
+        *self.history.borrow_mut() = persisted.history;
+
+        // Back into the window each message would be shown in
+        let mut by_category: HashMap<Option<String>, BTreeMap<String, OsdMessage>> = HashMap::new();
+        for (tag, msg) in persisted.last_messages {
+            let category = self
+                .config
+                .borrow()
+                .category(&msg.message_type)
+                .map(str::to_string);
+            by_category.entry(category).or_default().insert(tag, msg);
+        }
+        for (category, last_messages) in by_category {
+            self.popup(category.as_deref())
+                .state
+                .borrow_mut()
+                .restore(last_messages);
+        }
     }
 
-    let icon_data = if value > 100 {
-        ICON_VOLUME_OVERAMPLIFIED
-    } else if value > 66 {
-        ICON_VOLUME_HIGH
-    } else if value > 33 {
-        ICON_VOLUME_MEDIUM
-    } else {
-        ICON_VOLUME_LOW
-    };
-
-    load_icon_from_string(icon_data)
-}
+    /// The last message shown for every tag, in any window
+    fn last_messages(&self) -> BTreeMap<String, OsdMessage> {
+        let mut last_messages = BTreeMap::new();
+        for popup in self.popups.borrow().values() {
+            last_messages.extend(popup.state.borrow().last_messages().clone());
+        }
+        last_messages
+    }
 
-fn setup_css() -> gtk::CssProvider {
-    let provider = gtk::CssProvider::new();
-    let css_data = "
-        window {
-            background-color: rgba(0, 0, 0, 0.8);
-            transform: translateX(-50%);
-            border-radius: 10px;
-        }
-        .osd-overlay {
-            margin-left: 10px;
-            margin-right: 10px;
-            margin-top: 5px;
-            margin-bottom: 5px;
-            padding: 10px;
-        }
-        progressbar {
-            min-height: 10px;
-        }
-        progressbar trough {
-            min-height: 10px;
-            background-color: rgba(100, 100, 100, 0.7);
-            border-radius: 5px;
-        }
-        progressbar progress {
-            min-height: 10px;
-            background-color: #729fcf;
-            border-radius: 5px;
-        }
-        progressbar.overamplified progress {
-            background-color: #cc0000;
-        }
-        progressbar.overamplified trough {
-            background-color: rgba(204, 0, 0, 0.3) !important;
-        }
-        label {
-            color: white;
-            font-size: 16px;
-        }
-        .device-label {
-            color: #cccccc;
-            font-size: 12px;
-            margin-top: -10px;
-            margin-bottom: -10px;
-        }
-    ";
-    provider.load_from_data(css_data);
-    provider
-}
+    fn save_state(&self) {
+        let persisted = PersistedState {
+            profile: self.profile.borrow().clone(),
+            dnd: self.dnd.get(),
+            last_messages: self.last_messages(),
+            history: self.history.borrow().clone(),
+        };
 
-fn create_ui(app: &gtk::Application) -> UiElements {
-    let window = gtk::ApplicationWindow::builder()
-        .application(app)
-        .title("Wayland OSD")
-        .build();
+        if let Err(e) = persisted.save(&self.instance) {
+            error!("{:#}", e);
+        }
+    }
 
-    // Initialize as layer shell window
-    window.init_layer_shell();
-    window.set_layer(Layer::Overlay);
+    /// Uptime, counters and what is on screen, for debugging
+    fn status(&self) -> Status {
+        let shown = self
+            .popups
+            .borrow()
+            .iter()
+            .filter_map(|(category, popup)| {
+                let msg = popup.state.borrow().current_message()?.clone();
+                Some((category.clone().unwrap_or_else(|| "main".to_string()), msg))
+            })
+            .collect();
+
+        Status {
+            uptime_secs: self.started.elapsed().as_secs(),
+            stats: self.stats.snapshot(),
+            profile: self.profile.borrow().clone(),
+            dnd: self.dnd.get(),
+            shown,
+            last_messages: self.last_messages(),
+            history: self.history.borrow().clone(),
+        }
+    }
 
-    // Anchor to bottom-center
-    window.set_anchor(Edge::Bottom, true);
+    /// The window of a category, `None` for the main one
+    fn popup(&self, category: Option<&str>) -> Rc<Popup> {
+        self.popups
+            .borrow_mut()
+            .entry(category.map(str::to_string))
+            .or_insert_with(|| {
+                let config = self.config.borrow().for_category(category);
+                Popup::new(&self.app, config, self.started)
+            })
+            .clone()
+    }
 
-    // Set margins
-    window.set_margin(Edge::Bottom, 50);
+    /// The window showing messages of the given type
+    fn popup_for(&self, msg: &OsdMessage) -> Rc<Popup> {
+        let category = self
+            .config
+            .borrow()
+            .category(&msg.message_type)
+            .map(str::to_string);
+        self.popup(category.as_deref())
+    }
 
-    // Set up CSS
-    let provider = setup_css();
-    gtk::style_context_add_provider_for_display(
-        &gtk::gdk::Display::default().expect("Could not get default display"),
-        &provider,
-        gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-    );
+    /// Switch to the settings of another profile
+    ///
+    /// The window is rebuilt from the new settings the next time it is shown.
+    /// The message queue keeps the settings it was created with.
+    fn switch_profile(&self, name: &str) {
+        let config = match self.config.borrow().with_profile(name) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("{:#}, keeping the current profile", e);
+                return;
+            }
+        };
+        info!("Switching to profile {}", name);
 
-    let main_box = gtk::Box::builder()
-        .orientation(gtk::Orientation::Vertical)
-        .spacing(10)
-        .css_classes(vec!["osd-overlay"])
-        .build();
+        self.apply_config(config);
+        *self.profile.borrow_mut() = Some(name.to_string());
+    }
 
-    // Create horizontal box for icon and progress bar
-    let hbox = gtk::Box::builder()
-        .orientation(gtk::Orientation::Horizontal)
-        .spacing(10)
-        .halign(gtk::Align::Center)
-        .build();
+    fn set_dnd(&self, enabled: bool) {
+        info!(
+            "Do-not-disturb {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+        self.dnd.set(enabled);
+        if enabled {
+            self.hide();
+        }
+    }
 
-    let icon = load_icon_from_string(ICON_VOLUME_MEDIUM);
-    icon.set_visible(false);
-
-    // Create an overlay for progress bar and marker line
-    let progress_overlay = gtk::Overlay::new();
-
-    let progress_bar = gtk::ProgressBar::new();
-    progress_bar.set_visible(false);
-    progress_overlay.set_child(Some(&progress_bar));
-
-    // Create drawing area for the marker line
-    let drawing_area = gtk::DrawingArea::new();
-    drawing_area.set_visible(false);
-    drawing_area.set_can_target(false);
-    drawing_area.set_content_height(10); // Match progress bar height
-
-    // Create shared max_value for drawing area
-    let max_value = Arc::new(Mutex::new(100));
-    let max_value_for_draw = max_value.clone();
-
-    drawing_area.set_draw_func(move |_area, cr, width, height| {
-        // Draw white vertical line
-        cr.set_source_rgba(1.0, 1.0, 1.0, 0.8);
-        cr.set_line_width(2.0);
-
-        // Position line at 100% mark using the current max_value
-        let max = *max_value_for_draw.lock().unwrap();
-        let x = (width as f64) * (100.0 / max as f64);
-        trace!("Drawing line to y={}", height);
-        cr.move_to(x, 1.0);
-        cr.line_to(x, 11.0);
-        cr.stroke().expect("Failed to draw line");
-    });
+    /// Re-read the config file and stylesheet, keeping the current profile
+    fn reload(&self) {
+        let config = Config::load_instance(&self.instance).and_then(|config| {
+            match self.profile.borrow().as_deref() {
+                Some(profile) => config.with_profile(profile),
+                None => Ok(config),
+            }
+        });
+        let config = match config {
+            Ok(config) => config,
+            Err(e) => {
+                error!("{:#}, keeping the current config", e);
+                return;
+            }
+        };
+        info!("Reloading config");
 
-    progress_overlay.add_overlay(&drawing_area);
+        self.apply_config(config);
+    }
 
-    let label = gtk::Label::new(None);
-    label.set_visible(false);
+    /// Replace everything built from the config, the window is rebuilt the next time it is shown
+    fn apply_config(&self, config: Config) {
+        let popups: Vec<_> = self
+            .popups
+            .borrow()
+            .iter()
+            .map(|(category, popup)| (category.clone(), popup.clone()))
+            .collect();
+        for (category, popup) in popups {
+            // Still with the old hooks, which saw it shown
+            if popup.set_config(config.for_category(category.as_deref())) {
+                self.emit(HookEvent::Hide, None);
+            }
+        }
 
-    let device_label = gtk::Label::new(None);
-    device_label.set_visible(false);
-    device_label.set_css_classes(&["device-label"]);
+        if config.window.blur {
+            blur::request_blur();
+        }
+        *self.hooks.borrow_mut() = Hooks::new(config.hooks.clone());
+        *self.journal.borrow_mut() = connect_journal(&config);
+        #[cfg(feature = "scripting")]
+        {
+            *self.transformer.borrow_mut() = load_transformer(&config);
+        }
+        self.stylesheet.borrow().uninstall();
+        *self.stylesheet.borrow_mut() = Stylesheet::install(&config.style);
+        *self.read_settings.write().unwrap() = ReadSettings::from_config(&config);
+        *self.config.borrow_mut() = config;
+    }
 
-    hbox.append(&icon);
-    hbox.append(&progress_overlay);
+    /// Run the hooks for an event and record it in the journal
+    fn emit(&self, event: HookEvent, msg: Option<&OsdMessage>) {
+        self.hooks.borrow_mut().fire(event, msg);
+        if let Some(journal) = self.journal.borrow().as_ref() {
+            journal.record(event, msg);
+        }
+    }
 
-    main_box.append(&hbox);
-    main_box.append(&device_label);
-    main_box.append(&label);
-    window.set_child(Some(&main_box));
+    /// Let the user's script rewrite or drop the message
+    #[cfg(feature = "scripting")]
+    fn transform(&self, msg: OsdMessage) -> Option<OsdMessage> {
+        let transformer = self.transformer.borrow();
+        let Some(transformer) = transformer.as_ref() else {
+            return Some(msg);
+        };
 
-    window.set_visible(false);
+        match transformer.transform(msg.clone()) {
+            Ok(transformed) => transformed,
+            Err(e) => {
+                error!("{:#}, using the original message", e);
+                Some(msg)
+            }
+        }
+    }
 
-    UiElements {
-        window,
-        progress_bar,
-        label,
-        device_label,
-        icon,
-        drawing_area,
-        max_value,
-        timeout_source_id: Arc::new(Mutex::new(None)),
+    #[cfg(not(feature = "scripting"))]
+    fn transform(&self, msg: OsdMessage) -> Option<OsdMessage> {
+        Some(msg)
     }
-}
 
-fn handle_message(ui: &UiElements, msg: OsdMessage) {
-    debug!("Handling message: {:?}", msg);
+    /// Handle a batch of messages, only rendering the net effect on every window once
+    fn handle_messages(self: &Rc<Self>, msgs: Vec<OsdMessage>) {
+        // Last update of every window, with whether it was visible before the batch
+        let mut last_updates: Vec<(Rc<Popup>, bool, Update, OsdMessage)> = Vec::new();
+
+        for msg in msgs {
+            self.stats.record_handled(&msg.message_type);
+            debug!(
+                message_type = msg.message_type.as_str(),
+                value:? = msg.value;
+                "Handling message: {:?}", msg
+            );
+
+            // Unknown fields are skipped when parsing, so newer messages mostly still work
+            if msg.is_newer_protocol() && !self.warned_version.replace(true) {
+                warn!(
+                    "A client speaks protocol version {}, newer than version {} of the server, \
+                     parts of its messages may be ignored",
+                    msg.version.unwrap_or_default(),
+                    PROTOCOL_VERSION
+                );
+            }
+
+            if msg.message_type == PROFILE_MESSAGE_TYPE {
+                match &msg.text {
+                    Some(name) => self.switch_profile(name),
+                    None => warn!("Ignoring profile message without `text`"),
+                }
+                continue;
+            }
+            if msg.message_type == RELOAD_MESSAGE_TYPE {
+                self.reload();
+                continue;
+            }
+            if msg.message_type == DISMISS_MESSAGE_TYPE {
+                // Also drops whatever earlier messages of the batch would have shown
+                last_updates.clear();
+                self.hide();
+                continue;
+            }
+            if msg.message_type == DND_MESSAGE_TYPE {
+                match msg.text.as_deref() {
+                    Some("on") => self.set_dnd(true),
+                    Some("off") => self.set_dnd(false),
+                    None => self.set_dnd(!self.dnd.get()),
+                    Some(other) => warn!("Ignoring dnd message with unknown `text` {}", other),
+                }
+                continue;
+            }
 
-    match msg.message_type.as_str() {
-        "volume" => {
-            if let (Some(value), Some(max)) = (msg.value, msg.max_value) {
+            if self.dnd.get() {
+                debug!("Do-not-disturb is on, suppressing message");
+                continue;
+            }
+            if self.quiet_mode(&msg) == Some(QuietMode::Suppress) {
+                debug!("Quiet hours, suppressing message");
+                continue;
+            }
+
+            let Some(msg) = self.transform(msg) else {
+                continue;
+            };
+            // After the script, which may have changed the type
+            if !self.config.borrow().shows(&msg.message_type) {
                 debug!(
-                    "Volume update - level: {}, max: {}, muted: {:?}",
-                    value, max, msg.muted
+                    "Messages of type {} are turned off, ignoring",
+                    msg.message_type
                 );
-                let fraction = value as f64 / max as f64;
-                ui.progress_bar.set_fraction(fraction);
-                ui.progress_bar.set_visible(true);
-                ui.label.set_visible(false);
-                
-                // Update device name if provided
-                if let Some(device_name) = msg.device_name {
-                    ui.device_label.set_text(&device_name);
-                    ui.device_label.set_visible(true);
-                } else {
-                    ui.device_label.set_visible(false);
-                }
+                continue;
+            }
 
-                // Add CSS classes based on volume level
-                let style_context = ui.progress_bar.style_context();
-                if value > 100 {
-                    style_context.add_class("overamplified");
-                } else {
-                    style_context.remove_class("overamplified");
+            let popup = self.popup_for(&msg);
+            let was_visible = popup.state.borrow().is_visible();
+            let result = popup
+                .state
+                .borrow_mut()
+                .handle(msg.clone(), &popup.config());
+            match result {
+                Ok(Some(update)) => {
+                    self.emit(HookEvent::Message, Some(&msg));
+                    // Key auto-repeat would crowd out everything else
+                    if !update.repeated {
+                        self.history.borrow_mut().push(msg.clone());
+                    }
+                    match last_updates
+                        .iter_mut()
+                        .find(|(shown, ..)| Rc::ptr_eq(shown, &popup))
+                    {
+                        Some(last) => (last.2, last.3) = (update, msg),
+                        None => last_updates.push((popup, was_visible, update, msg)),
+                    }
                 }
-
-                // Update max value and show/hide marker line
-                if max > 100 {
-                    *ui.max_value.lock().unwrap() = max;
-                    ui.drawing_area.set_visible(true);
-                    ui.drawing_area.queue_draw(); // Force redraw with new max value
-                } else {
-                    ui.drawing_area.set_visible(false);
+                Ok(None) => debug!("Held back or dropped for the message on screen"),
+                Err(e) => {
+                    self.stats.record_rejected();
+                    if msg.is_newer_protocol() {
+                        warn!("Ignoring message from a newer client: {}", e);
+                    } else {
+                        warn!("Ignoring message: {}", e);
+                    }
                 }
+            }
+        }
 
-                // Update icon based on volume level and muted state
-                let new_icon = get_volume_icon(value, msg.muted.unwrap_or(false));
-                if let Some(paintable) = new_icon.paintable() {
-                    ui.icon.set_paintable(Some(&paintable));
-                    trace!("Updated volume icon");
-                }
-                ui.icon.set_visible(true);
-            } else {
-                warn!("Received volume message with missing value or max_value");
+        for (popup, was_visible, update, msg) in last_updates {
+            self.show(&popup, &update, &msg, was_visible);
+        }
+    }
+
+    fn show(
+        self: &Rc<Self>,
+        popup: &Rc<Popup>,
+        update: &Update,
+        msg: &OsdMessage,
+        was_visible: bool,
+    ) {
+        let dimmed = self.quiet_mode(msg) == Some(QuietMode::Dim);
+        // Key auto-repeat sends the same message over and over, nothing to redraw
+        if was_visible && update.repeated {
+            debug!("Same message as on screen, only restarting the timeout");
+        } else {
+            let ui = popup.ui();
+            ui.set_output(msg.output.as_deref());
+            let mut classes = msg.window_classes();
+            if dimmed {
+                classes.push("quiet".to_string());
             }
+            ui.set_classes(classes);
+            ui.apply(&update.view, &popup.config().layout(&msg.message_type));
+            ui.announce(&update.view, &msg.message_type);
+
+            debug!("Showing window");
+            ui.show();
         }
-        "brightness" => {
-            if let (Some(value), Some(max)) = (msg.value, msg.max_value) {
-                info!("Brightness update - level: {}, max: {}", value, max);
-                ui.progress_bar.set_fraction(value as f64 / max as f64);
-                ui.progress_bar.set_visible(true);
-                ui.label.set_visible(false);
-                ui.device_label.set_visible(false);
-                ui.drawing_area.set_visible(false); // Always hide marker for brightness
-
-                let brightness_icon = load_icon_from_string(ICON_BRIGHTNESS);
-                if let Some(paintable) = brightness_icon.paintable() {
-                    ui.icon.set_paintable(Some(&paintable));
-                    trace!("Updated brightness icon");
-                }
-                ui.icon.set_visible(true);
-            } else {
-                warn!("Received brightness message with missing value or max_value");
+        // A message with another id counts as a new popup even while one is shown
+        if !was_visible || update.new_popup {
+            self.emit(HookEvent::Show, Some(msg));
+        }
+        match msg.countdown() {
+            Some(total) => self.start_countdown(popup, update, total),
+            None => popup.set_countdown(None),
+        }
+        if update.sticky {
+            debug!("Sticky message, keeping the window up");
+            popup.keep_shown();
+        } else if dimmed {
+            let hide_after = update
+                .hide_after
+                .min(self.config.borrow().quiet_hours.timeout());
+            self.schedule_hide(popup, update.generation, hide_after);
+        } else {
+            self.schedule_hide(popup, update.generation, update.hide_after);
+        }
+    }
+
+    /// Count down the message on screen once a second, until it runs out or is replaced
+    fn start_countdown(&self, popup: &Rc<Popup>, update: &Update, total: Duration) {
+        let View::Progress(progress) = &update.view else {
+            return;
+        };
+        let mut progress = progress.clone();
+        let layout = popup.config().layout("countdown");
+        let started = Instant::now();
+        let weak_popup = Rc::downgrade(popup);
+
+        let mut tick = move || {
+            let Some(popup) = weak_popup.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            let remaining = total.saturating_sub(started.elapsed());
+            progress.set_countdown(remaining, total);
+            popup.ui().apply(&View::Progress(progress.clone()), &layout);
+
+            if remaining.is_zero() {
+                popup.countdown_finished();
+                return glib::ControlFlow::Break;
+            }
+            glib::ControlFlow::Continue
+        };
+
+        // Right away too, a repeated message restarts the countdown without redrawing
+        popup.set_countdown(None);
+        if tick().is_continue() {
+            popup.set_countdown(Some(glib::timeout_add_local(Duration::from_secs(1), tick)));
+        }
+    }
+
+    /// What quiet hours do to a message right now, `None` outside of them and for critical messages
+    fn quiet_mode(&self, msg: &OsdMessage) -> Option<QuietMode> {
+        if msg.critical() {
+            return None;
+        }
+        let config = self.config.borrow();
+        let now = glib::DateTime::now_local().ok()?;
+        let time = TimeOfDay::new(now.hour() as u32, now.minute() as u32);
+        config
+            .quiet_hours
+            .contains(time)
+            .then_some(config.quiet_hours.mode)
+    }
+
+    /// Show the next message that was held back, returns whether there was one
+    fn show_pending(self: &Rc<Self>, popup: &Rc<Popup>) -> bool {
+        loop {
+            let Some(msg) = popup.state.borrow_mut().next_pending() else {
+                return false;
+            };
+
+            let result = popup
+                .state
+                .borrow_mut()
+                .handle(msg.clone(), &popup.config());
+            if let Ok(Some(update)) = result {
+                self.emit(HookEvent::Message, Some(&msg));
+                self.show(popup, &update, &msg, false);
+                return true;
             }
         }
-        "text" => {
-            if let Some(text) = msg.text {
-                info!("Text message update: {}", text);
-                ui.label.set_text(&text);
-                ui.label.set_visible(true);
-                ui.progress_bar.set_visible(false);
-                ui.icon.set_visible(false);
-                ui.device_label.set_visible(false);
-                ui.drawing_area.set_visible(false); // Hide marker for text messages
-            } else {
-                warn!("Received text message with no text content");
+    }
+
+    /// Pause the hide timeout of a window while the pointer is over it, like notifications
+    ///
+    /// The full timeout starts over when the pointer leaves.
+    fn hover_changed(self: &Rc<Self>) {
+        let popups: Vec<Rc<Popup>> = self.popups.borrow().values().cloned().collect();
+
+        for popup in popups {
+            let Some((generation, hide_after)) = popup.pending_hide() else {
+                continue;
+            };
+            if popup.is_hovered() {
+                debug!("Pausing hide timeout while hovered");
+                popup.set_timeout(None);
+            } else if !popup.has_timeout() {
+                debug!("Resuming hide timeout");
+                self.schedule_hide(&popup, generation, hide_after);
             }
         }
-        _ => {
-            warn!("Received unknown message type: {}", msg.message_type);
+    }
+
+    /// Run the scroll command of the message on screen in the hovered window
+    fn scrolled(&self, dy: f64) {
+        // Scrolling up is negative, and raises the value
+        let steps = -dy.round() as i32;
+        if steps == 0 {
             return;
         }
+
+        let popups: Vec<Rc<Popup>> = self.popups.borrow().values().cloned().collect();
+        for popup in popups.iter().filter(|popup| popup.is_hovered()) {
+            let message_type = match popup.state.borrow().current_message() {
+                Some(msg) => msg.message_type.clone(),
+                None => continue,
+            };
+            match popup.config().scroll.command(&message_type, steps) {
+                Some(command) => hooks::run(&command),
+                None => debug!("No scroll command for {}", message_type),
+            }
+        }
     }
 
-    debug!("Getting to end of building window");
-
-    // Remove existing timeout if any
-    if let Some(source_id) = ui.timeout_source_id.lock().unwrap().take() {
-        unsafe {
-            if let Err(err) = result_from_gboolean!(
-                glib::ffi::g_source_remove(source_id.as_raw()),
-                "Failed to remove source"
-            ) {
-                error!(
-                    "Failed to remove source {}, it may have already been removed: {}",
-                    source_id.as_raw(),
-                    err.message
-                );
+    /// Hide every window right away, e.g. when dismissed or asked to over D-Bus
+    fn hide(&self) {
+        let popups: Vec<Rc<Popup>> = self.popups.borrow().values().cloned().collect();
+
+        for popup in popups {
+            popup.cancel_hide();
+            if popup.state.borrow_mut().hide() {
+                debug!("Hiding window");
+                popup.ui().hide();
+                self.emit(HookEvent::Hide, None);
             }
         }
     }
 
-    ui.window.set_visible(true);
-    debug!("Showing window");
+    fn schedule_hide(self: &Rc<Self>, popup: &Rc<Popup>, generation: u64, hide_after: Duration) {
+        popup.set_pending_hide(generation, hide_after);
+        // Started over once the pointer leaves
+        if popup.is_hovered() {
+            popup.set_timeout(None);
+            return;
+        }
 
-    // Schedule new hide timeout after 3 seconds
-    let window = ui.window.clone();
-    let timeout_source_id = ui.timeout_source_id.clone();
-    let source_id = glib::timeout_add_seconds_local(3, move || {
-        window.set_visible(false);
-        *timeout_source_id.lock().unwrap() = None;
-        glib::ControlFlow::Break
-    });
+        let server: Weak<Self> = Rc::downgrade(self);
+        let weak_popup = Rc::downgrade(popup);
+        let source_id = glib::timeout_add_local(hide_after, move || {
+            if let (Some(server), Some(popup)) = (server.upgrade(), weak_popup.upgrade()) {
+                popup.timeout_fired();
+                if popup.state.borrow_mut().expire(generation) {
+                    server.emit(HookEvent::Hide, None);
+                    // The window stays up for whatever was held back
+                    if !server.show_pending(&popup) {
+                        debug!("Hiding window");
+                        popup.ui().hide();
+                    }
+                }
+            }
+            glib::ControlFlow::Break
+        });
 
-    // Store the new timeout source ID
-    *ui.timeout_source_id.lock().unwrap() = Some(source_id);
+        // Replaces the previous timeout of the window, if any
+        popup.set_timeout(Some(source_id));
+    }
 }
 
-fn setup_pipe() -> anyhow::Result<()> {
-    debug!("Setting up named pipe at {}", PIPE_PATH);
-
-    // Remove existing pipe if it exists
-    if Path::new(PIPE_PATH).exists() {
-        debug!("Removing existing pipe");
-        fs::remove_file(PIPE_PATH)?;
+fn connect_journal(config: &Config) -> Option<Journal> {
+    if !config.journal.events {
+        return None;
     }
 
-    // Create new pipe with proper permissions
-    debug!("Creating new pipe with permissions");
-    nix::unistd::mkfifo(
-        PIPE_PATH,
-        stat::Mode::S_IRUSR | stat::Mode::S_IWUSR | stat::Mode::S_IWGRP | stat::Mode::S_IWOTH,
-    )?;
+    Journal::connect()
+        .inspect_err(|e| error!("Failed to connect to the journal: {}", e))
+        .ok()
+}
 
-    info!("Named pipe setup complete");
-    Ok(())
+#[cfg(feature = "scripting")]
+fn load_transformer(config: &Config) -> Option<Transformer> {
+    let path = Config::resolve_path(config.script.as_deref()?);
+    Transformer::load(&path)
+        .inspect_err(|e| error!("{:#}, messages will be shown unchanged", e))
+        .ok()
 }
 
-fn main() -> anyhow::Result<()> {
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Render a sample of every message type to PNG files in DIR and exit
+    #[arg(long, value_name = "DIR")]
+    render_preview: Option<PathBuf>,
+    /// Run as a named instance with its own pipe, config file and state
+    #[arg(long, value_name = "NAME")]
+    instance: Option<String>,
+    /// Record every received message with its timing to FILE, for `wayland-osd-client replay`
+    #[arg(long, value_name = "FILE")]
+    dump: Option<PathBuf>,
+    /// Stop the server already running for this instance and take over
+    #[arg(long)]
+    replace: bool,
+    /// Log to the systemd journal with structured fields instead of to stderr
+    #[arg(long)]
+    log_journal: bool,
+    /// Cycle through sample messages, for previewing a theme on the live display
+    #[arg(long)]
+    demo: bool,
+    /// Time each sample of `--demo` stays up
+    #[arg(long, value_name = "MS", default_value_t = 2000)]
+    demo_interval_ms: u64,
+}
+
+/// Render the preview images instead of running the server
+fn render_preview(config: Config, dir: PathBuf) -> anyhow::Result<()> {
+    gtk::init()?;
+
+    // Don't hand the preview over to a server that is already running
+    let application = gtk::Application::builder()
+        .application_id("org.wayland.osd.preview")
+        .flags(gio::ApplicationFlags::NON_UNIQUE)
+        .build();
+
+    let result = Rc::new(RefCell::new(Ok(())));
+    let result_for_activate = result.clone();
+
+    application.connect_activate(move |app| {
+        // Rendering is over before the user's stylesheet could change
+        Stylesheet::install(&config.style);
+
+        let app = app.clone();
+        let config = config.clone();
+        let dir = dir.clone();
+        let result = result_for_activate.clone();
+        glib::spawn_future_local(async move {
+            let _hold = app.hold();
+            *result.borrow_mut() = preview::render(&app, config, &dir).await;
+            app.quit();
+        });
+    });
+
+    // Our own arguments were already parsed
+    application.run_with_args::<&str>(&[]);
+    result.replace(Ok(()))
+}
+
+/// Show the demo samples one after another until the server exits
+///
+/// They go through the same path as received messages, alongside them.
+async fn run_demo(server: &Rc<Server>, interval: Duration) {
+    info!("Running demo, every {:?}", interval);
+    loop {
+        for (name, msg) in demo::samples() {
+            debug!("Demo sample {}", name);
+            server.handle_messages(vec![msg]);
+            glib::timeout_future(interval).await;
+        }
+    }
+}
+
+/// Log to the journal if asked to and it's reachable, to stderr otherwise
+///
+/// The journal only takes a plain level from `RUST_LOG`, like `debug`.
+fn init_logging(journal: bool) {
+    if journal {
+        let level = std::env::var("RUST_LOG")
+            .ok()
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(LevelFilter::Info);
+        match JournalLogger::init(level) {
+            Ok(()) => return,
+            Err(e) => eprintln!("Can't log to the journal, logging to stderr: {}", e),
+        }
+    }
+
     // Initialize logger with timestamp and module path
     env_logger::Builder::from_env(Env::default().default_filter_or("info"))
         .format_timestamp_millis()
         .format_module_path(true)
         .init();
+}
+
+fn main() -> anyhow::Result<()> {
+    let started = Instant::now();
+    let args = Args::parse();
+
+    init_logging(args.log_journal);
 
     info!("Starting Wayland OSD server");
-    gtk::init()?;
 
-    debug!("Setting up named pipe at {}", PIPE_PATH);
-    setup_pipe()?;
+    let instance = Instance::new(args.instance)?;
+    if let Some(name) = instance.name() {
+        info!("Running as instance {}", name);
+    }
+
+    let config = Config::load_instance(&instance).unwrap_or_else(|e| {
+        error!("{:#}, using defaults", e);
+        Config::default()
+    });
+
+    if let Some(dir) = args.render_preview {
+        return render_preview(config, dir);
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    if config.script.is_some() {
+        warn!("`script` is set, but the server was built without the `scripting` feature");
+    }
+
+    let _lock = InstanceLock::acquire(&instance.lock_path(), args.replace)?;
+
+    // Start listening before GTK is up, so a client that spawned us can send
+    // right away and its message is queued while the display connects
+    let pipe_path = instance.pipe_path();
+    pipe::setup_pipe(&pipe_path)?;
+    // A socket passed by systemd is systemd's to clean up
+    let (listener, owned_socket) = match socket::inherited_listener() {
+        Some(listener) => (listener, None),
+        None => {
+            let socket_path = instance.socket_path();
+            (socket::setup_socket(&socket_path)?, Some(socket_path))
+        }
+    };
+
+    let stats = Arc::new(Stats::new());
+    let (sender, receiver) = ingest::channel(
+        config.ingest.queue_size,
+        config.ingest.overflow,
+        stats.clone(),
+    );
+    let capture = args
+        .dump
+        .map(|path| {
+            info!("Recording messages to {}", path.display());
+            CaptureWriter::create(&path)
+        })
+        .transpose()?;
+    let capture = Arc::new(Mutex::new(capture));
+    let read_settings = Arc::new(RwLock::new(ReadSettings::from_config(&config)));
+    pipe::spawn_reader(
+        &pipe_path,
+        sender.clone(),
+        capture.clone(),
+        read_settings.clone(),
+    )?;
+    socket::spawn_listener(listener, sender, capture, read_settings.clone())?;
+
+    gtk::init()?;
+    debug!("GTK initialized {:?} after startup", started.elapsed());
 
     info!("Initializing GTK application");
     let application = gtk::Application::builder()
-        .application_id("org.wayland.osd")
+        .application_id(instance.application_id())
         .build();
 
-    let ui_elements = Arc::new(parking_lot::Mutex::new(None));
-    let ui_elements_clone = ui_elements.clone();
+    // Only the first activation gets to consume the queue
+    let receiver = RefCell::new(Some(receiver));
+    let demo = args
+        .demo
+        .then(|| Duration::from_millis(args.demo_interval_ms));
 
     application.connect_activate(move |app| {
-        let ui = create_ui(app);
-        *ui_elements_clone.lock() = Some(ui);
-
-        // Start pipe reading in the GTK main context
-        let ui_elements = ui_elements_clone.clone();
-        let mut buffer = Vec::with_capacity(4096);
-        let mut read_buffer = [0u8; 1024];
-        const MAX_MESSAGE_SIZE: usize = 8192;
-
-        // Open pipe in non-blocking mode
-        let pipe_fd = match open(PIPE_PATH, OFlag::O_RDONLY | OFlag::O_NONBLOCK, stat::Mode::empty()) {
-            Ok(fd) => {
-                trace!("Successfully opened pipe in non-blocking mode");
-                Some(fd)
-            }
-            Err(e) => {
-                error!("Failed to open pipe: {}", e);
-                None
-            }
+        let Some(receiver) = receiver.borrow_mut().take() else {
+            debug!("Already running, ignoring activation");
+            return;
         };
 
-        if let Some(fd) = pipe_fd {
-            let mut file = unsafe { std::fs::File::from_raw_fd(fd as RawFd) };
-            
-            glib::source::idle_add_local(move || {
-                match std::io::Read::read(&mut file, &mut read_buffer) {
-                    Ok(0) => {
-                        // EOF received, but we don't need to reopen since we use message delimiters
-                        trace!("EOF received, continuing to next iteration");
-                    }
-                    Ok(n) => {
-                        let mut start = 0;
-                        for (i, &byte) in read_buffer[..n].iter().enumerate() {
-                            if byte == 0 {
-                                if !buffer.is_empty() || i > start {
-                                    buffer.extend_from_slice(&read_buffer[start..i]);
-
-                                    if buffer.len() > MAX_MESSAGE_SIZE {
-                                        error!("Message too large ({} bytes), discarding", buffer.len());
-                                        buffer.clear();
-                                    } else if !buffer.is_empty() {
-                                        if let Ok(msg_str) = String::from_utf8(buffer.clone()) {
-                                            trace!("Received raw message: {}", msg_str);
-                                            if let Ok(msg) = serde_json::from_str::<OsdMessage>(&msg_str) {
-                                                debug!("Parsed message: {:?}", msg);
-                                                if let Some(ui) = &*ui_elements.lock() {
-                                                    handle_message(ui, msg);
-                                                } else {
-                                                    warn!("UI elements not initialized, skipping message");
-                                                }
-                                            } else {
-                                                error!("Failed to parse message: {}", msg_str);
-                                            }
-                                        } else {
-                                            error!("Invalid UTF-8 in message");
-                                        }
-                                    }
-                                    buffer.clear();
-                                }
-                                start = i + 1;
-                            }
-                        }
-
-                        if start < n {
-                            let remaining = &read_buffer[start..n];
-                            if buffer.len() + remaining.len() > MAX_MESSAGE_SIZE {
-                                error!("Message would exceed size limit, discarding");
-                                buffer.clear();
-                            } else {
-                                buffer.extend_from_slice(remaining);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        if e.kind() != ErrorKind::WouldBlock {
-                            error!("Error reading from pipe: {}", e);
-                        }
-                    }
+        let server = Server::new(
+            app,
+            instance.clone(),
+            config.clone(),
+            started,
+            stats.clone(),
+            read_settings.clone(),
+        );
+
+        let server_for_shutdown = server.clone();
+        app.connect_shutdown(move |_| {
+            server_for_shutdown.hide();
+            server_for_shutdown.save_state();
+        });
+
+        let server_for_reload = Rc::downgrade(&server);
+        glib::unix_signal_add_local(libc::SIGHUP, move || {
+            if let Some(server) = server_for_reload.upgrade() {
+                server.reload();
+            }
+            glib::ControlFlow::Continue
+        });
+
+        let server_for_dnd = Rc::downgrade(&server);
+        glib::unix_signal_add_local(libc::SIGUSR1, move || {
+            if let Some(server) = server_for_dnd.upgrade() {
+                server.set_dnd(!server.dnd.get());
+            }
+            glib::ControlFlow::Continue
+        });
+
+        // Activated by clicks on interactive windows
+        let dismiss = gio::SimpleAction::new("dismiss", None);
+        let server_for_dismiss = Rc::downgrade(&server);
+        dismiss.connect_activate(move |_, _| {
+            if let Some(server) = server_for_dismiss.upgrade() {
+                server.hide();
+            }
+        });
+        app.add_action(&dismiss);
+
+        // Activated when the pointer enters or leaves an interactive window
+        let hover = gio::SimpleAction::new("hover", None);
+        let server_for_hover = Rc::downgrade(&server);
+        hover.connect_activate(move |_, _| {
+            if let Some(server) = server_for_hover.upgrade() {
+                server.hover_changed();
+            }
+        });
+        app.add_action(&hover);
+
+        // Activated by scrolling over an interactive window, with the vertical steps
+        let scroll = gio::SimpleAction::new("scroll", Some(glib::VariantTy::DOUBLE));
+        let server_for_scroll = Rc::downgrade(&server);
+        scroll.connect_activate(move |_, dy| {
+            let dy = dy.and_then(|dy| dy.get::<f64>()).unwrap_or(0.0);
+            if let Some(server) = server_for_scroll.upgrade() {
+                server.scrolled(dy);
+            }
+        });
+        app.add_action(&scroll);
+
+        let server_for_dbus = server.clone();
+        dbus::export(app, move |request| {
+            match request {
+                dbus::Request::Show(msg) => server_for_dbus.handle_messages(vec![*msg]),
+                dbus::Request::Hide => server_for_dbus.hide(),
+                dbus::Request::GetStats => {
+                    return Some((server_for_dbus.status().to_json(),).to_variant());
                 }
+            }
+            None
+        });
 
-                glib::ControlFlow::Continue
+        if let Some(interval) = demo {
+            let server = server.clone();
+            glib::spawn_future_local(async move {
+                run_demo(&server, interval).await;
             });
         }
+
+        glib::spawn_future_local(async move {
+            while let Some(batch) = receiver.recv_batch().await {
+                // In sequential mode every message gets its turn on screen
+                let batch = match server.config.borrow().queue_mode {
+                    QueueMode::Sequential => batch,
+                    QueueMode::Replace => ingest::coalesce(batch),
+                };
+                server.handle_messages(batch);
+
+                // Whatever arrives meanwhile is reduced to the latest message per
+                // tag and id and shown together once the interval is up
+                let min_interval = server.config.borrow().ingest.min_interval();
+                if !min_interval.is_zero() {
+                    glib::timeout_future(min_interval).await;
+                }
+            }
+        });
+        debug!("Ready {:?} after startup", started.elapsed());
     });
 
-    application.run();
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        let app = application.clone();
+        glib::unix_signal_add_local(signal, move || {
+            info!("Received signal {}, shutting down", signal);
+            app.quit();
+            glib::ControlFlow::Continue
+        });
+    }
+
+    application.run_with_args::<&str>(&[]);
+
+    // Leave nothing behind for the next server to trip over
+    for path in std::iter::once(&pipe_path).chain(&owned_socket) {
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
     Ok(())
 }
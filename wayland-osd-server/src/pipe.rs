@@ -0,0 +1,178 @@
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use anyhow::Context;
+use log::{debug, error, info, trace};
+use nix::sys::stat;
+use wayland_osd_core::capture::CaptureWriter;
+use wayland_osd_core::config::{Config, ParseMode};
+use wayland_osd_core::ingest::IngestSender;
+use wayland_osd_core::ipc::{ErrorCode, ErrorReply, FrameDecoder, FrameError};
+use wayland_osd_core::OsdMessage;
+
+pub fn setup_pipe(path: &Path) -> anyhow::Result<()> {
+    debug!("Setting up named pipe at {}", path.display());
+
+    if let Some(dir) = path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    // Remove existing pipe if it exists
+    if path.exists() {
+        debug!("Removing existing pipe");
+        fs::remove_file(path)?;
+    }
+
+    // Only the user running the server may send to it
+    debug!("Creating new pipe with permissions");
+    nix::unistd::mkfifo(path, stat::Mode::S_IRUSR | stat::Mode::S_IWUSR)?;
+
+    info!("Named pipe setup complete");
+    Ok(())
+}
+
+/// Capture file shared by every reader thread, unset once writing to it failed
+pub type SharedCapture = Arc<Mutex<Option<CaptureWriter>>>;
+
+/// How the reader threads treat what they read, taken from the config
+#[derive(Debug, Clone, Copy)]
+pub struct ReadSettings {
+    pub parsing: ParseMode,
+    /// Check socket messages before they are queued, so the client can be told
+    /// why one was rejected
+    pub validate: bool,
+}
+
+impl ReadSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            parsing: config.ingest.parsing,
+            // A script may turn messages the server doesn't know into ones it does
+            validate: config.script.is_none(),
+        }
+    }
+}
+
+/// Read settings shared by every reader thread, replaced whenever the config is applied
+pub type SharedSettings = Arc<RwLock<ReadSettings>>;
+
+/// Messages read from a client, and why the others were rejected
+#[derive(Debug, Default)]
+pub struct Decoded {
+    pub messages: Vec<OsdMessage>,
+    pub rejected: Vec<ErrorReply>,
+}
+
+/// Split newly read bytes into messages, recording them to `capture` if set
+pub fn decode_messages(
+    decoder: &mut FrameDecoder,
+    data: &[u8],
+    sender: &IngestSender,
+    capture: &SharedCapture,
+    parsing: ParseMode,
+) -> Decoded {
+    let mut decoded = Decoded::default();
+
+    for frame in decoder.push(data) {
+        let msg_str = match frame {
+            Ok(msg_str) => msg_str,
+            Err(e) => {
+                match e {
+                    FrameError::TooLarge(_) => sender.stats().record_oversized(),
+                    FrameError::InvalidUtf8 => sender.stats().record_rejected(),
+                }
+                error!("{}", e);
+                decoded.rejected.push(ErrorReply::new(ErrorCode::Frame, e));
+                continue;
+            }
+        };
+
+        trace!("Received raw message: {}", msg_str);
+        let parsed = match parsing {
+            ParseMode::Lenient => OsdMessage::from_json(&msg_str).map_err(|e| e.to_string()),
+            ParseMode::Strict => OsdMessage::from_json_strict(&msg_str).map_err(|e| e.to_string()),
+        };
+        match parsed {
+            Ok(msg) => {
+                let mut capture = capture.lock().unwrap();
+                if let Some(writer) = capture.as_mut() {
+                    if let Err(e) = writer.record(&msg) {
+                        error!("{:#}, no longer capturing messages", e);
+                        *capture = None;
+                    }
+                }
+                decoded.messages.push(msg);
+            }
+            Err(e) => {
+                if parsing == ParseMode::Strict {
+                    error!("Rejected message, {}: {}", e, msg_str);
+                } else {
+                    error!("Failed to parse message: {}", msg_str);
+                }
+                sender.stats().record_rejected();
+                decoded.rejected.push(ErrorReply::new(ErrorCode::Parse, e));
+            }
+        }
+    }
+
+    decoded
+}
+
+/// Read messages from the pipe on a dedicated thread and queue them for the UI
+pub fn spawn_reader(
+    path: &Path,
+    sender: IngestSender,
+    capture: SharedCapture,
+    settings: SharedSettings,
+) -> anyhow::Result<()> {
+    // Opening for writing too keeps a writer around, so reads block instead of
+    // returning EOF whenever the last client disconnects
+    let mut pipe = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .context("Failed to open pipe")?;
+    trace!("Successfully opened pipe");
+
+    thread::Builder::new()
+        .name("pipe-reader".to_string())
+        .spawn(move || {
+            let mut decoder = FrameDecoder::new();
+            let mut read_buffer = [0u8; 1024];
+
+            loop {
+                let n = match pipe.read(&mut read_buffer) {
+                    Ok(0) => {
+                        trace!("EOF received, continuing to next iteration");
+                        continue;
+                    }
+                    Ok(n) => n,
+                    Err(e) => {
+                        error!("Error reading from pipe: {}", e);
+                        return;
+                    }
+                };
+
+                // Everything from one read is handed over together, there is
+                // nobody to tell about rejected messages
+                let parsing = settings.read().unwrap().parsing;
+                let decoded =
+                    decode_messages(&mut decoder, &read_buffer[..n], &sender, &capture, parsing);
+                if !sender.send_batch(decoded.messages, None) {
+                    debug!("UI has gone away, stopping pipe reader");
+                    return;
+                }
+            }
+        })
+        .context("Failed to spawn pipe reader thread")?;
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use std::cell::{Cell, Ref, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use gtk::glib;
+use log::debug;
+use wayland_osd_core::{Config, OsdState};
+
+use crate::surfaces::Surfaces;
+
+/// The window of one category of messages, with its own state and hide timeout
+pub struct Popup {
+    app: gtk::Application,
+    /// The server's config with the category's overrides
+    config: RefCell<Config>,
+    pub state: RefCell<OsdState>,
+    /// Built when the first message arrives, so startup doesn't pay for it
+    ui: RefCell<Option<Rc<Surfaces>>>,
+    timeout_source_id: RefCell<Option<glib::SourceId>>,
+    /// Updates the countdown on screen every second
+    countdown_source_id: RefCell<Option<glib::SourceId>>,
+    /// Generation and timeout of the message on screen until it's hidden, kept while paused
+    pending_hide: Cell<Option<(u64, Duration)>>,
+    started: Instant,
+}
+
+impl Popup {
+    pub fn new(app: &gtk::Application, config: Config, started: Instant) -> Rc<Self> {
+        Rc::new(Self {
+            app: app.clone(),
+            config: RefCell::new(config),
+            state: RefCell::new(OsdState::new()),
+            ui: RefCell::new(None),
+            timeout_source_id: RefCell::new(None),
+            countdown_source_id: RefCell::new(None),
+            pending_hide: Cell::new(None),
+            started,
+        })
+    }
+
+    pub fn config(&self) -> Ref<'_, Config> {
+        self.config.borrow()
+    }
+
+    /// Switch to new settings, the window is rebuilt the next time it is shown
+    ///
+    /// The message on screen goes away with the old window, returns whether there was one.
+    pub fn set_config(&self, config: Config) -> bool {
+        self.cancel_hide();
+        let was_visible = self.state.borrow_mut().hide();
+        if let Some(ui) = self.ui.borrow_mut().take() {
+            ui.destroy();
+        }
+        *self.config.borrow_mut() = config;
+        was_visible
+    }
+
+    pub fn ui(&self) -> Rc<Surfaces> {
+        self.ui
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                let ui = Surfaces::new(&self.app, &self.config.borrow());
+                debug!("Built UI {:?} after startup", self.started.elapsed());
+                ui
+            })
+            .clone()
+    }
+
+    /// Replace the pending hide timeout, if any
+    pub fn set_timeout(&self, source_id: Option<glib::SourceId>) {
+        if let Some(previous) = self.timeout_source_id.replace(source_id) {
+            previous.remove();
+        }
+    }
+
+    /// Forget the hide timeout once it has fired
+    pub fn timeout_fired(&self) {
+        self.timeout_source_id.borrow_mut().take();
+        self.pending_hide.set(None);
+    }
+
+    /// Drop the hide timeout because the window is hidden already
+    pub fn cancel_hide(&self) {
+        self.set_timeout(None);
+        self.set_countdown(None);
+        self.pending_hide.set(None);
+    }
+
+    /// Drop the hide timeout, so the message on screen stays until it's replaced or dismissed
+    pub fn keep_shown(&self) {
+        self.set_timeout(None);
+        self.pending_hide.set(None);
+    }
+
+    /// Replace the timer updating the countdown on screen, if any
+    pub fn set_countdown(&self, source_id: Option<glib::SourceId>) {
+        if let Some(previous) = self.countdown_source_id.replace(source_id) {
+            previous.remove();
+        }
+    }
+
+    /// Forget the countdown timer once the countdown ran out
+    pub fn countdown_finished(&self) {
+        self.countdown_source_id.borrow_mut().take();
+    }
+
+    /// Remember when the message on screen is due to be hidden
+    pub fn set_pending_hide(&self, generation: u64, hide_after: Duration) {
+        self.pending_hide.set(Some((generation, hide_after)));
+    }
+
+    pub fn pending_hide(&self) -> Option<(u64, Duration)> {
+        self.pending_hide.get()
+    }
+
+    pub fn has_timeout(&self) -> bool {
+        self.timeout_source_id.borrow().is_some()
+    }
+
+    /// Whether the pointer is over the window, which pauses its hide timeout
+    pub fn is_hovered(&self) -> bool {
+        self.ui.borrow().as_ref().is_some_and(|ui| ui.is_hovered())
+    }
+}
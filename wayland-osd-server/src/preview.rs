@@ -0,0 +1,45 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use log::info;
+use wayland_osd_core::{demo, Config, OsdState};
+
+use crate::ui::UiElements;
+
+/// Render every demo message with the given config into `dir` as PNG files
+pub async fn render(app: &gtk::Application, mut config: Config, dir: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    // Samples should be captured at their final value, in a window that stays mapped
+    config.animation.progress_duration_ms = 0;
+    config.animation.icon_crossfade_ms = 0;
+    config.animation.fade_in_ms = 0;
+    config.animation.fade_out_ms = 0;
+    config.window.unmap_when_hidden = false;
+
+    let ui = UiElements::new(app, &config, None);
+    let mut state = OsdState::new();
+    ui.show();
+    ui.hide();
+
+    for (name, msg) in demo::samples() {
+        let layout = config.layout(&msg.message_type);
+        ui.set_classes(&msg.window_classes());
+        let Some(update) = state.handle(msg, &config)? else {
+            continue;
+        };
+        ui.apply(&update.view, &layout);
+
+        // The first frame picks up the new contents, the second has them laid out
+        ui.next_frame().await;
+        ui.next_frame().await;
+
+        let path = dir.join(format!("{}.png", name));
+        ui.render_to_png(&path)?;
+        info!("Rendered {}", path.display());
+    }
+
+    ui.destroy();
+    Ok(())
+}
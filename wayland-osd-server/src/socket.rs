@@ -0,0 +1,203 @@
+use std::fs::{self, DirBuilder, Permissions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+
+use anyhow::Context;
+use log::{debug, error, info, trace, warn};
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+use wayland_osd_core::config::Config;
+use wayland_osd_core::ingest::IngestSender;
+use wayland_osd_core::ipc::{ErrorCode, ErrorReply, FrameDecoder};
+use wayland_osd_core::message::Peer;
+use wayland_osd_core::router::Router;
+
+use crate::pipe::{decode_messages, SharedCapture, SharedSettings};
+
+/// First file descriptor passed by systemd, see sd_listen_fds(3)
+const LISTEN_FDS_START: i32 = 3;
+
+/// Take over the listening socket passed by systemd socket activation, if any
+///
+/// The variables are removed afterwards, so hooks started by the server
+/// don't mistake themselves for the activated service.
+pub fn inherited_listener() -> Option<UnixListener> {
+    let pid = std::env::var("LISTEN_PID").ok()?;
+    let fds = std::env::var("LISTEN_FDS").ok()?;
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    if pid.parse::<u32>().ok()? != std::process::id() {
+        debug!("LISTEN_FDS is meant for another process, ignoring it");
+        return None;
+    }
+    match fds.parse::<i32>() {
+        Ok(1) => {}
+        Ok(n) if n > 1 => warn!("Got {} sockets from systemd, only using the first", n),
+        _ => return None,
+    }
+
+    // Not inherited by hooks and scripts
+    if let Err(e) = fcntl(LISTEN_FDS_START, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)) {
+        warn!("Failed to set close-on-exec on the activated socket: {}", e);
+    }
+
+    info!("Using socket passed by systemd");
+    // SAFETY: systemd passed this descriptor to us and nothing else has taken it
+    Some(unsafe { UnixListener::from_raw_fd(LISTEN_FDS_START) })
+}
+
+/// Bind the socket, replacing one left behind by a previous server
+pub fn setup_socket(path: &Path) -> anyhow::Result<UnixListener> {
+    debug!("Setting up socket at {}", path.display());
+
+    if let Some(dir) = path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    match fs::remove_file(path) {
+        Ok(()) => debug!("Removed existing socket"),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context("Failed to remove existing socket"),
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind socket {}", path.display()))?;
+    // Only the user running the server may connect
+    fs::set_permissions(path, Permissions::from_mode(0o600))?;
+
+    info!("Socket setup complete");
+    Ok(listener)
+}
+
+/// Accept clients on a dedicated thread, reading each connection on its own
+///
+/// Every connection has its own decoder, so messages from clients writing at
+/// the same time can't end up mixed into each other.
+pub fn spawn_listener(
+    listener: UnixListener,
+    sender: IngestSender,
+    capture: SharedCapture,
+    settings: SharedSettings,
+) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name("socket-listener".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        error!("Failed to accept connection: {}", e);
+                        continue;
+                    }
+                };
+
+                let sender = sender.clone();
+                let capture = capture.clone();
+                let settings = settings.clone();
+                let spawned = thread::Builder::new()
+                    .name("socket-client".to_string())
+                    .spawn(move || read_client(stream, sender, capture, settings));
+                if let Err(e) = spawned {
+                    error!("Failed to spawn socket client thread: {}", e);
+                }
+            }
+        })
+        .context("Failed to spawn socket listener thread")?;
+
+    Ok(())
+}
+
+/// Read messages from one client until it disconnects, replying to every rejected one
+fn read_client(
+    mut stream: UnixStream,
+    sender: IngestSender,
+    capture: SharedCapture,
+    settings: SharedSettings,
+) {
+    // Passed on with every message, so the journal can tell clients apart
+    let peer = getsockopt(stream.as_raw_fd(), PeerCredentials)
+        .map(|credentials| Peer {
+            pid: credentials.pid(),
+            uid: credentials.uid(),
+        })
+        .ok();
+    let client_pid = peer.map(|peer| peer.pid);
+    trace!(client_pid:?; "Client connected");
+    let mut decoder = FrameDecoder::new();
+    let mut read_buffer = [0u8; 1024];
+    // Only the built-in types, their handlers don't depend on the config
+    let router = Router::default();
+    let config = Config::default();
+
+    loop {
+        let n = match stream.read(&mut read_buffer) {
+            Ok(0) => {
+                trace!("Client disconnected");
+                return;
+            }
+            Ok(n) => n,
+            Err(e) => {
+                error!("Error reading from socket: {}", e);
+                return;
+            }
+        };
+
+        // Read again every time, so reloading the config applies to connected clients too
+        let settings = *settings.read().unwrap();
+        let mut decoded = decode_messages(
+            &mut decoder,
+            &read_buffer[..n],
+            &sender,
+            &capture,
+            settings.parsing,
+        );
+        if settings.validate {
+            decoded.messages.retain(|msg| {
+                if msg.is_control() {
+                    return true;
+                }
+                match router.route(msg.clone(), &config) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        warn!("Ignoring message: {}", e);
+                        sender.stats().record_rejected();
+                        decoded
+                            .rejected
+                            .push(ErrorReply::new(ErrorCode::Invalid, e));
+                        false
+                    }
+                }
+            });
+        }
+
+        // Clients that don't wait for replies have usually hung up already
+        for reply in &decoded.rejected {
+            if let Err(e) = stream.write_all(&reply.to_frame()) {
+                trace!("Failed to reply to client: {}", e);
+                break;
+            }
+        }
+
+        for msg in &decoded.messages {
+            debug!(
+                client_pid:?,
+                message_type = msg.message_type.as_str();
+                "Received {} message from client", msg.message_type
+            );
+        }
+        if !sender.send_batch(decoded.messages, peer) {
+            debug!("UI has gone away, closing client connection");
+            return;
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use gtk::{gdk, gio, glib, prelude::*};
+use log::{debug, info, warn};
+use wayland_osd_core::config::{ColorScheme, StyleConfig};
+use wayland_osd_core::Config;
+
+const BUILTIN_CSS: &str = "
+    window {
+        background-color: rgba(0, 0, 0, 0.8);
+        transform: translateX(-50%);
+        border-radius: 10px;
+    }
+    .osd-overlay {
+        margin-left: 10px;
+        margin-right: 10px;
+        margin-top: 5px;
+        margin-bottom: 5px;
+        padding: 10px;
+    }
+    progressbar {
+        min-height: 10px;
+    }
+    progressbar trough {
+        min-height: 10px;
+        background-color: rgba(100, 100, 100, 0.7);
+        border-radius: 5px;
+    }
+    progressbar progress {
+        min-height: 10px;
+        background-color: #729fcf;
+        border-radius: 5px;
+    }
+    .bar-overlay {
+        color: #729fcf;
+    }
+    progressbar.mic progress {
+        background-color: #ad7fa8;
+    }
+    progressbar.critical progress {
+        background-color: #cc0000;
+    }
+    progressbar.overamplified progress {
+        background-color: #cc0000;
+    }
+    progressbar.overamplified trough {
+        background-color: rgba(204, 0, 0, 0.3) !important;
+    }
+    progressbar.muted, .bar-overlay.muted, .value-label.muted {
+        opacity: 0.4;
+        filter: grayscale(1);
+    }
+    label {
+        color: white;
+        font-size: 16px;
+    }
+    image {
+        color: white;
+    }
+    .value-label {
+        font-size: 14px;
+    }
+    .device-label {
+        color: #cccccc;
+        font-size: 12px;
+        margin-top: -10px;
+        margin-bottom: -10px;
+    }
+";
+
+/// Overrides of the built-in styles for light color schemes
+const LIGHT_CSS: &str = "
+    window {
+        background-color: rgba(255, 255, 255, 0.85);
+    }
+    label, image {
+        color: #2e3436;
+    }
+    .device-label {
+        color: #555753;
+    }
+";
+
+/// Replace the rules of `provider` with `css`
+///
+/// GTK 4.12 renamed the call, and the `announce` feature builds against a newer GTK.
+pub fn load_css(provider: &gtk::CssProvider, css: &str) {
+    #[cfg(feature = "announce")]
+    provider.load_from_string(css);
+    #[cfg(not(feature = "announce"))]
+    provider.load_from_data(css);
+}
+
+fn builtin_css(light: bool) -> String {
+    if light {
+        format!("{}{}", BUILTIN_CSS, LIGHT_CSS)
+    } else {
+        BUILTIN_CSS.to_string()
+    }
+}
+
+/// The stylesheets registered for the display: the built-in one and the user's
+///
+/// The user's file is watched and reloaded whenever it changes, so edits show
+/// up without restarting the server.
+pub struct Stylesheet {
+    display: gdk::Display,
+    builtin: Option<gtk::CssProvider>,
+    user: Option<gtk::CssProvider>,
+    _monitor: Option<gio::FileMonitor>,
+    /// Reports changes of the desktop's color scheme while it's kept around,
+    /// set once the settings portal has answered
+    _portal: Rc<RefCell<Option<gio::DBusProxy>>>,
+}
+
+impl Stylesheet {
+    /// Register the stylesheets ahead of building any widgets
+    pub fn install(config: &StyleConfig) -> Self {
+        let display = gdk::Display::default().expect("Could not get default display");
+
+        let path = config
+            .file
+            .as_deref()
+            .map(Config::resolve_path)
+            .filter(|path| path.exists());
+
+        let builtin = (!config.replace_builtin || path.is_none()).then(|| {
+            let provider = gtk::CssProvider::new();
+            gtk::style_context_add_provider_for_display(
+                &display,
+                &provider,
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+            );
+            provider
+        });
+        let portal = Rc::new(RefCell::new(None));
+        if let Some(provider) = &builtin {
+            follow_color_scheme(config.color_scheme, provider, &portal);
+        }
+
+        let Some(path) = path else {
+            return Self {
+                display,
+                builtin,
+                user: None,
+                _monitor: None,
+                _portal: portal,
+            };
+        };
+
+        info!("Loading stylesheet from {}", path.display());
+        let user = gtk::CssProvider::new();
+        user.connect_parsing_error(|_provider, section, error| {
+            warn!("Stylesheet error at {}: {}", section, error);
+        });
+        user.load_from_path(&path);
+
+        // Added after the built-in one with the same priority, so its rules win
+        gtk::style_context_add_provider_for_display(
+            &display,
+            &user,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        );
+
+        let monitor = watch(&path, &user);
+        Self {
+            display,
+            builtin,
+            user: Some(user),
+            _monitor: monitor,
+            _portal: portal,
+        }
+    }
+
+    /// Unregister the stylesheets, e.g. before installing ones for another config
+    pub fn uninstall(&self) {
+        for provider in self.builtin.iter().chain(&self.user) {
+            gtk::style_context_remove_provider_for_display(&self.display, provider);
+        }
+    }
+}
+
+/// Load the built-in styles for the configured color scheme into `provider`
+///
+/// With `auto`, the dark styles apply until the settings portal has answered
+/// with the desktop's preference, so startup doesn't wait on D-Bus. The styles
+/// are then switched whenever it changes, for as long as `portal` holds the proxy.
+fn follow_color_scheme(
+    scheme: ColorScheme,
+    provider: &gtk::CssProvider,
+    portal: &Rc<RefCell<Option<gio::DBusProxy>>>,
+) {
+    load_css(provider, &builtin_css(scheme == ColorScheme::Light));
+    if scheme != ColorScheme::Auto {
+        return;
+    }
+
+    let provider = provider.clone();
+    let slot = Rc::downgrade(portal);
+    glib::spawn_future_local(async move {
+        let Some(portal) = settings_portal().await else {
+            return;
+        };
+        let light = read_color_scheme(&portal).await.is_some_and(prefers_light);
+        // The stylesheet may have been replaced by a reload meanwhile
+        let Some(slot) = slot.upgrade() else {
+            return;
+        };
+        debug!(
+            "Desktop prefers a {} color scheme",
+            if light { "light" } else { "dark" }
+        );
+        if light {
+            load_css(&provider, &builtin_css(true));
+        }
+
+        portal.connect_local("g-signal", false, move |values| {
+            let signal = values[2].get::<String>().ok()?;
+            let parameters = values[3].get::<glib::Variant>().ok()?;
+            let (namespace, key, value) = parameters.get::<(String, String, glib::Variant)>()?;
+            if signal == "SettingChanged" && namespace == APPEARANCE && key == COLOR_SCHEME {
+                let light = value.get::<u32>().is_some_and(prefers_light);
+                info!(
+                    "Switching to the {} styles",
+                    if light { "light" } else { "dark" }
+                );
+                load_css(&provider, &builtin_css(light));
+            }
+            None
+        });
+        *slot.borrow_mut() = Some(portal);
+    });
+}
+
+const APPEARANCE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME: &str = "color-scheme";
+
+/// 0 is no preference, 1 prefers dark and 2 prefers light
+fn prefers_light(color_scheme: u32) -> bool {
+    color_scheme == 2
+}
+
+async fn settings_portal() -> Option<gio::DBusProxy> {
+    gio::DBusProxy::for_bus_future(
+        gio::BusType::Session,
+        gio::DBusProxyFlags::DO_NOT_LOAD_PROPERTIES,
+        None,
+        "org.freedesktop.portal.Desktop",
+        "/org/freedesktop/portal/desktop",
+        "org.freedesktop.portal.Settings",
+    )
+    .await
+    .inspect_err(|e| debug!("Settings portal is unavailable: {}", e))
+    .ok()
+}
+
+async fn read_color_scheme(portal: &gio::DBusProxy) -> Option<u32> {
+    let reply = portal
+        .call_future(
+            "ReadOne",
+            Some(&(APPEARANCE, COLOR_SCHEME).to_variant()),
+            gio::DBusCallFlags::NONE,
+            1000,
+        )
+        .await
+        .inspect_err(|e| debug!("Can't read the color scheme: {}", e))
+        .ok()?;
+    reply.get::<(glib::Variant,)>()?.0.get::<u32>()
+}
+
+fn watch(path: &Path, provider: &gtk::CssProvider) -> Option<gio::FileMonitor> {
+    let monitor = gio::File::for_path(path)
+        .monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE)
+        .inspect_err(|e| warn!("Can't watch {} for changes: {}", path.display(), e))
+        .ok()?;
+
+    let path: PathBuf = path.to_path_buf();
+    let provider = provider.clone();
+    monitor.connect_changed(move |_monitor, _file, _other, event| {
+        // Editors often replace the file, which shows up as it being deleted and created again
+        let reload = matches!(
+            event,
+            gio::FileMonitorEvent::ChangesDoneHint
+                | gio::FileMonitorEvent::Created
+                | gio::FileMonitorEvent::MovedIn
+                | gio::FileMonitorEvent::Renamed
+        );
+
+        if reload && path.exists() {
+            debug!("Reloading stylesheet {}", path.display());
+            provider.load_from_path(&path);
+        }
+    });
+
+    Some(monitor)
+}
@@ -0,0 +1,146 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use gtk::{gdk, gio, glib, prelude::*};
+use log::debug;
+use wayland_osd_core::layout::Layout;
+use wayland_osd_core::{Config, View};
+
+use crate::ui::UiElements;
+
+/// Every OSD window: a single one, or one per output when mirroring
+pub struct Surfaces {
+    app: gtk::Application,
+    config: Config,
+    /// Monitor each window is pinned to, unset for the single window
+    windows: RefCell<Vec<(Option<gdk::Monitor>, UiElements)>>,
+    /// Last applied contents, for windows of outputs connected later
+    last: RefCell<Option<(View, Layout)>>,
+    classes: RefCell<Vec<String>>,
+    visible: Cell<bool>,
+    monitors_changed: RefCell<Option<(gio::ListModel, glib::SignalHandlerId)>>,
+}
+
+impl Surfaces {
+    pub fn new(app: &gtk::Application, config: &Config) -> Rc<Self> {
+        let surfaces = Rc::new(Self {
+            app: app.clone(),
+            config: config.clone(),
+            windows: RefCell::new(Vec::new()),
+            last: RefCell::new(None),
+            classes: RefCell::new(Vec::new()),
+            visible: Cell::new(false),
+            monitors_changed: RefCell::new(None),
+        });
+
+        if !config.window.show_on_all_outputs {
+            let ui = UiElements::new(app, config, None);
+            surfaces.windows.borrow_mut().push((None, ui));
+            return surfaces;
+        }
+
+        let Some(display) = gdk::Display::default() else {
+            return surfaces;
+        };
+        let monitors = display.monitors();
+        surfaces.sync(&monitors);
+
+        let weak = Rc::downgrade(&surfaces);
+        let handler = monitors.connect_items_changed(move |monitors, _, _, _| {
+            if let Some(surfaces) = weak.upgrade() {
+                surfaces.sync(monitors);
+            }
+        });
+        *surfaces.monitors_changed.borrow_mut() = Some((monitors, handler));
+
+        surfaces
+    }
+
+    /// Match the mirrored windows to the connected monitors
+    fn sync(&self, monitors: &gio::ListModel) {
+        let connected: Vec<gdk::Monitor> = (0..monitors.n_items())
+            .filter_map(|i| monitors.item(i).and_downcast())
+            .collect();
+
+        let mut windows = self.windows.borrow_mut();
+        windows.retain(|(monitor, ui)| {
+            let keep = monitor
+                .as_ref()
+                .is_some_and(|monitor| connected.contains(monitor));
+            if !keep {
+                ui.destroy();
+            }
+            keep
+        });
+
+        for monitor in connected {
+            if windows
+                .iter()
+                .any(|(existing, _)| existing.as_ref() == Some(&monitor))
+            {
+                continue;
+            }
+
+            debug!("Mirroring OSD on output {:?}", monitor.connector());
+            let ui = UiElements::new(&self.app, &self.config, Some(&monitor));
+            ui.set_classes(&self.classes.borrow());
+            if let Some((view, layout)) = &*self.last.borrow() {
+                ui.apply(view, layout);
+                if self.visible.get() {
+                    ui.show();
+                }
+            }
+            windows.push((Some(monitor), ui));
+        }
+    }
+
+    fn each(&self, f: impl Fn(&UiElements)) {
+        for (_, ui) in self.windows.borrow().iter() {
+            f(ui);
+        }
+    }
+
+    pub fn set_output(&self, output: Option<&str>) {
+        self.each(|ui| ui.set_output(output));
+    }
+
+    pub fn set_classes(&self, classes: Vec<String>) {
+        self.each(|ui| ui.set_classes(&classes));
+        *self.classes.borrow_mut() = classes;
+    }
+
+    pub fn apply(&self, view: &View, layout: &Layout) {
+        self.each(|ui| ui.apply(view, layout));
+        *self.last.borrow_mut() = Some((view.clone(), layout.clone()));
+    }
+
+    /// Tell screen readers about the view, once rather than for every window
+    pub fn announce(&self, view: &View, message_type: &str) {
+        if let Some((_, ui)) = self.windows.borrow().first() {
+            ui.announce(view, message_type);
+        }
+    }
+
+    pub fn is_hovered(&self) -> bool {
+        self.windows.borrow().iter().any(|(_, ui)| ui.is_hovered())
+    }
+
+    pub fn show(&self) {
+        self.visible.set(true);
+        self.each(UiElements::show);
+    }
+
+    pub fn hide(&self) {
+        self.visible.set(false);
+        self.each(UiElements::hide);
+    }
+
+    pub fn destroy(&self) {
+        if let Some((monitors, handler)) = self.monitors_changed.borrow_mut().take() {
+            monitors.disconnect(handler);
+        }
+        for (_, ui) in self.windows.borrow_mut().drain(..) {
+            ui.destroy();
+        }
+    }
+}
@@ -0,0 +1,1299 @@
+use std::cell::{Cell, RefCell};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use gtk::{gdk, gio, glib, prelude::*};
+use gtk4_layer_shell::{Edge, Layer, LayerShell};
+use log::{debug, error, trace, warn};
+use wayland_osd_core::config::{
+    self, BarConfig, FontConfig, TextConfig, ThemeConfig, WindowConfig,
+};
+use wayland_osd_core::layout::{Layout, LayoutAlign, LayoutElement};
+use wayland_osd_core::{Config, MediaView, ProgressView, TextView, View};
+
+use crate::animation::Animation;
+use crate::blur::NAMESPACE;
+use crate::icons::{load_artwork, IconCache};
+use crate::style::load_css;
+
+pub struct UiElements {
+    window: gtk::ApplicationWindow,
+    /// Unset for windows pinned to a monitor
+    outputs: Option<Rc<OutputPlacement>>,
+    /// Holds the layout rows, unset when the widgets come from a `.ui` file
+    main_box: Option<gtk::Box>,
+    progress_bar: gtk::ProgressBar,
+    /// The bar together with its marker line
+    bar_widget: gtk::Widget,
+    label: gtk::Label,
+    device_label: gtk::Label,
+    value_label: gtk::Label,
+    /// The value is drawn on top of the bar rather than placed by the layout
+    value_inside: bool,
+    /// Takes input while shown instead of letting it through
+    interactive: bool,
+    /// The pointer is over the window, only tracked when interactive
+    hovered: Rc<Cell<bool>>,
+    /// Reserves space along its edge while shown
+    exclusive_zone: bool,
+    width_percent: Option<u32>,
+    artwork: gtk::Picture,
+    /// Counts requested covers, so one that loads late doesn't replace a newer one
+    artwork_request: Rc<Cell<u64>>,
+    icon: CrossfadeIcon,
+    drawing_area: gtk::DrawingArea,
+    /// What the drawing area on top of the bar draws
+    bar_overlay: Rc<RefCell<BarOverlay>>,
+    icons: IconCache,
+    /// Holds the fill color picked by `bar_colors`
+    color_provider: gtk::CssProvider,
+    /// Sizes and looks from the `[bar]`, `[window]` and `[theme]` sections
+    bar_provider: gtk::CssProvider,
+    /// Extra CSS classes currently set on the bar and the label by a view
+    bar_class: RefCell<Option<String>>,
+    label_class: RefCell<Option<String>>,
+    /// CSS classes of the window set by the message on screen
+    window_classes: RefCell<Vec<String>>,
+    progress_animation: Animation,
+    /// Window opacity, for fading in and out
+    fade: Animation,
+    fade_in: Duration,
+    fade_out: Duration,
+    /// Window opacity once faded in
+    opacity: f64,
+    progress_duration: Duration,
+    /// The view the widgets are currently configured for
+    applied: RefCell<Option<View>>,
+    /// The layout the widgets are currently arranged in
+    layout: RefCell<Option<Layout>>,
+    /// Scale of the output the view was applied on, icons are rendered for it
+    scale: Cell<i32>,
+}
+
+/// Lines and blocks drawn on top of the bar, shared with the draw function
+#[derive(Debug, Default)]
+struct BarOverlay {
+    /// Positions of the marker lines as fractions of the width
+    markers: Vec<f64>,
+    /// Positions of the tick marks as fractions of the width
+    ticks: Vec<f64>,
+    /// Number of blocks the bar is drawn as, the progress bar is hidden meanwhile
+    segments: Option<u32>,
+    fraction: f64,
+    /// Where overamplified blocks start, as a fraction of the width
+    normal_end: f64,
+    /// Fill color picked by `bar_colors`, if any
+    color: Option<gdk::RGBA>,
+}
+
+impl BarOverlay {
+    fn is_empty(&self) -> bool {
+        self.markers.is_empty() && self.ticks.is_empty() && self.segments.is_none()
+    }
+
+    fn draw_segments(
+        &self,
+        area: &gtk::DrawingArea,
+        cr: &gtk::cairo::Context,
+        width: f64,
+        height: f64,
+    ) {
+        let Some(segments) = self.segments else {
+            return;
+        };
+
+        const GAP: f64 = 2.0;
+        let count = segments as f64;
+        let block = (width - GAP * (count - 1.0)) / count;
+        let filled = (self.fraction * count).round() as u32;
+        #[allow(deprecated)]
+        let color = self.color.unwrap_or_else(|| area.style_context().color());
+
+        for i in 0..segments {
+            let end = (i + 1) as f64 / count;
+            let (red, green, blue, alpha) = if i >= filled {
+                (color.red(), color.green(), color.blue(), 0.3)
+            } else if end > self.normal_end + f64::EPSILON {
+                (0.8, 0.0, 0.0, 1.0)
+            } else {
+                (color.red(), color.green(), color.blue(), color.alpha())
+            };
+            cr.set_source_rgba(red.into(), green.into(), blue.into(), alpha.into());
+            cr.rectangle(i as f64 * (block + GAP), 0.0, block, height);
+            cr.fill().expect("Failed to draw segment");
+        }
+    }
+}
+
+/// An icon that crossfades to the next one when it changes
+struct CrossfadeIcon {
+    /// Unset for icons from a `.ui` file, which are swapped instantly
+    stack: Option<gtk::Stack>,
+    images: [gtk::Image; 2],
+    /// Index of the image currently shown
+    front: Cell<usize>,
+}
+
+impl CrossfadeIcon {
+    fn new() -> Self {
+        let images = [gtk::Image::new(), gtk::Image::new()];
+        let stack = gtk::Stack::builder()
+            .transition_type(gtk::StackTransitionType::Crossfade)
+            .build();
+        for image in &images {
+            stack.add_child(image);
+        }
+
+        Self {
+            stack: Some(stack),
+            images,
+            front: Cell::new(0),
+        }
+    }
+
+    fn from_image(image: gtk::Image) -> Self {
+        Self {
+            stack: None,
+            images: [image.clone(), image],
+            front: Cell::new(0),
+        }
+    }
+
+    fn widget(&self) -> gtk::Widget {
+        match &self.stack {
+            Some(stack) => stack.clone().upcast(),
+            None => self.images[0].clone().upcast(),
+        }
+    }
+
+    fn set_size(&self, size: i32) {
+        for image in &self.images {
+            image.set_pixel_size(size);
+        }
+    }
+
+    fn set_duration(&self, duration: Duration) {
+        if let Some(stack) = &self.stack {
+            stack.set_transition_duration(duration.as_millis() as u32);
+        }
+    }
+
+    fn set_paintable(&self, paintable: &impl IsA<gtk::gdk::Paintable>) {
+        let Some(stack) = &self.stack else {
+            self.images[0].set_paintable(Some(paintable));
+            return;
+        };
+
+        // Fill the hidden image and fade over to it
+        let back = 1 - self.front.get();
+        self.images[back].set_paintable(Some(paintable));
+        stack.set_visible_child(&self.images[back]);
+        self.front.set(back);
+    }
+}
+
+/// The widgets messages are bound to
+struct Widgets {
+    root: gtk::Widget,
+    main_box: Option<gtk::Box>,
+    icon: CrossfadeIcon,
+    progress_bar: gtk::ProgressBar,
+    bar_widget: gtk::Widget,
+    drawing_area: gtk::DrawingArea,
+    label: gtk::Label,
+    device_label: gtk::Label,
+    value_label: gtk::Label,
+    artwork: gtk::Picture,
+}
+
+impl Widgets {
+    /// The built-in widgets, arranged by the layout of the first message
+    fn build() -> Self {
+        // Rows are filled in by the layout of the first message
+        let main_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .css_classes(vec!["osd-overlay"])
+            .build();
+
+        // Icons are only decoded once a message needs them
+        let icon = CrossfadeIcon::new();
+        icon.widget().set_visible(false);
+
+        // Create an overlay for progress bar and marker line
+        let progress_overlay = gtk::Overlay::new();
+
+        let progress_bar = gtk::ProgressBar::new();
+        progress_bar.set_visible(false);
+        progress_overlay.set_child(Some(&progress_bar));
+
+        // Create drawing area for the marker line
+        let drawing_area = gtk::DrawingArea::new();
+        drawing_area.set_visible(false);
+        drawing_area.set_can_target(false);
+        progress_overlay.add_overlay(&drawing_area);
+
+        let label = gtk::Label::new(None);
+        label.set_visible(false);
+
+        let device_label = gtk::Label::new(None);
+        device_label.set_visible(false);
+        device_label.set_css_classes(&["device-label"]);
+
+        let value_label = gtk::Label::new(None);
+        value_label.set_visible(false);
+        value_label.set_css_classes(&["value-label"]);
+
+        // Covers are scaled down to the size the layout gives them
+        let artwork = gtk::Picture::builder()
+            .can_shrink(true)
+            .overflow(gtk::Overflow::Hidden)
+            .css_classes(vec!["artwork"])
+            .visible(false)
+            .build();
+
+        Self {
+            root: main_box.clone().upcast(),
+            main_box: Some(main_box),
+            icon,
+            progress_bar,
+            bar_widget: progress_overlay.upcast(),
+            drawing_area,
+            label,
+            device_label,
+            value_label,
+            artwork,
+        }
+    }
+
+    /// Load the widgets from a GtkBuilder file
+    ///
+    /// `osd-root` becomes the content of the window. Messages are bound to
+    /// `osd-icon`, `osd-bar`, `osd-label`, `osd-caption`, `osd-value`, `osd-marker`
+    /// and `osd-artwork`, any of which may be left out to never show that part.
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let builder = gtk::Builder::new();
+        builder
+            .add_from_file(path)
+            .with_context(|| format!("Failed to load UI file {}", path.display()))?;
+
+        let root: gtk::Widget = builder
+            .object("osd-root")
+            .with_context(|| format!("UI file {} has no `osd-root` widget", path.display()))?;
+
+        // Stand-ins for left out widgets are never added to the window
+        let progress_bar = builder
+            .object::<gtk::ProgressBar>("osd-bar")
+            .unwrap_or_default();
+
+        Ok(Self {
+            root,
+            main_box: None,
+            icon: CrossfadeIcon::from_image(builder.object("osd-icon").unwrap_or_default()),
+            bar_widget: progress_bar.clone().upcast(),
+            progress_bar,
+            drawing_area: builder.object("osd-marker").unwrap_or_default(),
+            label: builder.object("osd-label").unwrap_or_default(),
+            device_label: builder.object("osd-caption").unwrap_or_default(),
+            value_label: builder.object("osd-value").unwrap_or_default(),
+            artwork: builder.object("osd-artwork").unwrap_or_default(),
+        })
+    }
+}
+
+fn layer_shell_edge(edge: config::Edge) -> Edge {
+    match edge {
+        config::Edge::Top => Edge::Top,
+        config::Edge::Bottom => Edge::Bottom,
+        config::Edge::Left => Edge::Left,
+        config::Edge::Right => Edge::Right,
+    }
+}
+
+/// Keeps the window on the most preferred connected output
+///
+/// Re-evaluated whenever outputs come and go, so the OSD falls back when the
+/// preferred output is unplugged and moves back once it returns.
+struct OutputPlacement {
+    window: glib::WeakRef<gtk::ApplicationWindow>,
+    config: WindowConfig,
+    /// Output asked for by the message being shown
+    requested: RefCell<Option<String>>,
+}
+
+impl OutputPlacement {
+    fn new(window: &gtk::ApplicationWindow, config: &WindowConfig) -> Rc<Self> {
+        let placement = Rc::new(Self {
+            window: window.downgrade(),
+            config: config.clone(),
+            requested: RefCell::new(None),
+        });
+
+        let monitors = WidgetExt::display(window).monitors();
+        let weak = Rc::downgrade(&placement);
+        monitors.connect_items_changed(move |monitors, _, _, _| {
+            if let Some(placement) = weak.upgrade() {
+                placement.place(monitors);
+            }
+        });
+
+        placement.place(&monitors);
+        placement
+    }
+
+    fn request(&self, output: Option<&str>) {
+        if self.requested.borrow().as_deref() == output {
+            return;
+        }
+        *self.requested.borrow_mut() = output.map(str::to_string);
+
+        if let Some(window) = self.window.upgrade() {
+            self.place(&WidgetExt::display(&window).monitors());
+        }
+    }
+
+    fn place(&self, monitors: &gio::ListModel) {
+        let requested = self.requested.borrow();
+        let requested = requested.as_deref();
+        // Left to the compositor unless something asks for a particular output
+        if !self.config.selects_output(requested) {
+            return;
+        }
+        let Some(window) = self.window.upgrade() else {
+            return;
+        };
+
+        let monitors: Vec<gdk::Monitor> = (0..monitors.n_items())
+            .filter_map(|i| monitors.item(i).and_downcast())
+            .collect();
+        let connectors: Vec<String> = monitors
+            .iter()
+            .map(|monitor| monitor.connector().map(String::from).unwrap_or_default())
+            .collect();
+        let connected: Vec<&str> = connectors.iter().map(String::as_str).collect();
+
+        // Without any of the listed outputs, the first one is at least predictable
+        let index = self
+            .config
+            .preferred_output(requested, &connected)
+            .unwrap_or(0);
+        if let Some(monitor) = monitors.get(index) {
+            debug!("Placing OSD on output {}", connected[index]);
+            window.set_monitor(monitor);
+        }
+    }
+}
+
+/// At least `percent` of the width of the output the window is on
+///
+/// Windows the compositor places only know their output once mapped, until
+/// then GDK's best guess is used.
+fn fit_width(window: &gtk::ApplicationWindow, percent: u32) {
+    let monitor = window.monitor().or_else(|| {
+        let surface = window.surface()?;
+        WidgetExt::display(window).monitor_at_surface(&surface)
+    });
+    let Some(monitor) = monitor else {
+        return;
+    };
+
+    let width = monitor.geometry().width() * percent.min(100) as i32 / 100;
+    window.set_size_request(width, -1);
+}
+
+/// Pass through colors GTK understands, so they can be put into CSS
+fn valid_color(color: &str) -> Option<&str> {
+    if gtk::gdk::RGBA::parse(color).is_err() {
+        warn!("Ignoring invalid color: {}", color);
+        return None;
+    }
+    Some(color)
+}
+
+/// Wrap and ellipsize long text as configured in `[text]`
+fn fit_text(label: &gtk::Label, text: &TextConfig) {
+    label.set_max_width_chars(text.max_width_chars);
+    label.set_wrap(text.wrap);
+    label.set_wrap_mode(gtk::pango::WrapMode::WordChar);
+    label.set_lines(if text.max_lines > 0 {
+        text.max_lines
+    } else {
+        -1
+    });
+    label.set_ellipsize(match text.ellipsize {
+        config::Ellipsize::None => gtk::pango::EllipsizeMode::None,
+        config::Ellipsize::Start => gtk::pango::EllipsizeMode::Start,
+        config::Ellipsize::Middle => gtk::pango::EllipsizeMode::Middle,
+        config::Ellipsize::End => gtk::pango::EllipsizeMode::End,
+    });
+    label.set_justify(gtk::Justification::Center);
+}
+
+/// Sizes and looks from the `[bar]`, `[window]`, `[theme]` and `[fonts]` sections as CSS
+fn size_css(config: &Config) -> String {
+    let mut css = bar_css(&config.bar);
+    if let Some(padding) = config.window.padding {
+        css.push_str(&format!(".osd-overlay {{ padding: {}px; }}\n", padding));
+    }
+    css.push_str(&theme_css(&config.theme));
+    css.push_str(&font_css(".message-label", &config.fonts.label));
+    css.push_str(&font_css(".device-label", &config.fonts.device_label));
+    // Set on the window while quiet hours dim it
+    css.push_str(&format!(
+        "window.quiet {{ opacity: {}; }}\n",
+        config.quiet_hours.opacity.clamp(0.0, 1.0)
+    ));
+    css
+}
+
+fn font_css(selector: &str, font: &FontConfig) -> String {
+    let mut rules = String::new();
+    if let Some(family) = &font.family {
+        // Quoted, so names with spaces or digits work as they are
+        let family = family.replace(['\\', '"'], "");
+        rules.push_str(&format!("font-family: \"{}\"; ", family));
+    }
+    if let Some(size) = font.size {
+        rules.push_str(&format!("font-size: {}px; ", size));
+    }
+    if let Some(weight) = font.weight {
+        rules.push_str(&format!("font-weight: {}; ", weight.css()));
+    }
+
+    if rules.is_empty() {
+        return String::new();
+    }
+    format!("{} {{ {}}}\n", selector, rules)
+}
+
+fn theme_css(theme: &ThemeConfig) -> String {
+    let mut window = String::new();
+
+    if let Some(radius) = theme.radius {
+        window.push_str(&format!("border-radius: {}px; ", radius));
+    }
+
+    let border_color = theme.border_color.as_deref().and_then(valid_color);
+    if theme.border_width.is_some() || border_color.is_some() {
+        window.push_str(&format!(
+            "border: {}px solid {}; ",
+            theme.border_width.unwrap_or(1),
+            border_color.unwrap_or("rgba(255, 255, 255, 0.2)")
+        ));
+    }
+
+    if theme.background.is_some() || theme.background_opacity.is_some() {
+        let background = theme.background.as_deref().unwrap_or("black");
+        match gdk::RGBA::parse(background) {
+            Ok(mut color) => {
+                if let Some(opacity) = theme.background_opacity {
+                    color.set_alpha(opacity.clamp(0.0, 1.0) as f32);
+                }
+                window.push_str(&format!("background-color: {}; ", color));
+            }
+            Err(_) => warn!("Ignoring invalid color: {}", background),
+        }
+    }
+
+    let mut css = String::new();
+    if !window.is_empty() {
+        css.push_str(&format!("window {{ {}}}\n", window));
+    }
+    if theme.shadow {
+        css.push_str(
+            "label { text-shadow: 0 1px 3px rgba(0, 0, 0, 0.8); }\n\
+             image { -gtk-icon-shadow: 0 1px 3px rgba(0, 0, 0, 0.8); }\n",
+        );
+    }
+    css
+}
+
+/// Stylesheet applying the `[bar]` settings on top of the built-in one
+fn bar_css(bar: &BarConfig) -> String {
+    let mut css = format!(
+        "progressbar, progressbar trough, progressbar progress {{ min-height: {}px; }}\n\
+         progressbar trough, progressbar progress {{ border-radius: {}px; }}\n",
+        bar.height, bar.radius
+    );
+
+    if let Some(width) = bar.width {
+        css.push_str(&format!(
+            "progressbar trough {{ min-width: {}px; }}\n",
+            width
+        ));
+    }
+
+    if let Some(color) = valid_color(&bar.trough_color) {
+        css.push_str(&format!(
+            "progressbar:not(.overamplified) trough {{ background-color: {}; }}\n",
+            color
+        ));
+    }
+
+    css
+}
+
+/// Replace the extra class a view set on `widget` before with `class`
+fn swap_css_class(
+    widget: &impl IsA<gtk::Widget>,
+    current: &RefCell<Option<String>>,
+    class: Option<&str>,
+) {
+    if current.borrow().as_deref() == class {
+        return;
+    }
+    if let Some(previous) = current.replace(class.map(str::to_string)) {
+        widget.remove_css_class(&previous);
+    }
+    if let Some(class) = class {
+        widget.add_css_class(class);
+    }
+}
+
+impl UiElements {
+    /// Build the window, on `monitor` if given and otherwise on the preferred output
+    pub fn new(app: &gtk::Application, config: &Config, monitor: Option<&gdk::Monitor>) -> Self {
+        let animation = &config.animation;
+
+        let window = gtk::ApplicationWindow::builder()
+            .application(app)
+            .title("Wayland OSD")
+            .build();
+
+        // Initialize as layer shell window
+        window.init_layer_shell();
+        window.set_layer(Layer::Overlay);
+        window.set_namespace(NAMESPACE);
+
+        // Keep some distance from the edges the OSD is attached to
+        for &edge in config.window.anchor.edges() {
+            let margin = config.window.margin.get(edge);
+            let edge = layer_shell_edge(edge);
+            window.set_anchor(edge, true);
+            window.set_margin(edge, margin);
+        }
+
+        let outputs = match monitor {
+            Some(monitor) => {
+                window.set_monitor(monitor);
+                None
+            }
+            None => Some(OutputPlacement::new(&window, &config.window)),
+        };
+
+        // Connected after the placement, so the window is already on its new output
+        if let Some(percent) = config.window.width_percent {
+            let resized = window.downgrade();
+            WidgetExt::display(&window)
+                .monitors()
+                .connect_items_changed(move |_, _, _, _| {
+                    if let Some(window) = resized.upgrade() {
+                        fit_width(&window, percent);
+                    }
+                });
+        }
+
+        let widgets = match &config.ui_file {
+            Some(path) => {
+                let path = Config::resolve_path(path);
+                Widgets::load(&path).unwrap_or_else(|e| {
+                    error!("{:#}, using the built-in layout", e);
+                    Widgets::build()
+                })
+            }
+            None => Widgets::build(),
+        };
+
+        widgets
+            .icon
+            .set_duration(animation.icon_crossfade_duration());
+
+        let bar = widgets.progress_bar.downgrade();
+        let progress_animation = Animation::new(
+            &widgets.progress_bar,
+            animation.min_frame_interval(),
+            move |fraction| {
+                if let Some(bar) = bar.upgrade() {
+                    bar.set_fraction(fraction);
+                }
+            },
+        )
+        .with_easing(animation.progress_easing);
+
+        let unmap_when_hidden = config.window.unmap_when_hidden;
+        let faded = window.downgrade();
+        let fade = Animation::new(&window, animation.min_frame_interval(), move |opacity| {
+            if let Some(window) = faded.upgrade() {
+                window.set_opacity(opacity);
+                // Only unmap once fully faded out
+                if unmap_when_hidden && opacity <= 0.0 {
+                    window.set_visible(false);
+                }
+            }
+        });
+
+        // Configured colors win over the built-in stylesheet
+        let color_provider = gtk::CssProvider::new();
+        gtk::style_context_add_provider_for_display(
+            &WidgetExt::display(&window),
+            &color_provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+        );
+
+        let bar_provider = gtk::CssProvider::new();
+        load_css(&bar_provider, &size_css(config));
+        gtk::style_context_add_provider_for_display(
+            &WidgetExt::display(&window),
+            &bar_provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+        );
+        widgets
+            .drawing_area
+            .set_content_height(config.bar.height as i32);
+        fit_text(&widgets.label, &config.text);
+        // Also for labels from a `.ui` file, for the `[fonts]` settings
+        widgets.label.add_css_class("message-label");
+        widgets.icon.set_size(config.icon_size);
+
+        let bar_overlay = Rc::new(RefCell::new(BarOverlay::default()));
+        let overlay_for_draw = bar_overlay.clone();
+
+        widgets.drawing_area.add_css_class("bar-overlay");
+        widgets
+            .drawing_area
+            .set_draw_func(move |area, cr, width, height| {
+                let overlay = overlay_for_draw.borrow();
+                overlay.draw_segments(area, cr, width as f64, height as f64);
+
+                // Snap lines to device pixels so they stay crisp on scaled outputs
+                let scale = area.scale_factor() as f64;
+                let draw_lines = |positions: &[f64], alpha, line_width| {
+                    cr.set_source_rgba(1.0, 1.0, 1.0, alpha);
+                    cr.set_line_width(line_width);
+                    for position in positions {
+                        let x = (width as f64 * position * scale).round() / scale;
+                        trace!("Drawing line at x={} to y={}", x, height);
+                        cr.move_to(x, 0.0);
+                        cr.line_to(x, height as f64);
+                    }
+                    cr.stroke().expect("Failed to draw line");
+                };
+
+                // Thin and faint ticks below the white marker lines
+                draw_lines(&overlay.ticks, 0.4, 1.0);
+                draw_lines(&overlay.markers, 0.8, 2.0);
+            });
+
+        // Only the built-in bar sits in an overlay the value can be put on
+        let value_inside = config.bar.value_inside
+            && match widgets.bar_widget.downcast_ref::<gtk::Overlay>() {
+                Some(overlay) => {
+                    overlay.add_overlay(&widgets.value_label);
+                    true
+                }
+                None => false,
+            };
+
+        window.set_child(Some(&widgets.root));
+
+        // A transparent surface would still swallow clicks meant for windows below it
+        window.connect_realize(|window| {
+            if let Some(surface) = window.surface() {
+                surface.set_input_region(&gtk::cairo::Region::create());
+            }
+        });
+
+        // The server hides every window when its `dismiss` action is activated
+        if config.window.interactive {
+            let click = gtk::GestureClick::new();
+            click.connect_released(|gesture, _, _, _| {
+                if let Some(widget) = gesture.widget() {
+                    debug!("Dismissed by click");
+                    let _ = widget.activate_action("app.dismiss", None);
+                }
+            });
+            window.add_controller(click);
+        }
+
+        // The server pauses the hide timeout while hovered, via its `hover` action
+        let hovered = Rc::new(Cell::new(false));
+        if config.window.interactive {
+            let motion = gtk::EventControllerMotion::new();
+            let hovered_on_enter = hovered.clone();
+            motion.connect_enter(move |controller, _, _| {
+                hovered_on_enter.set(true);
+                if let Some(widget) = controller.widget() {
+                    let _ = widget.activate_action("app.hover", None);
+                }
+            });
+            let hovered_on_leave = hovered.clone();
+            motion.connect_leave(move |controller| {
+                hovered_on_leave.set(false);
+                if let Some(widget) = controller.widget() {
+                    let _ = widget.activate_action("app.hover", None);
+                }
+            });
+            window.add_controller(motion);
+
+            // The server runs the command configured for the message on screen, via its `scroll` action
+            let scroll = gtk::EventControllerScroll::new(
+                gtk::EventControllerScrollFlags::VERTICAL
+                    | gtk::EventControllerScrollFlags::DISCRETE,
+            );
+            scroll.connect_scroll(|controller, _dx, dy| {
+                if let Some(widget) = controller.widget() {
+                    let _ = widget.activate_action("app.scroll", Some(&dy.to_variant()));
+                }
+                glib::Propagation::Stop
+            });
+            window.add_controller(scroll);
+        }
+
+        window.set_visible(false);
+
+        Self {
+            window,
+            outputs,
+            main_box: widgets.main_box,
+            progress_bar: widgets.progress_bar,
+            bar_widget: widgets.bar_widget,
+            label: widgets.label,
+            device_label: widgets.device_label,
+            value_label: widgets.value_label,
+            value_inside,
+            interactive: config.window.interactive,
+            hovered,
+            exclusive_zone: config.window.exclusive_zone,
+            width_percent: config.window.width_percent,
+            artwork: widgets.artwork,
+            artwork_request: Rc::new(Cell::new(0)),
+            icon: widgets.icon,
+            drawing_area: widgets.drawing_area,
+            bar_overlay,
+            icons: IconCache::new(config.icon_pack, config.use_icon_theme, config.icon_size),
+            color_provider,
+            bar_provider,
+            bar_class: RefCell::new(None),
+            label_class: RefCell::new(None),
+            window_classes: RefCell::new(Vec::new()),
+            progress_animation,
+            fade,
+            fade_in: animation.fade_in_duration(),
+            fade_out: animation.fade_out_duration(),
+            opacity: config
+                .window
+                .opacity
+                .map_or(1.0, |opacity| opacity.clamp(0.0, 1.0)),
+            progress_duration: animation.progress_duration(),
+            applied: RefCell::new(None),
+            layout: RefCell::new(None),
+            scale: Cell::new(0),
+        }
+    }
+
+    /// The widget that represents an element in a layout
+    fn element_widget(&self, element: LayoutElement) -> gtk::Widget {
+        match element {
+            LayoutElement::Icon => self.icon.widget(),
+            LayoutElement::Bar => self.bar_widget.clone(),
+            LayoutElement::Label => self.label.clone().upcast(),
+            LayoutElement::Caption => self.device_label.clone().upcast(),
+            LayoutElement::Value => self.value_label.clone().upcast(),
+            LayoutElement::Artwork => self.artwork.clone().upcast(),
+        }
+    }
+
+    /// Rebuild the rows of the window to match `layout`
+    fn arrange(&self, layout: &Layout) {
+        let Some(main_box) = &self.main_box else {
+            return;
+        };
+
+        let elements = [
+            LayoutElement::Icon,
+            LayoutElement::Bar,
+            LayoutElement::Label,
+            LayoutElement::Caption,
+            LayoutElement::Value,
+            LayoutElement::Artwork,
+        ];
+        for element in elements {
+            let widget = self.element_widget(element);
+            if let Some(row) = widget.parent().and_downcast::<gtk::Box>() {
+                row.remove(&widget);
+            }
+        }
+        while let Some(row) = main_box.first_child() {
+            main_box.remove(&row);
+        }
+
+        main_box.set_spacing(layout.spacing);
+
+        for row in &layout.rows {
+            let hbox = gtk::Box::builder()
+                .orientation(gtk::Orientation::Horizontal)
+                .spacing(row.spacing)
+                .halign(match row.align {
+                    LayoutAlign::Start => gtk::Align::Start,
+                    LayoutAlign::Center => gtk::Align::Center,
+                    LayoutAlign::End => gtk::Align::End,
+                    LayoutAlign::Fill => gtk::Align::Fill,
+                })
+                .build();
+
+            for item in &row.items {
+                if item.element() == LayoutElement::Value && self.value_inside {
+                    continue;
+                }
+
+                let widget = self.element_widget(item.element());
+                if widget.parent().is_some() {
+                    warn!("{:?} appears more than once in a layout", item.element());
+                    continue;
+                }
+
+                let (width, height) = item.size();
+                widget.set_size_request(width, height);
+                widget.set_hexpand(row.align == LayoutAlign::Fill);
+                hbox.append(&widget);
+            }
+
+            main_box.append(&hbox);
+        }
+    }
+
+    /// Make the OSD visible
+    ///
+    /// Unless configured otherwise, the surface is mapped once and then kept
+    /// around fully transparent, since remapping layer surfaces flickers or
+    /// lands in the wrong place on some compositors.
+    pub fn show(&self) {
+        if !self.window.is_visible() {
+            // Fade in from nothing rather than flashing the previous contents
+            self.window.set_opacity(0.0);
+            self.window.set_visible(true);
+        }
+        if let Some(percent) = self.width_percent {
+            fit_width(&self.window, percent);
+        }
+        self.set_input(true);
+        if self.exclusive_zone {
+            // Follows the size of the window
+            self.window.auto_exclusive_zone_enable();
+        }
+        self.fade.animate(
+            self.window.opacity(),
+            self.opacity,
+            self.motion(self.fade_in),
+        );
+    }
+
+    pub fn is_hovered(&self) -> bool {
+        self.hovered.get()
+    }
+
+    /// Let input reach the surface while shown in interactive mode, pass it through otherwise
+    fn set_input(&self, enabled: bool) {
+        let Some(surface) = self.window.surface() else {
+            return;
+        };
+        let region = if enabled && self.interactive {
+            // Clipped to the surface, so this covers all of it whatever its size
+            gtk::cairo::Region::create_rectangle(&gtk::cairo::RectangleInt::new(
+                0,
+                0,
+                i32::MAX / 2,
+                i32::MAX / 2,
+            ))
+        } else {
+            gtk::cairo::Region::create()
+        };
+        surface.set_input_region(&region);
+    }
+
+    /// Wait until the window has been laid out for the current contents
+    pub async fn next_frame(&self) {
+        let (sender, receiver) = async_channel::bounded(1);
+        self.window.add_tick_callback(move |_window, _clock| {
+            let _ = sender.try_send(());
+            glib::ControlFlow::Break
+        });
+        let _ = receiver.recv().await;
+    }
+
+    /// Render the window as it currently looks to a PNG file
+    ///
+    /// The window is expected to be mapped but hidden, it is only made visible
+    /// for the duration of the snapshot and never painted on screen that way.
+    pub fn render_to_png(&self, path: &Path) -> anyhow::Result<()> {
+        let renderer = self.window.renderer().context("Window is not realized")?;
+        let paintable = gtk::WidgetPaintable::new(Some(&self.window));
+        let snapshot = gtk::Snapshot::new();
+
+        self.window.set_opacity(self.opacity);
+        paintable.snapshot(
+            &snapshot,
+            self.window.width() as f64,
+            self.window.height() as f64,
+        );
+        self.window.set_opacity(0.0);
+
+        let node = snapshot.to_node().context("Window did not draw anything")?;
+        renderer
+            .render_texture(&node, None)
+            .save_to_png(path)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Move to the output a message asked for, or back to the configured one for `None`
+    pub fn set_output(&self, output: Option<&str>) {
+        if let Some(outputs) = &self.outputs {
+            outputs.request(output);
+        }
+    }
+
+    /// Close the window for good, e.g. before building a new one
+    pub fn destroy(&self) {
+        let display = WidgetExt::display(&self.window);
+        gtk::style_context_remove_provider_for_display(&display, &self.color_provider);
+        gtk::style_context_remove_provider_for_display(&display, &self.bar_provider);
+        self.window.destroy();
+    }
+
+    pub fn hide(&self) {
+        if !self.window.is_visible() {
+            return;
+        }
+        self.set_input(false);
+        // No leave event follows once input passes through
+        self.hovered.set(false);
+        // The surface stays mapped while hidden, it mustn't keep the space
+        if self.exclusive_zone {
+            self.window.set_exclusive_zone(0);
+        }
+        self.fade
+            .animate(self.window.opacity(), 0.0, self.motion(self.fade_out));
+    }
+
+    /// Replace the CSS classes of the window, e.g. `osd-volume`
+    pub fn set_classes(&self, classes: &[String]) {
+        let mut current = self.window_classes.borrow_mut();
+        if *current == classes {
+            return;
+        }
+        for class in current.iter() {
+            self.window.remove_css_class(class);
+        }
+        for class in classes {
+            self.window.add_css_class(class);
+        }
+        *current = classes.to_vec();
+    }
+
+    /// Tell screen readers what the view shows
+    ///
+    /// The text always becomes the accessible label of the window, it's only
+    /// read out right away when built with the `announce` feature.
+    pub fn announce(&self, view: &View, message_type: &str) {
+        let mut announcement = view.announcement(message_type);
+        if let View::Text(TextView { markup: true, .. }) = view {
+            if let Ok((_, text, _)) = gtk::pango::parse_markup(&announcement, '\0') {
+                announcement = text.to_string();
+            }
+        }
+
+        debug!("Announcing \"{}\"", announcement);
+        self.window
+            .update_property(&[gtk::accessible::Property::Label(&announcement)]);
+        #[cfg(feature = "announce")]
+        self.window
+            .announce(&announcement, gtk::AccessibleAnnouncementPriority::Medium);
+    }
+
+    /// `duration`, or none at all while the desktop asks for reduced motion
+    ///
+    /// GTK follows the preference from the settings portal, and applies it to
+    /// the icon crossfade on its own.
+    fn motion(&self, duration: Duration) -> Duration {
+        if self.window.settings().is_gtk_enable_animations() {
+            duration
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Configure the widgets to display the given view
+    ///
+    /// Only the parts that differ from the previously applied view are touched,
+    /// so repeated updates of the same kind just move the bar.
+    pub fn apply(&self, view: &View, layout: &Layout) {
+        let started = Instant::now();
+        let mut previous = self.applied.replace(None);
+
+        // Moved to an output of another scale, render everything anew
+        let scale = self.window.scale_factor();
+        if self.scale.replace(scale) != scale {
+            previous = None;
+        }
+
+        if self.layout.borrow().as_ref() != Some(layout) {
+            self.arrange(layout);
+            *self.layout.borrow_mut() = Some(layout.clone());
+        }
+
+        match view {
+            View::Progress(progress) => {
+                let previous = match &previous {
+                    Some(View::Progress(previous)) => Some(previous),
+                    _ => None,
+                };
+                self.apply_progress(progress, previous);
+            }
+            View::Text(text) => {
+                let previous = match &previous {
+                    Some(View::Text(previous)) => Some(previous),
+                    _ => None,
+                };
+                self.apply_text(text, previous);
+            }
+            View::Media(media) => {
+                let previous = match &previous {
+                    Some(View::Media(previous)) => Some(previous),
+                    _ => None,
+                };
+                self.apply_media(media, previous);
+            }
+        }
+
+        *self.applied.borrow_mut() = Some(view.clone());
+        trace!("Applied view in {:?}", started.elapsed());
+    }
+
+    fn apply_progress(&self, view: &ProgressView, previous: Option<&ProgressView>) {
+        self.progress_animation.animate(
+            self.progress_bar.fraction(),
+            view.fraction,
+            self.motion(self.progress_duration),
+        );
+
+        if previous.is_none() {
+            self.progress_bar.set_visible(true);
+            self.label.set_visible(false);
+            self.show_artwork(None);
+        }
+
+        // Update device name if provided
+        if previous.map(|p| &p.device_label) != Some(&view.device_label) {
+            if let Some(device_name) = &view.device_label {
+                self.device_label.set_text(device_name);
+                self.device_label.set_visible(true);
+            } else {
+                self.device_label.set_visible(false);
+            }
+        }
+
+        if previous.map(|p| &p.value_label) != Some(&view.value_label) {
+            match &view.value_label {
+                Some(value) => {
+                    self.value_label.set_text(value);
+                    self.value_label.set_visible(true);
+                }
+                None => self.value_label.set_visible(false),
+            }
+        }
+
+        if previous.map(|p| p.muted) != Some(view.muted) {
+            for widget in [
+                self.progress_bar.upcast_ref::<gtk::Widget>(),
+                self.drawing_area.upcast_ref(),
+                self.value_label.upcast_ref(),
+            ] {
+                if view.muted {
+                    widget.add_css_class("muted");
+                } else {
+                    widget.remove_css_class("muted");
+                }
+            }
+        }
+
+        if previous.map(|p| p.overamplified) != Some(view.overamplified) {
+            if view.overamplified {
+                self.progress_bar.add_css_class("overamplified");
+            } else {
+                self.progress_bar.remove_css_class("overamplified");
+            }
+        }
+
+        swap_css_class(
+            &self.progress_bar,
+            &self.bar_class,
+            view.css_class.as_deref(),
+        );
+
+        // Update marker, tick and block positions and show/hide the overlay
+        if previous != Some(view) {
+            let mut overlay = self.bar_overlay.borrow_mut();
+            overlay.markers = view.marker.iter().chain(&view.markers).copied().collect();
+            overlay.ticks = view.ticks.clone();
+            overlay.segments = view.segments;
+            overlay.fraction = view.fraction;
+            overlay.normal_end = view.marker.unwrap_or(1.0);
+            overlay.color = view
+                .color
+                .as_deref()
+                .and_then(|color| gdk::RGBA::parse(color).ok());
+
+            self.drawing_area.set_visible(!overlay.is_empty());
+            self.progress_bar
+                .set_opacity(if view.segments.is_some() { 0.0 } else { 1.0 });
+            self.drawing_area.queue_draw(); // Force redraw with new positions
+        }
+
+        if previous.map(|p| &p.color) != Some(&view.color) {
+            self.apply_color(view.color.as_deref());
+        }
+
+        if previous.map(|p| (p.icon, &p.custom_icon)) != Some((view.icon, &view.custom_icon)) {
+            // Messages asking for an icon that can't be found get the built-in one
+            let paintable = view
+                .custom_icon
+                .as_ref()
+                .and_then(|icon| self.icons.custom(icon, &self.icon.widget()))
+                .or_else(|| {
+                    view.icon
+                        .map(|icon| self.icons.get(icon, &self.icon.widget()))
+                });
+            if let Some(paintable) = &paintable {
+                self.icon.set_paintable(paintable);
+            }
+            self.icon.widget().set_visible(paintable.is_some());
+            trace!("Updated icon to {:?}", view.icon);
+        }
+    }
+
+    fn apply_color(&self, color: Option<&str>) {
+        match color.and_then(valid_color) {
+            Some(color) => {
+                load_css(
+                    &self.color_provider,
+                    &format!(
+                        "progressbar.colored progress {{ background-color: {}; }}",
+                        color
+                    ),
+                );
+                self.progress_bar.add_css_class("colored");
+            }
+            None => self.progress_bar.remove_css_class("colored"),
+        }
+    }
+
+    fn apply_text(&self, view: &TextView, previous: Option<&TextView>) {
+        if previous.is_none() {
+            self.label.set_visible(true);
+            self.progress_bar.set_visible(false);
+            self.icon.widget().set_visible(false);
+            self.device_label.set_visible(false);
+            self.value_label.set_visible(false);
+            self.drawing_area.set_visible(false); // Hide marker for text messages
+            self.show_artwork(None);
+        }
+
+        swap_css_class(&self.label, &self.label_class, view.css_class.as_deref());
+
+        if previous.map(|p| (&p.text, p.markup)) != Some((&view.text, view.markup)) {
+            if view.markup && gtk::pango::parse_markup(&view.text, '\0').is_ok() {
+                self.label.set_markup(&view.text);
+            } else {
+                if view.markup {
+                    warn!("Invalid markup in text message, showing it as plain text");
+                }
+                self.label.set_text(&view.text); // Escapes the text
+            }
+
+            // Shrink back to the natural size of the new text, the window keeps its size otherwise
+            self.window.set_default_size(-1, -1);
+            self.window.queue_resize();
+        }
+
+        if previous.map(|p| (p.icon, &p.custom_icon)) != Some((view.icon, &view.custom_icon)) {
+            let paintable = view
+                .custom_icon
+                .as_ref()
+                .and_then(|icon| self.icons.custom(icon, &self.icon.widget()))
+                .or_else(|| {
+                    view.icon
+                        .map(|icon| self.icons.get(icon, &self.icon.widget()))
+                });
+            if let Some(paintable) = &paintable {
+                self.icon.set_paintable(paintable);
+            }
+            self.icon.widget().set_visible(paintable.is_some());
+        }
+    }
+
+    fn apply_media(&self, view: &MediaView, previous: Option<&MediaView>) {
+        if previous.is_none() {
+            self.label.set_visible(true);
+            self.icon.widget().set_visible(true);
+            self.progress_bar.set_visible(false);
+            self.value_label.set_visible(false);
+            self.drawing_area.set_visible(false);
+            swap_css_class(&self.label, &self.label_class, None);
+        }
+
+        if previous.map(|p| &p.title) != Some(&view.title) {
+            self.label.set_text(&view.title);
+        }
+
+        if previous.map(|p| &p.artist) != Some(&view.artist) {
+            match &view.artist {
+                Some(artist) => {
+                    self.device_label.set_text(artist);
+                    self.device_label.set_visible(true);
+                }
+                None => self.device_label.set_visible(false),
+            }
+        }
+
+        if previous.map(|p| &p.artwork) != Some(&view.artwork) {
+            self.show_artwork(view.artwork.as_deref());
+        }
+
+        if previous.map(|p| (p.icon, &p.custom_icon)) != Some((view.icon, &view.custom_icon)) {
+            let paintable = view
+                .custom_icon
+                .as_ref()
+                .and_then(|icon| self.icons.custom(icon, &self.icon.widget()))
+                .unwrap_or_else(|| self.icons.get(view.icon, &self.icon.widget()));
+            self.icon.set_paintable(&paintable);
+        }
+    }
+
+    /// Show the cover at `artwork` once it has loaded, or hide it for `None`
+    ///
+    /// Covers may come from the network, so they are loaded in the background
+    /// and only shown if no other cover was asked for in the meantime.
+    fn show_artwork(&self, artwork: Option<&str>) {
+        let request = self.artwork_request.get() + 1;
+        self.artwork_request.set(request);
+        self.artwork.set_paintable(None::<&gdk::Paintable>);
+        self.artwork.set_visible(false);
+
+        let Some(artwork) = artwork.map(str::to_string) else {
+            return;
+        };
+        let picture = self.artwork.downgrade();
+        let latest = self.artwork_request.clone();
+        glib::spawn_future_local(async move {
+            let texture = load_artwork(&artwork).await;
+            if latest.get() != request {
+                return;
+            }
+            if let (Some(picture), Some(texture)) = (picture.upgrade(), texture) {
+                picture.set_paintable(Some(&texture));
+                picture.set_visible(true);
+            }
+        });
+    }
+}